@@ -0,0 +1,213 @@
+use std::{
+    fs::{self, File},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use age::{
+    DecryptError,
+    scrypt::{Identity, Recipient},
+    secrecy::SecretString,
+};
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{Result, bail, eyre};
+use serde::{Deserialize, Serialize};
+use zip::{ZipArchive, ZipWriter, write::SimpleFileOptions};
+
+use crate::Note;
+
+const ARCHIVE_VERSION: u32 = 1;
+const ZIP_MANIFEST_VERSION: u32 = 1;
+const ZIP_MANIFEST_NAME: &str = "manifest.json";
+
+#[derive(Serialize, Deserialize)]
+struct Archive {
+    version: u32,
+    notes: Vec<NoteRecord>,
+}
+
+/// Lists the notes stored in a zip export. Note bodies live in their own Markdown files so the
+/// archive stays browsable with any unzip tool.
+#[derive(Serialize, Deserialize)]
+struct ZipManifest {
+    version: u32,
+    notes: Vec<ZipEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ZipEntry {
+    title: String,
+    file: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created_at: Option<DateTime<Utc>>,
+}
+
+/// A note as it travels between machines. Ids are local to a database and not carried over.
+#[derive(Serialize, Deserialize)]
+pub struct NoteRecord {
+    pub title: String,
+    pub content: String,
+    /// Kept when the source knows it; imported notes are otherwise dated at import time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// A page of Roam Research's JSON import format, one per note.
+#[derive(Serialize)]
+pub struct RoamPage {
+    pub title: String,
+    pub children: Vec<RoamBlock>,
+}
+
+#[derive(Serialize)]
+pub struct RoamBlock {
+    pub string: String,
+}
+
+/// Every non-blank line of a note becomes a top-level block of its page. `[[title]]` links are
+/// left as they are, Roam reads them the same way.
+pub fn roam_pages(notes: &[Note]) -> Vec<RoamPage> {
+    notes
+        .iter()
+        .map(|note| RoamPage {
+            title: note.title.clone(),
+            children: note
+                .content
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| RoamBlock {
+                    string: line.to_string(),
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Where a file is written before it's renamed over `path`: in the same directory so the rename
+/// can't cross file systems, and named after the whole file name so it never lands on another
+/// file, such as `notes.tmp` next to `notes.zip`.
+pub fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".tmp");
+    PathBuf::from(name)
+}
+
+/// Writes `notes` as passphrase-encrypted JSON. The file is written next to `path` first and
+/// renamed into place, so an interrupted export never leaves a truncated archive behind.
+pub fn export_encrypted(notes: &[Note], path: &Path, passphrase: &str) -> Result<()> {
+    let archive = Archive {
+        version: ARCHIVE_VERSION,
+        notes: notes
+            .iter()
+            .map(|note| NoteRecord {
+                title: note.title.clone(),
+                content: note.content.clone(),
+                created_at: Some(note.created_at),
+            })
+            .collect(),
+    };
+
+    let plaintext = serde_json::to_vec(&archive)?;
+    let recipient = Recipient::new(SecretString::from(passphrase.to_owned()));
+    let encrypted = age::encrypt(&recipient, &plaintext)?;
+
+    let tmp_path = temp_path(path);
+    fs::write(&tmp_path, encrypted)?;
+    fs::rename(&tmp_path, path)?;
+
+    Ok(())
+}
+
+pub fn import_encrypted(path: &Path, passphrase: &str) -> Result<Vec<NoteRecord>> {
+    let encrypted = fs::read(path)?;
+    let identity = Identity::new(SecretString::from(passphrase.to_owned()));
+
+    let plaintext = age::decrypt(&identity, &encrypted).map_err(|e| match e {
+        DecryptError::DecryptionFailed | DecryptError::NoMatchingKeys => {
+            eyre!("wrong passphrase")
+        }
+        e => eyre!(e),
+    })?;
+
+    let archive: Archive = serde_json::from_slice(&plaintext)?;
+    if archive.version != ARCHIVE_VERSION {
+        bail!("unsupported archive version {}", archive.version);
+    }
+
+    Ok(archive.notes)
+}
+
+pub fn export_zip(notes: &[Note], path: &Path) -> Result<()> {
+    let tmp_path = temp_path(path);
+    let mut zip = ZipWriter::new(File::create(&tmp_path)?);
+    let options = SimpleFileOptions::default();
+    let mut manifest = ZipManifest {
+        version: ZIP_MANIFEST_VERSION,
+        notes: Vec::with_capacity(notes.len()),
+    };
+
+    for (index, note) in notes.iter().enumerate() {
+        let file = format!("notes/{:04}.md", index + 1);
+        zip.start_file(file.as_str(), options)?;
+        zip.write_all(note.content.as_bytes())?;
+        manifest.notes.push(ZipEntry {
+            title: note.title.clone(),
+            file,
+            created_at: Some(note.created_at),
+        });
+    }
+
+    zip.start_file(ZIP_MANIFEST_NAME, options)?;
+    zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+    zip.finish()?;
+
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Reads every note out of a zip export. Nothing is returned unless the whole archive is
+/// readable, so a truncated or tampered file can't lead to a partial import.
+pub fn import_zip(path: &Path) -> Result<Vec<NoteRecord>> {
+    let mut zip = ZipArchive::new(File::open(path)?)?;
+
+    let mut raw_manifest = String::new();
+    zip.by_name(ZIP_MANIFEST_NAME)?
+        .read_to_string(&mut raw_manifest)?;
+    let manifest: ZipManifest = serde_json::from_str(&raw_manifest)?;
+    if manifest.version != ZIP_MANIFEST_VERSION {
+        bail!("unsupported manifest version {}", manifest.version);
+    }
+
+    let mut records = Vec::with_capacity(manifest.notes.len());
+    for entry in manifest.notes {
+        let mut content = String::new();
+        zip.by_name(&entry.file)
+            .map_err(|e| eyre!("{}: {e}", entry.file))?
+            .read_to_string(&mut content)?;
+        records.push(NoteRecord {
+            title: entry.title,
+            content,
+            created_at: entry.created_at,
+        });
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn temp_path_never_is_the_target_or_a_sibling() {
+        assert_eq!(
+            temp_path(Path::new("out/notes.zip")),
+            Path::new("out/notes.zip.tmp")
+        );
+        assert_eq!(
+            temp_path(Path::new("notes.tmp")),
+            Path::new("notes.tmp.tmp")
+        );
+        assert_eq!(temp_path(Path::new("notes")), Path::new("notes.tmp"));
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+use chrono::{Days, Utc};
+use color_eyre::eyre::{Result, eyre};
+
+use crate::{collation::TitleCollator, db::Database};
+
+/// Titles listed before asking for confirmation.
+const SAMPLE_SIZE: usize = 5;
+
+/// `ratata-notes archive --older-than DAYS`: lists what would be archived, asks unless `yes`,
+/// then archives it all in one transaction. With `to_file` the notes move to that database
+/// instead of staying in this one marked archived.
+pub fn run(
+    db: &Database,
+    days: u64,
+    yes: bool,
+    to_file: Option<&Path>,
+    title_collator: &TitleCollator,
+) -> Result<()> {
+    let cutoff = Utc::now()
+        .checked_sub_days(Days::new(days))
+        .ok_or_else(|| eyre!("--older-than {days} reaches too far back"))?;
+    let notes = db.get_notes_not_updated_since(cutoff)?;
+    if notes.is_empty() {
+        println!("No notes left unedited for {days} days");
+        return Ok(());
+    }
+
+    println!("{} notes not updated in {days} days:", notes.len());
+    for note in notes.iter().take(SAMPLE_SIZE) {
+        println!("  {}", note.title);
+    }
+    if notes.len() > SAMPLE_SIZE {
+        println!("  … and {} more", notes.len() - SAMPLE_SIZE);
+    }
+
+    let question = match to_file {
+        Some(path) => format!("Move them to {}?", path.display()),
+        None => "Archive them?".to_string(),
+    };
+    if !yes && !confirm(&question)? {
+        return Ok(());
+    }
+
+    if let Some(path) = to_file {
+        open_archive(path, title_collator)?;
+        let moved = db.move_notes_not_updated_since(cutoff, path)?;
+        println!(
+            "Moved {moved} notes to {}, `ratata-notes archive --restore-archive` brings them back",
+            path.display()
+        );
+        return Ok(());
+    }
+
+    let archived = db.archive_notes_not_updated_since(cutoff)?;
+    println!("Archived {archived} notes, `ratata-notes archive --undo` restores them");
+    Ok(())
+}
+
+/// `ratata-notes archive --restore-archive`: moves every note in the archive file back.
+pub fn restore(db: &Database, path: &Path, title_collator: &TitleCollator) -> Result<()> {
+    if !path.exists() {
+        return Err(eyre!("No archive at {}", path.display()));
+    }
+    open_archive(path, title_collator)?;
+    match db.restore_archive(path)? {
+        0 => println!("No notes in {}", path.display()),
+        restored => println!("Restored {restored} notes from {}", path.display()),
+    }
+    Ok(())
+}
+
+/// Creates the archive file, or brings an older one up to the current schema.
+fn open_archive(path: &Path, title_collator: &TitleCollator) -> Result<()> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| eyre!("{} isn't valid UTF-8", path.display()))?;
+    Database::new(path, title_collator.clone())?.close()?;
+    Ok(())
+}
+
+/// `ratata-notes archive --undo`: restores the notes archived by the last run.
+pub fn undo(db: &Database) -> Result<()> {
+    match db.unarchive_last_batch()? {
+        0 => println!("No archived notes"),
+        restored => println!("Restored {restored} notes"),
+    }
+    Ok(())
+}
+
+fn confirm(question: &str) -> io::Result<bool> {
+    print!("{question} [y/N] ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
@@ -0,0 +1,32 @@
+/// Flips the checkbox of a task line such as `- [ ] buy milk` or `  * [x] done`. Anything
+/// else, including `-[ ]` or `- [y]`, is left alone and gives `None`.
+pub fn toggle_line(line: &str) -> Option<String> {
+    let indent = line.len() - line.trim_start().len();
+    let (prefix, rest) = line.split_at(indent);
+
+    let mut chars = rest.chars();
+    let bullet = chars.next().filter(|c| matches!(c, '-' | '*' | '+'))?;
+    let rest = chars.as_str().strip_prefix(" [")?;
+
+    let mut chars = rest.chars();
+    let checked = match chars.next()? {
+        ' ' => false,
+        'x' | 'X' => true,
+        _ => return None,
+    };
+    let text = chars.as_str().strip_prefix(']')?;
+    if !(text.is_empty() || text.starts_with([' ', '\r'])) {
+        return None;
+    }
+
+    let mark = if checked { ' ' } else { 'x' };
+    Some(format!("{prefix}{bullet} [{mark}]{text}"))
+}
+
+/// `content` with the checkbox on line `index` flipped, or `None` if that line isn't a task.
+pub fn toggle(content: &str, index: usize) -> Option<String> {
+    let mut lines = content.split('\n').collect::<Vec<_>>();
+    let toggled = toggle_line(lines.get(index)?)?;
+    lines[index] = &toggled;
+    Some(lines.join("\n"))
+}
@@ -0,0 +1,213 @@
+use std::{io, path::PathBuf};
+
+use chrono::NaiveDate;
+use clap::{ArgGroup, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+
+const BIN_NAME: &str = "ratata-notes";
+
+#[derive(Parser)]
+#[command(name = BIN_NAME, version, about)]
+pub struct Cli {
+    /// Start with this note selected in the list
+    #[arg(long, value_name = "ID")]
+    pub note_id: Option<i64>,
+
+    /// Start with the note titled TITLE selected, ignoring case
+    #[arg(long, value_name = "TITLE", conflicts_with = "note_id")]
+    pub note_title: Option<String>,
+
+    /// Write a read-only copy of the notes database to PATH and exit
+    #[arg(long, value_name = "PATH")]
+    pub export_sqlite: Option<PathBuf>,
+
+    /// Write the notes as a Roam Research JSON import file to PATH and exit
+    #[arg(long, value_name = "PATH", conflicts_with = "export_sqlite")]
+    pub export_roam: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Open a note straight in the editor
+    Edit { id: i64 },
+    /// Add a note with the content read from stdin, as a web clipper would
+    Add {
+        title: String,
+        /// The page the content was clipped from
+        #[arg(long, value_name = "URL")]
+        source_url: Option<String>,
+    },
+    /// Append a timestamped entry to the journal note, without opening the app
+    Log { text: String },
+    /// Open today's note in the editor, creating it from the daily template if needed
+    Today,
+    /// Add tags to a note, creating tags that don't exist yet
+    Tag {
+        id: i64,
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// Remove tags from a note
+    Untag {
+        id: i64,
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// Print the tags of a note, one per line
+    Tags { id: i64 },
+    /// Delete every note tagged TAG
+    DeleteTag {
+        tag: String,
+        /// Required, as the notes can't be recovered
+        #[arg(long)]
+        confirm: bool,
+    },
+    /// Print a random note
+    Random,
+    /// Print notes created, modified and words written per day over the last week
+    Summary {
+        /// First day to report (YYYY-MM-DD) instead of six days ago
+        #[arg(long, value_name = "DATE")]
+        since: Option<NaiveDate>,
+    },
+    /// Archive notes that haven't been edited for a while, after showing which
+    #[command(group(
+        ArgGroup::new("mode")
+            .required(true)
+            .args(["older_than", "undo", "restore_archive"])
+    ))]
+    Archive {
+        /// Archive notes not updated in the last DAYS days
+        #[arg(long, value_name = "DAYS")]
+        older_than: Option<u64>,
+        /// Don't ask for confirmation
+        #[arg(long)]
+        yes: bool,
+        /// Move the notes out to the database at PATH (archived_notes.db by default) instead
+        #[arg(
+            long,
+            value_name = "PATH",
+            num_args = 0..=1,
+            default_missing_value = "archived_notes.db"
+        )]
+        to_file: Option<PathBuf>,
+        /// Restore the notes archived by the last run instead
+        #[arg(long, conflicts_with_all = ["yes", "to_file"])]
+        undo: bool,
+        /// Move every note in the database at PATH (archived_notes.db by default) back instead
+        #[arg(
+            long,
+            value_name = "PATH",
+            num_args = 0..=1,
+            default_missing_value = "archived_notes.db",
+            conflicts_with_all = ["yes", "to_file"]
+        )]
+        restore_archive: Option<PathBuf>,
+    },
+    /// Flag notes updated so often they may be worth splitting into separate topics
+    Lint {
+        /// Count updates over the last DAYS days
+        #[arg(long, value_name = "DAYS", default_value_t = 30)]
+        days: u32,
+        /// Updates from which a note is flagged
+        #[arg(
+            long,
+            value_name = "COUNT",
+            default_value_t = 20,
+            value_parser = clap::value_parser!(u32).range(1..)
+        )]
+        threshold: u32,
+        /// Also flag notes longer than CHARS characters, instead of `long_note_chars` from the
+        /// config
+        #[arg(long, value_name = "CHARS")]
+        long_chars: Option<usize>,
+    },
+    /// Add the Markdown and text files in DIR as notes, skipping those already stored
+    ImportDir { dir: PathBuf },
+    /// List the notes with `[[title]]` links to notes that don't exist
+    Check,
+    /// Print the absolute path of the notes database
+    Path,
+    /// Print a shell completion script
+    Completions { shell: Shell },
+    /// Print `id<TAB>title` for every note, used by the completion scripts
+    #[command(hide = true)]
+    CompletionsIds,
+}
+
+pub fn print_completions(shell: Shell) {
+    clap_complete::generate(shell, &mut Cli::command(), BIN_NAME, &mut io::stdout());
+
+    // clap only knows the static shape of the CLI; note ids are looked up at completion time.
+    let note_ids = match shell {
+        Shell::Bash => BASH_NOTE_IDS,
+        Shell::Zsh => ZSH_NOTE_IDS,
+        Shell::Fish => FISH_NOTE_IDS,
+        _ => return,
+    };
+    print!("{note_ids}");
+}
+
+/// Makes a note title safe to print on a single tab-separated line.
+pub fn completion_title(title: &str) -> String {
+    title.replace(['\t', '\n', '\r'], " ")
+}
+
+const BASH_NOTE_IDS: &str = r#"
+_ratata__notes_with_ids() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" prev="${COMP_WORDS[COMP_CWORD-1]}"
+    if [[ "$prev" == "--note-id" ||
+          ( "${COMP_WORDS[1]}" == "edit" && $COMP_CWORD -eq 2 ) ]]; then
+        local ids="$(ratata-notes completions-ids 2>/dev/null | cut -f1)"
+        COMPREPLY=( $(compgen -W "$ids" -- "$cur") )
+        return 0
+    fi
+    _ratata__notes "$@"
+}
+complete -F _ratata__notes_with_ids -o nosort -o bashdefault -o default ratata-notes
+"#;
+
+const ZSH_NOTE_IDS: &str = r#"
+_ratata-notes_with_ids() {
+    if [[ "${words[CURRENT-1]}" == "--note-id" ||
+          ( "${words[2]}" == "edit" && $CURRENT -eq 3 ) ]]; then
+        local -a note_ids
+        note_ids=(${(f)"$(ratata-notes completions-ids 2>/dev/null | sed 's/:/\\:/g; s/\t/:/')"})
+        _describe 'note' note_ids
+        return
+    fi
+    _ratata-notes "$@"
+}
+compdef _ratata-notes_with_ids ratata-notes
+"#;
+
+const FISH_NOTE_IDS: &str = r#"
+complete -c ratata-notes -l note-id -x -a '(ratata-notes completions-ids 2>/dev/null)'
+complete -c ratata-notes -n '__fish_seen_subcommand_from edit' \
+    -x -a '(ratata-notes completions-ids 2>/dev/null)'
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parses(args: &str) -> bool {
+        Cli::try_parse_from([BIN_NAME].into_iter().chain(args.split_whitespace())).is_ok()
+    }
+
+    #[test]
+    fn archive_takes_exactly_one_mode() {
+        assert!(parses("archive --older-than 90 --to-file --yes"));
+        assert!(parses("archive --undo"));
+        assert!(parses("archive --restore-archive"));
+
+        assert!(!parses("archive"));
+        assert!(!parses("archive --older-than 90 --undo"));
+        assert!(!parses("archive --undo --restore-archive"));
+        assert!(!parses("archive --undo --to-file x.db"));
+        assert!(!parses("archive --restore-archive old.db --to-file x.db"));
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::models::Note;
+
+/// Entries shown in the cloud, most frequent first.
+pub const CLOUD_LIMIT: usize = 100;
+
+/// Words that say nothing about what a note is about, in English and French. Words shorter
+/// than three letters are skipped anyway.
+const STOPWORDS: &[&str] = &[
+    "about", "after", "all", "also", "and", "any", "are", "because", "been", "before", "but",
+    "can", "could", "did", "does", "for", "from", "had", "has", "have", "her", "his", "how",
+    "into", "its", "just", "like", "more", "not", "now", "one", "only", "other", "our", "out",
+    "should", "some", "than", "that", "the", "their", "them", "then", "there", "these", "they",
+    "this", "too", "was", "were", "what", "when", "where", "which", "who", "will", "with", "would",
+    "you", "your", "alors", "aussi", "avec", "avoir", "car", "ces", "cet", "cette", "comme",
+    "dans", "des", "donc", "elle", "est", "être", "fait", "ils", "les", "leur", "mais", "même",
+    "nous", "par", "pas", "peut", "plus", "pour", "quand", "que", "qui", "sans", "ses", "son",
+    "sont", "sur", "tout", "très", "une", "vous",
+];
+
+/// How often each significant word appears across the titles and contents of `notes`, most
+/// frequent first. Words are split on Unicode word boundaries and compared lowercased.
+pub fn word_frequencies(notes: &[Note]) -> Vec<(String, usize)> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for note in notes {
+        for word in note
+            .title
+            .unicode_words()
+            .chain(note.content.unicode_words())
+        {
+            let word = word.to_lowercase();
+            if word.chars().count() < 3
+                || word.chars().all(char::is_numeric)
+                || STOPWORDS.contains(&word.as_str())
+            {
+                continue;
+            }
+            *counts.entry(word).or_default() += 1;
+        }
+    }
+
+    let mut words = counts.into_iter().collect::<Vec<_>>();
+    words.sort_by(|(left, left_count), (right, right_count)| {
+        right_count.cmp(left_count).then_with(|| left.cmp(right))
+    });
+    words.truncate(CLOUD_LIMIT);
+    words
+}
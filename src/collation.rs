@@ -0,0 +1,70 @@
+use std::{cmp::Ordering, sync::Arc};
+
+use icu_collator::{CollatorBorrowed, options::CollatorOptions};
+use icu_locale_core::Locale;
+
+/// Name of the SQLite collation `Database::new` registers on every connection through `with_init`.
+pub const TITLE_COLLATION: &str = "TITLE";
+
+/// Orders titles the way readers of a locale expect: base letters first, then accents, then
+/// case, so "énergie" sorts with "energie" instead of after "zebra".
+#[derive(Clone)]
+pub struct TitleCollator(Arc<CollatorBorrowed<'static>>);
+
+impl TitleCollator {
+    /// `locale` is a BCP 47 tag such as `"fr"` or `"sv-SE"`; `"und"` uses the root ordering,
+    /// which suits most Latin-script languages.
+    pub fn new(locale: &str) -> Result<TitleCollator, String> {
+        let locale = Locale::try_from_str(locale).map_err(|e| format!("\"{locale}\": {e}"))?;
+        CollatorBorrowed::try_new((&locale).into(), CollatorOptions::default())
+            .map(|collator| TitleCollator(Arc::new(collator)))
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn compare(&self, left: &str, right: &str) -> Ordering {
+        self.0.compare(left, right)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted<'a>(locale: &str, titles: &[&'a str]) -> Vec<&'a str> {
+        let collator = TitleCollator::new(locale).unwrap();
+        let mut titles = titles.to_vec();
+        titles.sort_by(|a, b| collator.compare(a, b));
+        titles
+    }
+
+    #[test]
+    fn composed_and_decomposed_titles_are_equal() {
+        let collator = TitleCollator::new("und").unwrap();
+        assert_eq!(collator.compare("café", "cafe\u{301}"), Ordering::Equal);
+        assert_eq!(
+            collator.compare("Ångström", "A\u{30a}ngstro\u{308}m"),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn accents_come_after_base_letters() {
+        assert_eq!(
+            sorted("und", &["zebra", "énergie", "Energie", "eau"]),
+            ["eau", "Energie", "énergie", "zebra"]
+        );
+    }
+
+    #[test]
+    fn turkish_sorts_dotless_i_before_dotted_i() {
+        assert_eq!(
+            sorted("tr", &["İzmir", "iğne", "Istanbul", "ılık"]),
+            ["ılık", "Istanbul", "iğne", "İzmir"]
+        );
+    }
+
+    #[test]
+    fn unknown_locale_is_an_error() {
+        assert!(TitleCollator::new("not a locale").is_err());
+    }
+}
@@ -0,0 +1,145 @@
+use std::{fs, path::PathBuf};
+
+use serde::Deserialize;
+
+use crate::{
+    daily::DailyConfig, dates::DisplayConfig, journal::JournalConfig, keymap::KeymapConfig,
+    models::SortOrder, theme::ThemeConfig,
+};
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct Config {
+    pub scripting: ScriptingConfig,
+    pub quit: QuitConfig,
+    pub notes: NotesConfig,
+    pub search: SearchConfig,
+    pub keymap: KeymapConfig,
+    pub theme: ThemeConfig,
+    pub display: DisplayConfig,
+    pub debug: DebugConfig,
+    pub export: ExportConfig,
+    pub daily: DailyConfig,
+    pub journal: JournalConfig,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ScriptingConfig {
+    /// Scripts are never loaded unless this is explicitly turned on.
+    pub enabled: bool,
+    /// Wall-clock budget for a single hook or command call.
+    pub timeout_ms: u64,
+}
+
+impl Default for ScriptingConfig {
+    fn default() -> Self {
+        ScriptingConfig {
+            enabled: false,
+            timeout_ms: 200,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct NotesConfig {
+    /// Refuse to create a note whose title is already taken.
+    pub unique_titles: bool,
+    /// Ask before creating a note whose title is already taken.
+    pub warn_duplicate_titles: bool,
+    pub sort_order: SortOrder,
+    /// Give each note two rows in the list, the title wrapping onto the second one beside the
+    /// date and tags, instead of cutting long titles off.
+    pub wrap_titles: bool,
+    /// BCP 47 locale whose alphabet decides how titles sort, e.g. `"de"` or `"sv"`.
+    pub locale: String,
+    /// Leave a `[[title]]` link where a note was split.
+    pub link_split_notes: bool,
+    /// Notes with more characters than this are suggested for splitting.
+    pub long_note_chars: usize,
+}
+
+impl Default for NotesConfig {
+    fn default() -> Self {
+        NotesConfig {
+            unique_titles: false,
+            warn_duplicate_titles: true,
+            sort_order: SortOrder::default(),
+            wrap_titles: false,
+            locale: "und".to_string(),
+            link_split_notes: true,
+            long_note_chars: 10_000,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct SearchConfig {
+    /// Start searches ignoring diacritics; can be toggled from the search screen.
+    pub fold_accents: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ExportConfig {
+    /// Turns a Markdown file into a PDF, e.g. `"typst compile {input} {output}"`. `{input}` and
+    /// `{output}` are replaced with the paths.
+    pub pdf_command: String,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        ExportConfig {
+            pdf_command: "pandoc {input} -o {output}".to_string(),
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct DebugConfig {
+    /// Show how many frames were drawn so far; it should stay still while the app is idle.
+    pub draw_counter: bool,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct QuitConfig {
+    pub confirm: QuitConfirm,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum QuitConfirm {
+    Always,
+    Never,
+    /// Only ask when something was written or left unsaved during the session.
+    #[default]
+    OnChanges,
+    /// Skip the dialog; a second `q` within a second quits.
+    DoublePress,
+}
+
+impl Config {
+    pub fn load() -> color_eyre::Result<Config> {
+        let Some(path) = config_dir().map(|dir| dir.join("config.toml")) else {
+            return Ok(Config::default());
+        };
+
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        let raw = fs::read_to_string(&path)?;
+        let config = toml::from_str(&raw)
+            .map_err(|e| color_eyre::eyre::eyre!("invalid config {}: {e}", path.display()))?;
+
+        Ok(config)
+    }
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("ratata-notes"))
+}
@@ -0,0 +1,87 @@
+use std::{fs, io};
+
+use chrono::{
+    NaiveDate,
+    format::{Item, StrftimeItems},
+};
+use serde::Deserialize;
+
+use crate::{
+    config,
+    db::{Database, DbError, NoteOrDuplicate},
+    models::Note,
+};
+
+/// `[daily]` in the config file.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct DailyConfig {
+    /// `strftime`-style format of daily note titles; it has to keep the whole date for the
+    /// previous and next day to be found from a note.
+    pub title_format: String,
+}
+
+impl Default for DailyConfig {
+    fn default() -> Self {
+        DailyConfig {
+            title_format: "%Y-%m-%d".to_string(),
+        }
+    }
+}
+
+/// The note of each day, titled with its date.
+pub struct Daily {
+    title_format: String,
+    /// Content of new daily notes, with `{date}` replaced by the title.
+    template: String,
+}
+
+impl Daily {
+    /// Reads the template from `templates/daily.md` in the config directory. New daily notes
+    /// start empty when there's none.
+    pub fn new(config: &DailyConfig) -> Result<Daily, String> {
+        if StrftimeItems::new(&config.title_format).any(|item| item == Item::Error) {
+            return Err(format!(
+                "daily.title_format: invalid format \"{}\"",
+                config.title_format
+            ));
+        }
+
+        let path = config::config_dir().map(|dir| dir.join("templates").join("daily.md"));
+        let template = match path.map(|path| (fs::read_to_string(&path), path)) {
+            Some((Ok(template), _)) => template,
+            Some((Err(e), path)) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(format!("{}: {e}", path.display()));
+            }
+            _ => String::new(),
+        };
+
+        Ok(Daily {
+            title_format: config.title_format.clone(),
+            template,
+        })
+    }
+
+    pub fn title(&self, date: NaiveDate) -> String {
+        date.format(&self.title_format).to_string()
+    }
+
+    /// The day a note is for, if it is titled like a daily note.
+    pub fn date_of(&self, title: &str) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(title.trim(), &self.title_format).ok()
+    }
+
+    /// The note of `date`, found by title or created from the template. `true` when created.
+    pub fn open(&self, db: &Database, date: NaiveDate) -> Result<(Note, bool), DbError> {
+        let title = self.title(date);
+        if let Some(note) = db.get_note_by_title(&title)? {
+            return Ok((note, false));
+        }
+
+        let content = self.template.replace("{date}", &title);
+        match db.add_note(&title, &content, false)? {
+            NoteOrDuplicate::Note(note) => Ok((note, true)),
+            NoteOrDuplicate::Duplicate { existing } => Ok((existing, false)),
+        }
+    }
+}
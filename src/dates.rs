@@ -0,0 +1,234 @@
+use chrono::{
+    DateTime, FixedOffset, Local, NaiveDate, NaiveTime, TimeZone, Utc,
+    format::{Item, StrftimeItems},
+};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+/// `[display]` in the config file. Timestamps are stored in UTC and only converted here.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct DisplayConfig {
+    /// IANA zone such as `"Europe/Paris"`; the system zone when unset.
+    pub timezone: Option<String>,
+    /// `strftime`-style format for dates older than yesterday.
+    pub date_format: String,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            timezone: None,
+            date_format: "%Y-%m-%d %H:%M".to_string(),
+        }
+    }
+}
+
+pub struct DateDisplay {
+    zone: Option<Tz>,
+    format: String,
+}
+
+impl DateDisplay {
+    pub fn new(config: &DisplayConfig) -> Result<DateDisplay, String> {
+        let zone = config
+            .timezone
+            .as_deref()
+            .map(|name| {
+                name.parse::<Tz>()
+                    .map_err(|_| format!("display.timezone: unknown time zone \"{name}\""))
+            })
+            .transpose()?;
+
+        // An invalid specifier would only fail once formatting, and panic there.
+        if StrftimeItems::new(&config.date_format).any(|item| item == Item::Error) {
+            return Err(format!(
+                "display.date_format: invalid format \"{}\"",
+                config.date_format
+            ));
+        }
+
+        Ok(DateDisplay {
+            zone,
+            format: config.date_format.clone(),
+        })
+    }
+
+    fn local(&self, at: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self.zone {
+            Some(zone) => at.with_timezone(&zone).fixed_offset(),
+            None => at.with_timezone(&Local).fixed_offset(),
+        }
+    }
+
+    /// The calendar day `at` falls on in the display zone.
+    pub fn local_date(&self, at: DateTime<Utc>) -> NaiveDate {
+        self.local(at).date_naive()
+    }
+
+    /// The time of day `at` is in the display zone.
+    pub fn local_time(&self, at: DateTime<Utc>) -> NaiveTime {
+        self.local(at).time()
+    }
+
+    /// When `date` begins in the display zone. Where a DST change skips midnight, the day
+    /// starts at the first whole hour that exists.
+    pub fn start_of_day(&self, date: NaiveDate) -> DateTime<Utc> {
+        match self.zone {
+            Some(zone) => first_instant(&zone, date),
+            None => first_instant(&Local, date),
+        }
+    }
+
+    pub fn absolute(&self, at: DateTime<Utc>) -> String {
+        self.local(at).format(&self.format).to_string()
+    }
+
+    /// "today 09:12" or "yesterday 23:58" by the calendar of the display zone, falling back
+    /// to `absolute` for anything older or in the future.
+    pub fn relative(&self, at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+        let local = self.local(at);
+        let today = self.local(now).date_naive();
+
+        if local.date_naive() == today {
+            format!("today {}", local.format("%H:%M"))
+        } else if today.pred_opt() == Some(local.date_naive()) {
+            format!("yesterday {}", local.format("%H:%M"))
+        } else {
+            self.absolute(at)
+        }
+    }
+}
+
+/// "just now", "5m ago", "3h ago", "2d ago" or "1y ago", rounded down. Anything in the future
+/// is "just now", which hides small clock differences between machines.
+pub fn time_ago(at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let elapsed = now.signed_duration_since(at);
+    match (
+        elapsed.num_minutes(),
+        elapsed.num_hours(),
+        elapsed.num_days(),
+    ) {
+        (..1, _, _) => "just now".to_string(),
+        (minutes, ..1, _) => format!("{minutes}m ago"),
+        (_, hours, ..1) => format!("{hours}h ago"),
+        (_, _, days @ ..365) => format!("{days}d ago"),
+        (_, _, days) => format!("{}y ago", days / 365),
+    }
+}
+
+fn first_instant<Z: TimeZone>(zone: &Z, date: NaiveDate) -> DateTime<Utc> {
+    (0..24)
+        .filter_map(|hour| date.and_hms_opt(hour, 0, 0))
+        .find_map(|local| zone.from_local_datetime(&local).earliest())
+        .map_or_else(|| date.and_time(NaiveTime::MIN).and_utc(), |at| at.to_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display(zone: &str) -> DateDisplay {
+        DateDisplay::new(&DisplayConfig {
+            timezone: Some(zone.to_string()),
+            ..DisplayConfig::default()
+        })
+        .unwrap()
+    }
+
+    fn utc(at: &str) -> DateTime<Utc> {
+        at.parse().unwrap()
+    }
+
+    fn date(date: &str) -> NaiveDate {
+        date.parse().unwrap()
+    }
+
+    #[test]
+    fn yesterday_is_by_the_local_calendar() {
+        // 23:58 and 00:05 the next day in Paris, but the same UTC day.
+        let at = utc("2026-10-14T21:58:00Z");
+        let now = utc("2026-10-14T22:05:00Z");
+        assert_eq!(display("Europe/Paris").relative(at, now), "yesterday 23:58");
+        assert_eq!(display("UTC").relative(at, now), "today 21:58");
+    }
+
+    #[test]
+    fn days_are_counted_across_dst_changes() {
+        let paris = display("Europe/Paris");
+        // The night clocks go back, yesterday just after midnight is 25 hours ago.
+        let now = utc("2026-10-25T23:30:00Z");
+        assert_eq!(
+            paris.relative(utc("2026-10-24T22:30:00Z"), now),
+            "yesterday 00:30"
+        );
+        // The night they go forward, it's 23 hours ago.
+        let now = utc("2026-03-29T22:30:00Z");
+        assert_eq!(
+            paris.relative(utc("2026-03-28T23:15:00Z"), now),
+            "yesterday 00:15"
+        );
+        assert_eq!(
+            paris.relative(utc("2026-03-28T22:45:00Z"), now),
+            "2026-03-28 23:45"
+        );
+    }
+
+    #[test]
+    fn day_starts_at_local_midnight() {
+        let paris = display("Europe/Paris");
+        assert_eq!(
+            paris.start_of_day(date("2026-03-29")),
+            utc("2026-03-28T23:00:00Z")
+        );
+        assert_eq!(
+            paris.start_of_day(date("2026-03-30")),
+            utc("2026-03-29T22:00:00Z")
+        );
+    }
+
+    #[test]
+    fn day_without_a_midnight_starts_at_its_first_hour() {
+        // Chile moved from 00:00 straight to 01:00 on that day.
+        let santiago = display("America/Santiago");
+        assert_eq!(
+            santiago.start_of_day(date("2022-09-11")),
+            utc("2022-09-11T04:00:00Z")
+        );
+        assert_eq!(
+            santiago.local_date(utc("2022-09-11T04:00:00Z")),
+            date("2022-09-11")
+        );
+        assert_eq!(
+            santiago.local_date(utc("2022-09-11T03:59:00Z")),
+            date("2022-09-10")
+        );
+    }
+
+    #[test]
+    fn invalid_settings_are_errors() {
+        assert!(
+            DateDisplay::new(&DisplayConfig {
+                timezone: Some("Mars/Olympus".to_string()),
+                ..DisplayConfig::default()
+            })
+            .is_err()
+        );
+        assert!(
+            DateDisplay::new(&DisplayConfig {
+                date_format: "%Q".to_string(),
+                ..DisplayConfig::default()
+            })
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn time_ago_rounds_down() {
+        let now = utc("2026-10-14T12:00:00Z");
+        assert_eq!(time_ago(utc("2026-10-14T12:00:30Z"), now), "just now");
+        assert_eq!(time_ago(utc("2026-10-14T11:00:01Z"), now), "59m ago");
+        assert_eq!(time_ago(utc("2026-10-12T11:00:00Z"), now), "2d ago");
+        assert_eq!(time_ago(utc("2024-10-13T12:00:00Z"), now), "2y ago");
+    }
+}
@@ -1,73 +1,1640 @@
-use rusqlite::{Connection, Result, params};
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
 
-use crate::Note;
+use chrono::{DateTime, Utc};
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{Connection, MAIN_DB, OptionalExtension, params};
+use sha2::{Digest, Sha256};
 
+use crate::{
+    Note,
+    archive::{self, NoteRecord},
+    collation::{TITLE_COLLATION, TitleCollator},
+    migrations,
+    models::{Draft, Mark, NoteColor, Revision, SortOrder},
+    search,
+    stats::Stats,
+    wiki_parser,
+};
+
+/// Columns read by `note_from_row`. The tag names are joined with the unit separator, which
+/// can't be typed into a tag.
+const NOTE_COLUMNS: &str =
+    "notes.id, notes.title, notes.content, notes.created_at, notes.updated_at, notes.color,
+    notes.source_url, notes.position, notes.archived_at,
+    (SELECT group_concat(tags.name, char(31) ORDER BY tags.name) FROM note_tags
+     JOIN tags ON tags.id = note_tags.tag_id
+     WHERE note_tags.note_id = notes.id) AS tag_names";
+
+/// Connections kept open by `Database`, enough for the TUI and a few background tasks.
+const POOL_SIZE: u32 = 4;
+
+/// Longest title `add_note` makes up from the content.
+const AUTO_TITLE_CHARS: usize = 60;
+
+/// Put between the two contents when merging notes.
+const MERGE_SEPARATOR: &str = "\n\n---\n\n";
+
+/// Draft key for a note that hasn't been saved yet. Real ids start at 1.
+pub const NEW_NOTE_DRAFT_ID: i64 = 0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DbError {
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// No pooled connection became free before the pool's timeout.
+    #[error(transparent)]
+    Pool(#[from] r2d2::Error),
+    /// Titles are only unique with `notes.unique_titles`, so a lookup by title can be ambiguous.
+    /// `count` is a lower bound: the lookup stops at the second match.
+    #[error("{count} or more notes share this title")]
+    AmbiguousTitle { count: usize },
+    /// Opening it could lose what the newer version stored, so it is refused before anything
+    /// is written.
+    #[error(
+        "the database has schema version {found}, newer than the {supported} this build knows; \
+         update ratata-notes to open it"
+    )]
+    SchemaTooNew { found: u32, supported: usize },
+}
+
+pub enum NoteOrDuplicate {
+    Note(Note),
+    Duplicate { existing: Note },
+}
+
+type Result<T, E = DbError> = std::result::Result<T, E>;
+
+/// Shared by the TUI and anything running beside it. Every method borrows a connection from
+/// the pool while it runs; work that has to be atomic stays on that one connection through the
+/// free functions at the bottom of this file.
 pub struct Database {
-    connection: Connection,
+    pool: Pool<SqliteConnectionManager>,
 }
 
 impl Database {
-    pub fn new(db_path: &str) -> Result<Database> {
-        let conn = Connection::open(db_path)?;
+    /// `title_collator` is registered on every connection, as SQLite collations are.
+    pub fn new(db_path: &str, title_collator: TitleCollator) -> Result<Database> {
+        let manager = SqliteConnectionManager::file(db_path).with_init(move |conn| {
+            conn.pragma_update(None, "foreign_keys", true)?;
+            let collator = title_collator.clone();
+            conn.create_collation(TITLE_COLLATION, move |left, right| {
+                collator.compare(left, right)
+            })
+        });
+        let pool = Pool::builder().max_size(POOL_SIZE).build(manager)?;
+        let conn = pool.get()?;
+        migrations::run(&conn)?;
+        mark_timestamps_utc(&conn)?;
+        fill_missing_positions(&conn)?;
+
+        drop(conn);
+        Ok(Database { pool })
+    }
+
+    /// Checkpoints the write-ahead log, if the database has one, and closes the pool. The pool
+    /// closes its connections on drop and can't report a failure then, so this surfaces what
+    /// can go wrong before the file is let go.
+    pub fn close(self) -> Result<()> {
+        let conn = self.connection()?;
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        drop(conn);
+        drop(self.pool);
+        Ok(())
+    }
+
+    fn connection(&self) -> Result<PooledConnection<SqliteConnectionManager>> {
+        Ok(self.pool.get()?)
+    }
+
+    /// Writes a compacted copy of the database to `dest_path`, which must not exist yet. SQLite
+    /// older than 3.27 has no `VACUUM INTO` and gets a page-by-page backup instead.
+    pub fn vacuum_into(&self, dest_path: &Path) -> Result<()> {
+        let conn = self.connection()?;
+        if rusqlite::version_number() >= 3_027_000 {
+            conn.execute("VACUUM INTO ?1", params![dest_path.to_string_lossy()])?;
+            Ok(())
+        } else {
+            Ok(conn.backup(MAIN_DB, dest_path, None)?)
+        }
+    }
+
+    /// Writes a copy of the database to `dest_path` for sharing, without the unsaved drafts or
+    /// the app state.
+    /// SQLite has no flag that keeps a file read-only across connections (`PRAGMA query_only`
+    /// only lasts for one connection), so the copy is made read-only on the file system.
+    pub fn export_as_sqlite(&self, dest_path: &Path) -> Result<()> {
+        let tmp_path = archive::temp_path(dest_path);
+        // Left behind by an interrupted export; `VACUUM INTO` refuses to overwrite it.
+        match fs::remove_file(&tmp_path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        self.vacuum_into(&tmp_path)?;
+
+        let copy = Connection::open(&tmp_path)?;
+        copy.execute_batch("DELETE FROM drafts; DELETE FROM app_state; VACUUM;")?;
+        drop(copy);
+
+        let mut permissions = fs::metadata(&tmp_path)?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&tmp_path, permissions)?;
+        fs::rename(&tmp_path, dest_path)?;
+
+        Ok(())
+    }
+
+    /// Writes every note, archived ones included, as a Roam Research JSON import file.
+    pub fn export_as_roam_json(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_vec_pretty(&archive::roam_pages(&self.get_all_notes()?))?;
+        let tmp_path = archive::temp_path(path);
+        fs::write(&tmp_path, json)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Creates a note. With `detect_duplicates`, nothing is inserted if another note already
+    /// has the same title (ignoring case), and that note is returned instead. A blank title is
+    /// replaced with the first line of the content.
+    pub fn add_note(
+        &self,
+        title: &str,
+        content: &str,
+        detect_duplicates: bool,
+    ) -> Result<NoteOrDuplicate> {
+        let generated;
+        let title = if title.trim().is_empty() {
+            generated = title_from_content(content);
+            &generated
+        } else {
+            title
+        };
+        if detect_duplicates && let Some(existing) = self.find_note_by_title(title)? {
+            return Ok(NoteOrDuplicate::Duplicate { existing });
+        }
+
+        Ok(NoteOrDuplicate::Note(
+            self.add_note_with_created_at(title, content, None)?,
+        ))
+    }
+
+    /// Creates a note dated `created_at` instead of now, so imports keep their original dates.
+    pub fn add_note_with_created_at(
+        &self,
+        title: &str,
+        content: &str,
+        created_at: Option<DateTime<Utc>>,
+    ) -> Result<Note> {
+        Ok(insert_note(
+            &*self.connection()?,
+            title,
+            content,
+            created_at,
+            None,
+        )?)
+    }
+
+    /// Creates a note recording the `url` it was clipped from.
+    pub fn add_note_with_source(
+        &self,
+        title: &str,
+        content: &str,
+        url: Option<&str>,
+    ) -> Result<Note> {
+        Ok(insert_note(
+            &*self.connection()?,
+            title,
+            content,
+            None,
+            url,
+        )?)
+    }
+
+    fn find_note_by_title(&self, title: &str) -> Result<Option<Note>> {
+        let conn = self.connection()?;
+        let note = conn
+            .query_row(
+                &format!(
+                    "SELECT {NOTE_COLUMNS} FROM notes WHERE title = ?1 COLLATE NOCASE LIMIT 1"
+                ),
+                params![search::normalize(title)],
+                note_from_row,
+            )
+            .optional()?;
+        Ok(note)
+    }
+
+    /// The note titled `title`, ignoring ASCII case. Errors if several notes carry it.
+    pub fn get_note_by_title(&self, title: &str) -> Result<Option<Note>> {
+        let conn = self.connection()?;
+        let mut statement = conn.prepare(&format!(
+            "SELECT {NOTE_COLUMNS} FROM notes WHERE title = ?1 COLLATE NOCASE LIMIT 2"
+        ))?;
+        let mut notes = statement
+            .query_map(params![search::normalize(title)], note_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        match notes.len() {
+            0 | 1 => Ok(notes.pop()),
+            count => Err(DbError::AmbiguousTitle { count }),
+        }
+    }
+
+    /// The notes with `[[title]]` links that no note answers to, each with those titles in the
+    /// order they first appear. Archived notes are still valid targets but aren't checked.
+    pub fn find_notes_with_broken_links(&self) -> Result<Vec<(Note, Vec<String>)>> {
+        let mut broken = Vec::new();
+        for note in self.get_all_notes_sorted(SortOrder::Created, 0)?.iter() {
+            let mut targets: Vec<String> = Vec::new();
+            for link in wiki_parser::links(&note.content) {
+                if targets.iter().any(|target| target == link.target) {
+                    continue;
+                }
+                match self.get_note_by_title(link.target) {
+                    // Ambiguous links still lead somewhere.
+                    Ok(Some(_)) | Err(DbError::AmbiguousTitle { .. }) => {}
+                    Ok(None) => targets.push(link.target.to_string()),
+                    Err(e) => return Err(e),
+                }
+            }
+            if !targets.is_empty() {
+                broken.push((note.clone(), targets));
+            }
+        }
+        Ok(broken)
+    }
+
+    /// The notes whose content is exactly that of the file at `path`, once both are normalized
+    /// the way notes are stored. Used to tell which files were already imported.
+    pub fn get_notes_with_content_matching_file(&self, path: &Path) -> Result<Vec<Note>> {
+        let hash = content_hash(&search::normalize(&fs::read_to_string(path)?));
+        let conn = self.connection()?;
+        refresh_content_hashes(&conn)?;
+        let mut query = conn.prepare(&format!(
+            "SELECT {NOTE_COLUMNS} FROM notes WHERE content_hash = ?1 ORDER BY id"
+        ))?;
+        let notes = query
+            .query_map(params![hash], note_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(notes)
+    }
+
+    /// `true` with the note when something was written, see the free function.
+    pub fn update_note(&self, id: i64, title: &str, content: &str) -> Result<(Note, bool)> {
+        Ok(update_note(&*self.connection()?, id, title, content)?)
+    }
+    /// Adds `line` at the end of the note, on a line of its own. Unlike `update_note`, this
+    /// can't overwrite what another connection saved in the meantime.
+    pub fn append_to_note(&self, id: i64, line: &str) -> Result<Note> {
+        let conn = self.connection()?;
+        record_history(&conn, id)?;
+        let note = conn.query_row(
+            &format!(
+                "UPDATE notes
+                 SET content = CASE
+                        WHEN content = '' OR content LIKE '%' || char(10) THEN content
+                        ELSE content || char(10)
+                     END || ?1,
+                     updated_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+                 WHERE id = ?2
+                 RETURNING {NOTE_COLUMNS}"
+            ),
+            params![search::normalize(line), id],
+            note_from_row,
+        )?;
+
+        Ok(note)
+    }
+
+    /// Exchanges the positions of two notes, which moves them past each other in the created
+    /// order. Returns their new positions. Deleted notes leave gaps, which swapping doesn't mind;
+    /// notes sharing a position are first given positions of their own.
+    pub fn swap_positions(&self, id_a: i64, id_b: i64) -> Result<(i64, i64)> {
+        let conn = self.connection()?;
+        let transaction = conn.unchecked_transaction()?;
+        let position = |id: i64| {
+            transaction.query_row(
+                "SELECT position FROM notes WHERE id = ?1",
+                params![id],
+                |row| row.get::<_, i64>(0),
+            )
+        };
+        if position(id_a)? == position(id_b)? {
+            transaction.execute(
+                "UPDATE notes SET position = ranked.position
+                 FROM (SELECT id, ROW_NUMBER() OVER (ORDER BY position, id) AS position FROM notes)
+                     AS ranked
+                 WHERE ranked.id = notes.id AND notes.position IS NOT ranked.position",
+                [],
+            )?;
+        }
+        let (position_a, position_b) = (position(id_a)?, position(id_b)?);
+        transaction.execute(
+            "UPDATE notes SET position = CASE id WHEN ?1 THEN ?4 ELSE ?3 END
+             WHERE id IN (?1, ?2)",
+            params![id_a, id_b, position_a, position_b],
+        )?;
+        transaction.commit()?;
+        Ok((position_b, position_a))
+    }
+
+    pub fn delete_note(&self, id: i64) -> Result<()> {
+        let conn = self.connection()?;
+        conn.execute("DELETE FROM notes WHERE id = ?1", params![id])?;
+        conn.execute("DELETE FROM drafts WHERE note_id = ?1", params![id])?;
+
+        Ok(())
+    }
+
+    /// Deletes every note tagged `tag`, archived ones included, with their drafts. Returns how
+    /// many notes were deleted.
+    pub fn delete_all_notes_in_tag(&self, tag: &str) -> Result<u64> {
+        let conn = self.connection()?;
+        let transaction = conn.unchecked_transaction()?;
+        let ids = transaction
+            .prepare(
+                "SELECT note_tags.note_id FROM note_tags
+                 JOIN tags ON tags.id = note_tags.tag_id
+                 WHERE tags.name = ?1",
+            )?
+            .query_map(params![search::normalize(tag)], |row| row.get::<_, i64>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        for id in &ids {
+            // Tags go with the note through `ON DELETE CASCADE`; drafts aren't linked.
+            transaction.execute("DELETE FROM notes WHERE id = ?1", params![id])?;
+            transaction.execute("DELETE FROM drafts WHERE note_id = ?1", params![id])?;
+        }
+        transaction.commit()?;
+        Ok(ids.len() as u64)
+    }
+
+    /// Every note, archived ones included.
+    pub fn get_all_notes(&self) -> Result<Arc<[Note]>> {
+        let conn = self.connection()?;
+        let mut query = conn.prepare(&format!("SELECT {NOTE_COLUMNS} FROM notes ORDER BY id"))?;
+
+        let notes = query
+            .query_map([], note_from_row)?
+            .collect::<rusqlite::Result<Arc<[Note]>>>()?;
+
+        Ok(notes)
+    }
+
+    /// Appends `source` to `target` and deletes `source`. The merged note has the tags of both,
+    /// the earlier creation time and the later update time, and the history of both with the
+    /// last versions of each before the merge.
+    pub fn merge_notes(&self, source: &Note, target: &Note) -> Result<Note> {
+        let conn = self.connection()?;
+        let transaction = conn.unchecked_transaction()?;
+        transaction.execute(
+            "INSERT OR IGNORE INTO note_tags (note_id, tag_id)
+             SELECT ?1, tag_id FROM note_tags WHERE note_id = ?2",
+            params![target.id, source.id],
+        )?;
+        record_history(&transaction, target.id)?;
+        record_history(&transaction, source.id)?;
+        merge_note_history(&transaction, target.id, source.id, target.id)?;
+        let merged = transaction.query_row(
+            &format!(
+                "UPDATE notes SET content = ?1, created_at = ?2, updated_at = ?3
+                 WHERE id = ?4
+                 RETURNING {NOTE_COLUMNS}"
+            ),
+            params![
+                merged_content(target, source),
+                utc_timestamp(target.created_at.min(source.created_at)),
+                utc_timestamp(target.updated_at.max(source.updated_at)),
+                target.id
+            ],
+            note_from_row,
+        )?;
+        transaction.execute("DELETE FROM notes WHERE id = ?1", params![source.id])?;
+        transaction.execute("DELETE FROM drafts WHERE note_id = ?1", params![source.id])?;
+        transaction.commit()?;
+        Ok(merged)
+    }
+
+    /// Replaces note `id` with two new notes holding its lines before `split_line` and the ones
+    /// from it on, both with its tags. The top one keeps the title, so links to the note lead
+    /// to it, and the history; the bottom one is titled after its first line. Returns both.
+    pub fn split_note(&self, id: i64, split_line: usize) -> Result<(Note, Note)> {
+        let conn = self.connection()?;
+        let transaction = conn.unchecked_transaction()?;
+        let original = transaction.query_row(
+            &format!("SELECT {NOTE_COLUMNS} FROM notes WHERE id = ?1"),
+            params![id],
+            note_from_row,
+        )?;
+        let split = original
+            .content
+            .split_inclusive('\n')
+            .take(split_line)
+            .map(str::len)
+            .sum::<usize>();
+        let (head, tail) = original.content.split_at(split);
+        let head = head.strip_suffix('\n').unwrap_or(head);
+
+        let source_url = original.source_url.as_deref();
+        let created_at = Some(original.created_at);
+        let top = insert_note(&transaction, &original.title, head, created_at, source_url)?;
+        let bottom_title = title_from_content(tail);
+        let bottom = insert_note(&transaction, &bottom_title, tail, created_at, source_url)?;
+        for note in [&top, &bottom] {
+            transaction.execute(
+                "INSERT INTO note_tags (note_id, tag_id)
+                 SELECT ?1, tag_id FROM note_tags WHERE note_id = ?2",
+                params![note.id, id],
+            )?;
+        }
+        transaction.execute(
+            "UPDATE note_history SET note_id = ?1 WHERE note_id = ?2",
+            params![top.id, id],
+        )?;
+        transaction.execute("DELETE FROM notes WHERE id = ?1", params![id])?;
+        transaction.execute("DELETE FROM drafts WHERE note_id = ?1", params![id])?;
+        transaction.commit()?;
+
+        // Read back with the tags they were given after being inserted.
+        let read = |id| {
+            conn.query_row(
+                &format!("SELECT {NOTE_COLUMNS} FROM notes WHERE id = ?1"),
+                params![id],
+                note_from_row,
+            )
+        };
+        Ok((read(top.id)?, read(bottom.id)?))
+    }
 
+    /// Saves note `id` as `title` and `head`, and moves `tail` into a new note titled
+    /// `new_title` with the same tags. Returns both.
+    pub fn split_off_note(
+        &self,
+        id: i64,
+        title: &str,
+        head: &str,
+        new_title: &str,
+        tail: &str,
+    ) -> Result<(Note, Note)> {
+        let conn = self.connection()?;
+        let transaction = conn.unchecked_transaction()?;
+        let (original, _) = update_note(&transaction, id, title, head)?;
+        let created = insert_note(&transaction, new_title, tail, None, None)?;
+        transaction.execute(
+            "INSERT INTO note_tags (note_id, tag_id)
+             SELECT ?1, tag_id FROM note_tags WHERE note_id = ?2",
+            params![created.id, id],
+        )?;
+        transaction.commit()?;
+        Ok((original, created))
+    }
+
+    /// Puts notes back exactly as given, ids and tags included, recreating deleted ones, and
+    /// deletes the notes in `created`.
+    pub fn restore_notes(&self, notes: &[(Note, Vec<String>)], created: &[i64]) -> Result<()> {
+        let conn = self.connection()?;
+        let transaction = conn.unchecked_transaction()?;
+        for id in created {
+            transaction.execute("DELETE FROM notes WHERE id = ?1", params![id])?;
+        }
+        for (note, tags) in notes {
+            // Deleting the old row clears its tags. REPLACE would too, but without running the
+            // delete trigger that keeps `notes_fts` in sync.
+            transaction.execute("DELETE FROM notes WHERE id = ?1", params![note.id])?;
+            transaction.execute(
+                "INSERT INTO notes
+                 (id, title, content, created_at, updated_at, color, source_url, position,
+                  archived_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    note.id,
+                    note.title,
+                    note.content,
+                    utc_timestamp(note.created_at),
+                    utc_timestamp(note.updated_at),
+                    note.color.name(),
+                    note.source_url,
+                    note.position,
+                    note.archived_at.map(utc_timestamp)
+                ],
+            )?;
+            for tag in tags {
+                tag_note(&transaction, note.id, tag)?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Notes created in `[from, to)`.
+    pub fn get_notes_created_in_range(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Note>> {
+        self.get_notes_where(
+            "created_at >= ?1 AND created_at < ?2",
+            params![utc_timestamp(from), utc_timestamp(to)],
+        )
+    }
+
+    /// Notes last edited in `[from, to)`, after the moment they were created.
+    pub fn get_notes_modified_in_range(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Note>> {
+        self.get_notes_where(
+            "updated_at >= ?1 AND updated_at < ?2 AND updated_at > created_at",
+            params![utc_timestamp(from), utc_timestamp(to)],
+        )
+    }
+
+    /// Notes that aren't archived and haven't been edited since `cutoff`, least recent first.
+    pub fn get_notes_not_updated_since(&self, cutoff: DateTime<Utc>) -> Result<Vec<Note>> {
+        self.get_notes_where(
+            "archived_at IS NULL AND updated_at < ?1 ORDER BY updated_at, id",
+            params![utc_timestamp(cutoff)],
+        )
+    }
+
+    /// Archives every note `get_notes_not_updated_since` returns, numbering them as one new
+    /// `archive_batch` so `unarchive_last_batch` can find them again.
+    pub fn archive_notes_not_updated_since(&self, cutoff: DateTime<Utc>) -> Result<usize> {
+        let conn = self.connection()?;
+        let transaction = conn.unchecked_transaction()?;
+        let archived = transaction.execute(
+            "UPDATE notes
+             SET archived_at = ?1,
+                 archive_batch = (SELECT COALESCE(MAX(archive_batch), 0) + 1 FROM notes)
+             WHERE archived_at IS NULL AND updated_at < ?2",
+            params![utc_timestamp(Utc::now()), utc_timestamp(cutoff)],
+        )?;
+        transaction.commit()?;
+        Ok(archived)
+    }
+
+    /// Moves every note `get_notes_not_updated_since` returns into the database at
+    /// `archive_path`, with its tags, history and marks. The file must already have the schema
+    /// `Database::new` creates. Both files change in one transaction, so an interrupted move
+    /// leaves each note in exactly one of them.
+    pub fn move_notes_not_updated_since(
+        &self,
+        cutoff: DateTime<Utc>,
+        archive_path: &Path,
+    ) -> Result<usize> {
+        self.with_archive(archive_path, |conn| {
+            conn.execute(
+                "CREATE TEMP TABLE moving AS
+                 SELECT id FROM main.notes WHERE archived_at IS NULL AND updated_at < ?1",
+                params![utc_timestamp(cutoff)],
+            )?;
+            move_notes(conn, "main", "archive")
+        })
+    }
+
+    /// Moves every note in the database at `archive_path` back into this one.
+    pub fn restore_archive(&self, archive_path: &Path) -> Result<usize> {
+        self.with_archive(archive_path, |conn| {
+            conn.execute(
+                "CREATE TEMP TABLE moving AS SELECT id FROM archive.notes",
+                [],
+            )?;
+            move_notes(conn, "archive", "main")
+        })
+    }
+
+    /// Runs `f` in a transaction on a connection with `archive_path` attached as `archive`.
+    /// The pooled connection is handed back detached whatever `f` returns.
+    fn with_archive(
+        &self,
+        archive_path: &Path,
+        f: impl FnOnce(&Connection) -> rusqlite::Result<usize>,
+    ) -> Result<usize> {
+        let conn = self.connection()?;
+        conn.execute(
+            "ATTACH DATABASE ?1 AS archive",
+            params![archive_path.to_string_lossy()],
+        )?;
+        let result = conn.unchecked_transaction().and_then(|transaction| {
+            let moved = f(&transaction)?;
+            transaction.execute("DROP TABLE temp.moving", [])?;
+            transaction.commit()?;
+            Ok(moved)
+        });
+        conn.execute("DETACH DATABASE archive", [])?;
+        Ok(result?)
+    }
+
+    /// Archives the note now, or brings it back to the list. Like a color, this isn't an edit:
+    /// `updated_at` stays as it was. Either way the note no longer belongs to an archive batch.
+    pub fn set_archived(&self, id: i64, archived: bool) -> Result<()> {
+        let conn = self.connection()?;
         conn.execute(
-            "CREATE TABLE IF NOT EXISTS notes (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                title TEXT NOT NULL,
-                content TEXT NOT NULL
-            )",
+            "UPDATE notes
+             SET archived_at = CASE WHEN ?2 THEN strftime('%Y-%m-%dT%H:%M:%SZ', 'now') END,
+                 archive_batch = NULL
+             WHERE id = ?1",
+            params![id, archived],
+        )?;
+        Ok(())
+    }
+
+    /// Restores the notes of the last `archive_notes_not_updated_since` run still archived.
+    /// Notes archived one at a time with `set_archived` stay archived.
+    pub fn unarchive_last_batch(&self) -> Result<usize> {
+        let conn = self.connection()?;
+        let restored = conn.execute(
+            "UPDATE notes SET archived_at = NULL, archive_batch = NULL
+             WHERE archive_batch = (SELECT MAX(archive_batch) FROM notes)",
             [],
         )?;
+        Ok(restored)
+    }
+
+    /// Timestamps are stored as `YYYY-MM-DDTHH:MM:SSZ`, so comparing them as text is
+    /// chronological. `condition` is ordered by id unless it brings its own `ORDER BY`.
+    fn get_notes_where(&self, condition: &str, params: impl rusqlite::Params) -> Result<Vec<Note>> {
+        let conn = self.connection()?;
+        let order_by = if condition.contains("ORDER BY") {
+            ""
+        } else {
+            " ORDER BY id"
+        };
+        let mut query = conn.prepare(&format!(
+            "SELECT {NOTE_COLUMNS} FROM notes WHERE {condition}{order_by}"
+        ))?;
+        let notes = query
+            .query_map(params, note_from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(notes)
+    }
+
+    /// Labels the note without counting as an edit: `updated_at` stays as it was.
+    pub fn set_note_color(&self, id: i64, color: NoteColor) -> Result<()> {
+        let conn = self.connection()?;
+        conn.execute(
+            "UPDATE notes SET color = ?1 WHERE id = ?2",
+            params![color.name(), id],
+        )?;
+        Ok(())
+    }
+
+    /// Unarchived notes with every word of `query` in their title or content, best match first.
+    /// Words are matched whole, ignoring case and accents, except the last, which may be the
+    /// start of a word so that results show up while it's typed.
+    pub fn search(&self, query: &str) -> Result<Vec<Note>> {
+        let Some(match_expression) = full_text_query(query) else {
+            return Ok(Vec::new());
+        };
+        let conn = self.connection()?;
+        let mut statement = conn.prepare(&format!(
+            "SELECT {NOTE_COLUMNS} FROM notes_fts
+             JOIN notes ON notes.id = notes_fts.rowid
+             WHERE notes_fts MATCH ?1 AND notes.archived_at IS NULL
+             ORDER BY notes_fts.rank"
+        ))?;
+        let notes = statement
+            .query_map(params![match_expression], note_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(notes)
+    }
 
-        Ok(Database { connection: conn })
+    /// Unarchived notes labelled `color`, or carrying no label for `NoteColor::None`.
+    pub fn get_notes_by_color(&self, color: NoteColor) -> Result<Vec<Note>> {
+        match color.name() {
+            Some(name) => self.get_notes_where("archived_at IS NULL AND color = ?1", params![name]),
+            None => self.get_notes_where("archived_at IS NULL AND color IS NULL", []),
+        }
     }
 
-    pub fn add_note(&self, title: &str, content: &str) -> Result<Note> {
-        self.connection.execute(
-            "INSERT INTO notes (title, content) VALUES (?1, ?2)",
-            params![title, content],
+    pub fn get_note(&self, id: i64) -> Result<Option<Note>> {
+        let conn = self.connection()?;
+        let note = conn
+            .query_row(
+                &format!("SELECT {NOTE_COLUMNS} FROM notes WHERE id = ?1"),
+                params![id],
+                note_from_row,
+            )
+            .optional()?;
+        Ok(note)
+    }
+
+    /// Tags `note_id` with `tag`, creating the tag on first use.
+    pub fn add_tag_to_note(&self, note_id: i64, tag: &str) -> Result<()> {
+        tag_note(&*self.connection()?, note_id, tag)?;
+        Ok(())
+    }
+
+    /// Removes `tag` from `note_id`, and deletes the tag once it was the last note using it.
+    pub fn remove_tag_from_note(&self, note_id: i64, tag: &str) -> Result<()> {
+        let conn = self.connection()?;
+        let transaction = conn.unchecked_transaction()?;
+        untag_note(&transaction, note_id, tag)?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Adds and removes tags on every note in `note_ids` at once. Returns how many notes
+    /// actually changed.
+    pub fn edit_tags(&self, note_ids: &[i64], add: &[String], remove: &[String]) -> Result<usize> {
+        let conn = self.connection()?;
+        let transaction = conn.unchecked_transaction()?;
+        let mut touched = 0;
+        for &note_id in note_ids {
+            let mut changed = false;
+            for tag in add {
+                changed |= tag_note(&transaction, note_id, tag)?;
+            }
+            for tag in remove {
+                changed |= untag_note(&transaction, note_id, tag)?;
+            }
+            touched += usize::from(changed);
+        }
+        transaction.commit()?;
+        Ok(touched)
+    }
+
+    /// Every tag name, sorted.
+    pub fn get_tag_names(&self) -> Result<Vec<String>> {
+        let conn = self.connection()?;
+        let mut statement = conn.prepare("SELECT name FROM tags ORDER BY name")?;
+        let names = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(names)
+    }
+
+    /// The tags of every tagged note, by note id, each list sorted.
+    pub fn get_tags_by_note(&self) -> Result<HashMap<i64, Vec<String>>> {
+        let conn = self.connection()?;
+        let mut statement = conn.prepare(
+            "SELECT note_tags.note_id, tags.name FROM tags
+             JOIN note_tags ON note_tags.tag_id = tags.id
+             ORDER BY tags.name",
         )?;
+        let mut tags: HashMap<i64, Vec<String>> = HashMap::new();
+        let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        for row in rows {
+            let (note_id, tag) = row?;
+            tags.entry(note_id).or_default().push(tag);
+        }
+        Ok(tags)
+    }
 
-        Ok(Note {
-            id: self.connection.last_insert_rowid(),
-            title: title.to_string(),
-            content: content.to_string(),
-        })
+    pub fn get_note_tags(&self, note_id: i64) -> Result<Vec<String>> {
+        let conn = self.connection()?;
+        let mut statement = conn.prepare(
+            "SELECT tags.name FROM tags
+             JOIN note_tags ON note_tags.tag_id = tags.id
+             WHERE note_tags.note_id = ?1
+             ORDER BY tags.name",
+        )?;
+        let tags = statement
+            .query_map(params![note_id], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(tags)
     }
-    pub fn update_note(&self, id: i64, title: &str, content: &str) -> Result<Note> {
-        self.connection.execute(
-            "UPDATE notes SET title = ?1, content = ?2 WHERE id = ?3",
-            params![title, content, id],
+
+    pub fn get_stats(&self) -> Result<Stats> {
+        let conn = self.connection()?;
+        let (note_count, average_length) = conn.query_row(
+            "SELECT COUNT(*), AVG(length(content)) FROM notes",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        let pairs = |sql: &str| -> rusqlite::Result<Vec<(String, u32)>> {
+            let mut statement = conn.prepare(sql)?;
+            statement
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect()
+        };
+        let created_per_month = pairs(
+            "SELECT substr(created_at, 1, 7) AS month, COUNT(*) FROM notes
+             GROUP BY month ORDER BY month",
+        )?;
+        let top_tags = pairs(
+            "SELECT tags.name, COUNT(*) AS uses FROM tags
+             JOIN note_tags ON note_tags.tag_id = tags.id
+             GROUP BY tags.id ORDER BY uses DESC, tags.name LIMIT 10",
+        )?;
+        let largest_notes = pairs(
+            "SELECT title, length(content) AS size FROM notes ORDER BY size DESC, id LIMIT 10",
+        )?;
+
+        let mut statement = conn.prepare(
+            "SELECT date(created_at) AS day FROM notes
+             UNION SELECT date(updated_at) FROM notes
+             ORDER BY day DESC",
         )?;
+        let edit_days = statement
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
 
-        Ok(Note {
-            id,
-            title: title.to_string(),
-            content: content.to_string(),
+        Ok(Stats {
+            note_count,
+            average_length,
+            created_per_month,
+            top_tags,
+            largest_notes,
+            edit_days,
         })
     }
-    pub fn delete_note(&self, id: i64) -> Result<()> {
-        self.connection
-            .execute("DELETE FROM notes WHERE id = ?1", params![id])?;
 
-        Ok(())
+    /// Every tag that is used by at least one note, by name, with its notes in creation order.
+    pub fn get_notes_by_tag(&self) -> Result<Vec<(String, Vec<Note>)>> {
+        let conn = self.connection()?;
+        let mut query = conn.prepare(&format!(
+            "SELECT tags.name AS tag, {NOTE_COLUMNS} FROM tags
+             JOIN note_tags ON note_tags.tag_id = tags.id
+             JOIN notes ON notes.id = note_tags.note_id
+             WHERE notes.archived_at IS NULL
+             ORDER BY tags.name, notes.id"
+        ))?;
+
+        let mut tags: Vec<(String, Vec<Note>)> = Vec::new();
+        let rows = query.query_map([], |row| {
+            Ok((row.get::<_, String>("tag")?, note_from_row(row)?))
+        })?;
+        for row in rows {
+            let (tag, note) = row?;
+            match tags.last_mut() {
+                Some((last, notes)) if *last == tag => notes.push(note),
+                _ => tags.push((tag, vec![note])),
+            }
+        }
+
+        Ok(tags)
     }
 
-    pub fn get_all_notes(&self) -> Result<Vec<Note>> {
-        let mut query = self
-            .connection
-            .prepare("SELECT id, title, content FROM notes ORDER BY id")?;
+    /// `seed` only matters for `SortOrder::Random`: the same seed gives the same order.
+    pub fn get_all_notes_sorted(&self, order: SortOrder, seed: u64) -> Result<Arc<[Note]>> {
+        self.get_notes_sorted("archived_at IS NULL", order, seed)
+    }
+
+    /// The archived notes, which `get_all_notes_sorted` leaves out, in the same order.
+    pub fn get_archived_notes(&self, order: SortOrder, seed: u64) -> Result<Arc<[Note]>> {
+        self.get_notes_sorted("archived_at IS NOT NULL", order, seed)
+    }
+
+    fn get_notes_sorted(
+        &self,
+        condition: &str,
+        order: SortOrder,
+        seed: u64,
+    ) -> Result<Arc<[Note]>> {
+        let conn = self.connection()?;
+        let order_by = order_by(order);
+        let mut query = conn.prepare(&format!(
+            "SELECT {NOTE_COLUMNS}, {SHUFFLE_HASH} AS hash
+             FROM notes WHERE {condition} ORDER BY {order_by}"
+        ))?;
+
+        let seed = (seed % 2_147_483_648) as i64;
+        let rows = if matches!(order, SortOrder::Random) {
+            query.query_map(params![seed], note_from_row)?
+        } else {
+            query.query_map([], note_from_row)?
+        };
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// The notes just before and after `id` in the list as `get_all_notes_sorted` orders it.
+    pub fn get_note_siblings(
+        &self,
+        id: i64,
+        order: SortOrder,
+        seed: u64,
+    ) -> Result<(Option<Note>, Option<Note>)> {
+        let conn = self.connection()?;
+        let order_by = order_by(order);
+        let mut query = conn.prepare(&format!(
+            "WITH listed AS (
+                 SELECT id, LAG(id) OVER list AS prev_id, LEAD(id) OVER list AS next_id
+                 -- Every column `order_by` can sort on.
+                 FROM (
+                     SELECT id, title, position, updated_at, {SHUFFLE_HASH} AS hash
+                     FROM notes WHERE archived_at IS NULL
+                 )
+                 WINDOW list AS (ORDER BY {order_by})
+             )
+             SELECT {NOTE_COLUMNS}, notes.id IS listed.prev_id AS is_prev
+             FROM listed JOIN notes ON notes.id IN (listed.prev_id, listed.next_id)
+             WHERE listed.id = ?2"
+        ))?;
+
+        let seed = (seed % 2_147_483_648) as i64;
+        let mut siblings = (None, None);
+        let rows = query.query_map(params![seed, id], |row| {
+            Ok((row.get::<_, bool>("is_prev")?, note_from_row(row)?))
+        })?;
+        for row in rows {
+            match row? {
+                (true, note) => siblings.0 = Some(note),
+                (false, note) => siblings.1 = Some(note),
+            }
+        }
+        Ok(siblings)
+    }
 
+    /// Unarchived notes with more than `threshold_chars` characters of content, longest first.
+    pub fn find_long_notes(&self, threshold_chars: usize) -> Result<Vec<Note>> {
+        self.get_notes_where(
+            "LENGTH(content) > ?1 AND archived_at IS NULL ORDER BY LENGTH(content) DESC",
+            params![i64::try_from(threshold_chars).unwrap_or(i64::MAX)],
+        )
+    }
+
+    pub fn get_notes_by_content_length_range(&self, min: usize, max: usize) -> Result<Vec<Note>> {
+        let conn = self.connection()?;
+        let mut query = conn.prepare(&format!(
+            "SELECT {NOTE_COLUMNS} FROM notes
+             WHERE LENGTH(content) BETWEEN ?1 AND ?2 AND archived_at IS NULL
+             ORDER BY LENGTH(content) DESC"
+        ))?;
+
+        let min = i64::try_from(min).unwrap_or(i64::MAX);
+        let max = i64::try_from(max).unwrap_or(i64::MAX);
         let notes = query
-            .query_map([], |row| {
-                Ok(Note {
-                    id: row.get(0)?,
+            .query_map(params![min, max], note_from_row)?
+            .collect::<rusqlite::Result<Vec<Note>>>()?;
+
+        Ok(notes)
+    }
+
+    /// Inserts every record that isn't already stored verbatim, in a single transaction.
+    /// Returns how many notes were added.
+    pub fn import_notes(&self, records: &[NoteRecord]) -> Result<usize> {
+        let conn = self.connection()?;
+        let tx = conn.unchecked_transaction()?;
+        let mut imported = 0;
+
+        for record in records {
+            let exists: bool = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM notes WHERE title = ?1 AND content = ?2)",
+                params![
+                    search::normalize(&record.title),
+                    search::normalize(&record.content)
+                ],
+                |row| row.get(0),
+            )?;
+
+            if !exists {
+                insert_note(&tx, &record.title, &record.content, record.created_at, None)?;
+                imported += 1;
+            }
+        }
+
+        tx.commit()?;
+        Ok(imported)
+    }
+
+    /// How many times note `id` was updated in the last `days` days.
+    pub fn get_note_update_frequency(&self, id: i64, days: u32) -> Result<u32> {
+        let cutoff = Utc::now() - chrono::Duration::days(days.into());
+        let conn = self.connection()?;
+        let updates = conn.query_row(
+            "SELECT COUNT(*) FROM note_history WHERE note_id = ?1 AND saved_at >= ?2",
+            params![id, utc_timestamp(cutoff)],
+            |row| row.get(0),
+        )?;
+
+        Ok(updates)
+    }
+
+    /// A value the app keeps between sessions, such as the recently viewed notes.
+    pub fn get_state(&self, key: &str) -> Result<Option<String>> {
+        let conn = self.connection()?;
+        let value = conn
+            .query_row(
+                "SELECT value FROM app_state WHERE key = ?1",
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(value)
+    }
+
+    pub fn set_state(&self, key: &str, value: &str) -> Result<()> {
+        let conn = self.connection()?;
+        conn.execute(
+            "INSERT INTO app_state (key, value) VALUES (?1, ?2)
+             ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+            params![key, value],
+        )?;
+
+        Ok(())
+    }
+
+    /// Remembers `line` of the note under `name`, replacing any mark of that name.
+    pub fn set_mark(&self, note_id: i64, name: char, line: usize) -> Result<()> {
+        let conn = self.connection()?;
+        conn.execute(
+            "INSERT INTO marks (note_id, name, line) VALUES (?1, ?2, ?3)
+             ON CONFLICT (note_id, name) DO UPDATE SET line = excluded.line",
+            params![note_id, name.to_string(), line as i64],
+        )?;
+
+        Ok(())
+    }
+
+    /// Earlier versions of the note, most recent first. Those of merged notes are interleaved.
+    pub fn get_note_history(&self, note_id: i64) -> Result<Vec<Revision>> {
+        let conn = self.connection()?;
+        let mut query = conn.prepare(
+            "SELECT title, content, saved_at, merged_from FROM note_history
+             WHERE note_id = ?1 ORDER BY saved_at DESC, id DESC",
+        )?;
+        let revisions = query
+            .query_map(params![note_id], |row| {
+                Ok(Revision {
+                    title: row.get(0)?,
+                    content: row.get(1)?,
+                    saved_at: row.get(2)?,
+                    merged_from: row.get(3)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<Revision>>>()?;
+
+        Ok(revisions)
+    }
+
+    pub fn get_marks(&self, note_id: i64) -> Result<Vec<Mark>> {
+        let conn = self.connection()?;
+        let mut query =
+            conn.prepare("SELECT name, line FROM marks WHERE note_id = ?1 ORDER BY name")?;
+        let marks = query
+            .query_map(params![note_id], |row| {
+                let name: String = row.get(0)?;
+                let line: i64 = row.get(1)?;
+                Ok(Mark {
+                    name: name.chars().next().unwrap_or('?'),
+                    line: line.max(0) as usize,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<Mark>>>()?;
+
+        Ok(marks)
+    }
+
+    pub fn save_draft(&self, note_id: i64, title: &str, content: &str) -> Result<()> {
+        let conn = self.connection()?;
+        conn.execute(
+            "INSERT INTO drafts (note_id, title, content, saved_at)
+             VALUES (?1, ?2, ?3, unixepoch())
+             ON CONFLICT (note_id) DO UPDATE SET
+                title = excluded.title,
+                content = excluded.content,
+                saved_at = excluded.saved_at",
+            params![note_id, title, content],
+        )?;
+
+        Ok(())
+    }
+
+    /// Returns drafts of unsaved new notes, and drafts whose note still exists and differs from
+    /// what was left in the form.
+    pub fn get_drafts(&self) -> Result<Vec<Draft>> {
+        let conn = self.connection()?;
+        let mut query = conn.prepare(
+            "SELECT drafts.note_id, drafts.title, drafts.content FROM drafts
+             LEFT JOIN notes ON notes.id = drafts.note_id
+             WHERE drafts.note_id = ?1
+                OR drafts.title != notes.title
+                OR drafts.content != notes.content
+             ORDER BY drafts.saved_at",
+        )?;
+
+        let drafts = query
+            .query_map(params![NEW_NOTE_DRAFT_ID], |row| {
+                Ok(Draft {
+                    note_id: row.get(0)?,
                     title: row.get(1)?,
                     content: row.get(2)?,
                 })
             })?
-            .collect::<Result<Vec<Note>>>()?;
+            .collect::<rusqlite::Result<Vec<Draft>>>()?;
 
-        Ok(notes)
+        Ok(drafts)
+    }
+
+    pub fn delete_draft(&self, note_id: i64) -> Result<()> {
+        let conn = self.connection()?;
+        conn.execute("DELETE FROM drafts WHERE note_id = ?1", params![note_id])?;
+
+        Ok(())
+    }
+
+    pub fn delete_all_drafts(&self) -> Result<()> {
+        let conn = self.connection()?;
+        conn.execute("DELETE FROM drafts", [])?;
+
+        Ok(())
+    }
+}
+
+/// Timestamps used to be written by `datetime('now')`, which is UTC but doesn't say so. They're
+/// rewritten as RFC 3339 so nothing can mistake them for local time. Columns added by
+/// `add_timestamp_columns` have no default, so rows inserted by other tools are backfilled too.
+fn mark_timestamps_utc(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "UPDATE notes SET
+            created_at = replace(created_at, ' ', 'T') || 'Z',
+            updated_at = replace(updated_at, ' ', 'T') || 'Z'
+         WHERE created_at NOT LIKE '%Z' OR updated_at NOT LIKE '%Z';
+         UPDATE notes SET
+            created_at = COALESCE(created_at, strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+            updated_at = COALESCE(updated_at, strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+         WHERE created_at IS NULL OR updated_at IS NULL;",
+    )
+}
+
+/// Rows inserted by other tools have no position; they go after every other note, oldest first.
+fn fill_missing_positions(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE notes SET position = (SELECT COALESCE(MAX(position), 0) FROM notes) + id
+         WHERE position IS NULL",
+        [],
+    )?;
+    Ok(())
+}
+
+/// SQLite's RANDOM() can't be seeded, so the shuffle hashes the id, XORs in the seed (SQLite
+/// has no XOR operator) and hashes again. Values stay below 2^31 so the arithmetic never
+/// overflows to REAL.
+const SHUFFLE_HASH: &str = "id % 2147483648 * 1103515245 % 2147483648";
+
+/// `ORDER BY` terms listing notes in `order`. The random order reads a `hash` column of
+/// `SHUFFLE_HASH` and the seed from parameter `?1`.
+fn order_by(order: SortOrder) -> String {
+    match order {
+        SortOrder::Created => "position, id".to_string(),
+        SortOrder::Updated => "updated_at DESC, id DESC".to_string(),
+        SortOrder::Title => format!("title COLLATE {TITLE_COLLATION}, id"),
+        SortOrder::Random => {
+            "((hash | ?1) - (hash & ?1)) * 1103515245 % 2147483648, id".to_string()
+        }
+    }
+}
+
+/// `query` as an FTS5 expression: each word quoted, so that typed punctuation and words like
+/// `OR` are searched for rather than read as syntax, and the last one matched as a prefix.
+fn full_text_query(query: &str) -> Option<String> {
+    let words = query
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>();
+    if words.is_empty() {
+        return None;
+    }
+    Some(format!("{}*", words.join(" ")))
+}
+
+fn refresh_content_hashes(conn: &Connection) -> rusqlite::Result<()> {
+    let transaction = conn.unchecked_transaction()?;
+    let stale = transaction
+        .prepare("SELECT id, content FROM notes WHERE content_hash IS NULL")?
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for (id, content) in stale {
+        transaction.execute(
+            "UPDATE notes SET content_hash = ?1 WHERE id = ?2",
+            params![content_hash(&content), id],
+        )?;
+    }
+    transaction.commit()
+}
+
+fn content_hash(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+/// The first line of `content` with any text, without its Markdown heading marker, cut to
+/// `AUTO_TITLE_CHARS`. Empty when the content is.
+pub fn title_from_content(content: &str) -> String {
+    let line = content
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    // `#tag` at the start of a line is a tag rather than a heading.
+    let line = match line.trim_start_matches('#') {
+        heading if heading.len() < line.len() && heading.starts_with(' ') => heading.trim_start(),
+        _ => line,
+    };
+    line.chars()
+        .take(AUTO_TITLE_CHARS)
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+pub fn merged_content(target: &Note, source: &Note) -> String {
+    format!("{}{MERGE_SEPARATOR}{}", target.content, source.content)
+}
+
+/// Formats like `strftime('%Y-%m-%dT%H:%M:%SZ')`, so stored dates compare and sort as text.
+fn utc_timestamp(at: DateTime<Utc>) -> String {
+    at.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+fn insert_note(
+    conn: &Connection,
+    title: &str,
+    content: &str,
+    created_at: Option<DateTime<Utc>>,
+    source_url: Option<&str>,
+) -> rusqlite::Result<Note> {
+    conn.query_row(
+        &format!(
+            "INSERT INTO notes (title, content, created_at, updated_at, source_url, position)
+             VALUES (
+                ?1,
+                ?2,
+                COALESCE(?3, strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+                strftime('%Y-%m-%dT%H:%M:%SZ', 'now'),
+                ?4,
+                (SELECT COALESCE(MAX(position), 0) + 1 FROM notes)
+             )
+             RETURNING {NOTE_COLUMNS}"
+        ),
+        params![
+            search::normalize(title),
+            search::normalize(content),
+            created_at.map(utc_timestamp),
+            source_url
+        ],
+        note_from_row,
+    )
+}
+
+/// Saving a note unchanged writes nothing: `updated_at` and the history stay as they were and
+/// the stored note is returned with `false`.
+fn update_note(
+    conn: &Connection,
+    id: i64,
+    title: &str,
+    content: &str,
+) -> rusqlite::Result<(Note, bool)> {
+    let (title, content) = (search::normalize(title), search::normalize(content));
+    let existing = conn
+        .query_row(
+            &format!("SELECT {NOTE_COLUMNS} FROM notes WHERE id = ?1"),
+            params![id],
+            note_from_row,
+        )
+        .optional()?;
+    if let Some(note) = existing
+        && note.title == title
+        && note.content == content
+    {
+        return Ok((note, false));
+    }
+
+    record_history(conn, id)?;
+    let note = conn.query_row(
+        &format!(
+            "UPDATE notes
+             SET title = ?1, content = ?2, updated_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now')
+             WHERE id = ?3
+             RETURNING {NOTE_COLUMNS}"
+        ),
+        params![title, content, id],
+        note_from_row,
+    )?;
+    Ok((note, true))
+}
+
+/// Copies the notes listed in `temp.moving` from schema `from` to `to`, ids, tags, history and
+/// marks included, then deletes them from `from`. Returns how many moved.
+fn move_notes(conn: &Connection, from: &str, to: &str) -> rusqlite::Result<usize> {
+    conn.execute_batch(&format!(
+        "INSERT INTO {to}.notes
+         (id, title, content, created_at, updated_at, archived_at, color, source_url, position)
+         SELECT id, title, content, created_at, updated_at, archived_at, color, source_url, position
+         FROM {from}.notes WHERE id IN (SELECT id FROM temp.moving);
+
+         -- Tag ids differ between the files, so tags are matched by name.
+         INSERT OR IGNORE INTO {to}.tags (name)
+         SELECT DISTINCT tag.name FROM {from}.tags AS tag
+         JOIN {from}.note_tags AS note_tag ON note_tag.tag_id = tag.id
+         WHERE note_tag.note_id IN (SELECT id FROM temp.moving);
+         INSERT INTO {to}.note_tags (note_id, tag_id)
+         SELECT note_tag.note_id, to_tag.id FROM {from}.note_tags AS note_tag
+         JOIN {from}.tags AS tag ON tag.id = note_tag.tag_id
+         JOIN {to}.tags AS to_tag ON to_tag.name = tag.name
+         WHERE note_tag.note_id IN (SELECT id FROM temp.moving);
+
+         INSERT INTO {to}.note_history (note_id, title, content, saved_at, merged_from)
+         SELECT note_id, title, content, saved_at, merged_from FROM {from}.note_history
+         WHERE note_id IN (SELECT id FROM temp.moving) ORDER BY id;
+         INSERT INTO {to}.marks (note_id, name, line)
+         SELECT note_id, name, line FROM {from}.marks
+         WHERE note_id IN (SELECT id FROM temp.moving);
+
+         DELETE FROM {from}.drafts WHERE note_id IN (SELECT id FROM temp.moving);"
+    ))?;
+    // Tags, history and marks follow through `ON DELETE CASCADE`.
+    conn.execute(
+        &format!("DELETE FROM {from}.notes WHERE id IN (SELECT id FROM temp.moving)"),
+        [],
+    )
+}
+
+/// Gives the versions of notes `id_a` and `id_b` to `merged_id`, each labelled with the title of
+/// the note it was saved from. Versions already labelled by an earlier merge keep their label.
+fn merge_note_history(
+    conn: &Connection,
+    id_a: i64,
+    id_b: i64,
+    merged_id: i64,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE note_history
+         SET merged_from = COALESCE(
+                 merged_from,
+                 (SELECT title FROM notes WHERE notes.id = note_history.note_id)
+             ),
+             note_id = ?1
+         WHERE note_id = ?2 OR note_id = ?3",
+        params![merged_id, id_a, id_b],
+    )?;
+    Ok(())
+}
+
+/// Keeps the note as it is before an update overwrites it.
+fn record_history(conn: &Connection, id: i64) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO note_history (note_id, title, content)
+         SELECT id, title, content FROM notes WHERE id = ?1",
+        params![id],
+    )?;
+    Ok(())
+}
+
+/// Returns whether the note didn't have the tag yet.
+fn tag_note(conn: &Connection, note_id: i64, tag: &str) -> rusqlite::Result<bool> {
+    let tag = search::normalize(tag);
+    conn.execute(
+        "INSERT OR IGNORE INTO tags (name) VALUES (?1)",
+        params![tag],
+    )?;
+    let tag_id: i64 =
+        conn.query_row("SELECT id FROM tags WHERE name = ?1", params![tag], |row| {
+            row.get(0)
+        })?;
+    let added = conn.execute(
+        "INSERT OR IGNORE INTO note_tags (note_id, tag_id) VALUES (?1, ?2)",
+        params![note_id, tag_id],
+    )?;
+    Ok(added > 0)
+}
+
+/// Returns whether the note had the tag. A tag no note uses anymore is deleted.
+fn untag_note(conn: &Connection, note_id: i64, tag: &str) -> rusqlite::Result<bool> {
+    let tag = search::normalize(tag);
+    let removed = conn.execute(
+        "DELETE FROM note_tags
+         WHERE note_id = ?1 AND tag_id = (SELECT id FROM tags WHERE name = ?2)",
+        params![note_id, tag],
+    )?;
+    conn.execute(
+        "DELETE FROM tags
+         WHERE name = ?1 AND NOT EXISTS (SELECT 1 FROM note_tags WHERE tag_id = tags.id)",
+        params![tag],
+    )?;
+    Ok(removed > 0)
+}
+
+fn note_from_row(row: &rusqlite::Row) -> rusqlite::Result<Note> {
+    Ok(Note {
+        id: row.get("id")?,
+        title: row.get("title")?,
+        content: row.get("content")?,
+        created_at: row.get("created_at")?,
+        updated_at: row.get("updated_at")?,
+        tags: row
+            .get::<_, Option<String>>("tag_names")?
+            .map(|names| names.split('\u{1f}').map(str::to_string).collect())
+            .unwrap_or_default(),
+        color: NoteColor::from_name(row.get::<_, Option<String>>("color")?.as_deref()),
+        source_url: row.get("source_url")?,
+        position: row.get::<_, Option<i64>>("position")?.unwrap_or_default(),
+        archived_at: row.get("archived_at")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{TempDir, database};
+
+    fn add(db: &Database, title: &str) -> Note {
+        db.add_note_with_created_at(title, "", None).unwrap()
+    }
+
+    fn titles(notes: &[Note]) -> Vec<&str> {
+        notes.iter().map(|note| note.title.as_str()).collect()
+    }
+
+    fn sorted(db: &Database, order: SortOrder, seed: u64) -> Arc<[Note]> {
+        db.get_all_notes_sorted(order, seed).unwrap()
+    }
+
+    #[test]
+    fn title_order_ignores_accents_and_puts_lowercase_first() {
+        let dir = TempDir::new();
+        let db = database(&dir);
+        for title in [
+            "Zebra", "étude", "Banana", "Äpfel", "banana", "Énergie", "apple", "Ölkanne",
+        ] {
+            add(&db, title);
+        }
+
+        assert_eq!(
+            titles(&sorted(&db, SortOrder::Title, 0)),
+            [
+                "Äpfel", "apple", "banana", "Banana", "Énergie", "étude", "Ölkanne", "Zebra"
+            ]
+        );
+    }
+
+    #[test]
+    fn created_order_follows_positions() {
+        let dir = TempDir::new();
+        let db = database(&dir);
+        let first = add(&db, "first");
+        add(&db, "second");
+        let third = add(&db, "third");
+        assert_eq!(
+            titles(&sorted(&db, SortOrder::Created, 0)),
+            ["first", "second", "third"]
+        );
+
+        db.swap_positions(first.id, third.id).unwrap();
+        assert_eq!(
+            titles(&sorted(&db, SortOrder::Created, 0)),
+            ["third", "second", "first"]
+        );
+    }
+
+    #[test]
+    fn updated_order_puts_recent_edits_first() {
+        let dir = TempDir::new();
+        let db = database(&dir);
+        for (title, updated_at) in [
+            ("old", "2026-01-01T00:00:00Z"),
+            ("recent", "2026-03-01T00:00:00Z"),
+            ("tied", "2026-01-01T00:00:00Z"),
+        ] {
+            let note = add(&db, title);
+            db.connection()
+                .unwrap()
+                .execute(
+                    "UPDATE notes SET updated_at = ?1 WHERE id = ?2",
+                    params![updated_at, note.id],
+                )
+                .unwrap();
+        }
+
+        // Ties go to the newer note.
+        assert_eq!(
+            titles(&sorted(&db, SortOrder::Updated, 0)),
+            ["recent", "tied", "old"]
+        );
+    }
+
+    #[test]
+    fn siblings_are_the_neighbours_in_every_order() {
+        let dir = TempDir::new();
+        let db = database(&dir);
+        for title in ["b", "c", "a", "d"] {
+            add(&db, title);
+        }
+        let archived = add(&db, "archived");
+        db.set_archived(archived.id, true).unwrap();
+
+        for order in [
+            SortOrder::Created,
+            SortOrder::Updated,
+            SortOrder::Title,
+            SortOrder::Random,
+        ] {
+            let notes = sorted(&db, order, 7);
+            for (i, note) in notes.iter().enumerate() {
+                let (prev, next) = db.get_note_siblings(note.id, order, 7).unwrap();
+                let id = |note: Option<&Note>| note.map(|note| note.id);
+                let label = order.label();
+                assert_eq!(
+                    id(prev.as_ref()),
+                    id(i.checked_sub(1).map(|i| &notes[i])),
+                    "{label}"
+                );
+                assert_eq!(id(next.as_ref()), id(notes.get(i + 1)), "{label}");
+            }
+        }
+    }
+
+    #[test]
+    fn random_order_depends_only_on_the_seed() {
+        let dir = TempDir::new();
+        let db = database(&dir);
+        for i in 0..20 {
+            add(&db, &format!("note {i}"));
+        }
+
+        let shuffled = sorted(&db, SortOrder::Random, 42);
+        assert_eq!(
+            titles(&shuffled),
+            titles(&sorted(&db, SortOrder::Random, 42))
+        );
+        assert_ne!(
+            titles(&shuffled),
+            titles(&sorted(&db, SortOrder::Created, 0))
+        );
+        let mut ids = shuffled.iter().map(|note| note.id).collect::<Vec<_>>();
+        ids.sort();
+        let created = sorted(&db, SortOrder::Created, 0);
+        assert_eq!(ids, created.iter().map(|note| note.id).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn saving_an_unchanged_note_writes_nothing() {
+        let dir = TempDir::new();
+        let db = database(&dir);
+        let note = db
+            .add_note_with_created_at(
+                "Title",
+                "café",
+                Some(Utc::now() - chrono::Duration::days(1)),
+            )
+            .unwrap();
+        let stored = db.get_note(note.id).unwrap().unwrap();
+
+        // Decomposed, but the same text once normalized.
+        let (unchanged, written) = db.update_note(note.id, "Title", "cafe\u{301}").unwrap();
+        assert!(!written);
+        assert_eq!(unchanged.updated_at, stored.updated_at);
+        assert!(db.get_note_history(note.id).unwrap().is_empty());
+
+        let (_, written) = db.update_note(note.id, "Title", "tea").unwrap();
+        assert!(written);
+        assert_eq!(db.get_note_history(note.id).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn undoing_an_archive_run_skips_notes_archived_since() {
+        let dir = TempDir::new();
+        let db = database(&dir);
+        let old = add(&db, "old");
+        let older = add(&db, "older");
+        // Every note is older than a cutoff in the future.
+        let cutoff = Utc::now() + chrono::Duration::days(1);
+        assert_eq!(db.archive_notes_not_updated_since(cutoff).unwrap(), 2);
+
+        let recent = add(&db, "recent");
+        db.set_archived(recent.id, true).unwrap();
+
+        assert_eq!(db.unarchive_last_batch().unwrap(), 2);
+        let archived = |id| db.get_note(id).unwrap().unwrap().archived_at.is_some();
+        assert!(!archived(old.id));
+        assert!(!archived(older.id));
+        assert!(archived(recent.id));
+        assert_eq!(db.unarchive_last_batch().unwrap(), 0);
+    }
+
+    #[test]
+    fn splitting_replaces_the_note_with_two_tagged_halves() {
+        let dir = TempDir::new();
+        let db = database(&dir);
+        let note = db
+            .add_note_with_created_at("Plans", "Monday\ngym\n\n# Tuesday\nswim", None)
+            .unwrap();
+        db.add_tag_to_note(note.id, "week").unwrap();
+        db.update_note(note.id, "Plans", "Monday\ngym\n\n# Tuesday\nswim\ncook")
+            .unwrap();
+
+        let (top, bottom) = db.split_note(note.id, 3).unwrap();
+        assert!(db.get_note(note.id).unwrap().is_none());
+        assert_eq!(
+            (top.title.as_str(), top.content.as_str()),
+            ("Plans", "Monday\ngym\n")
+        );
+        assert_eq!(
+            (bottom.title.as_str(), bottom.content.as_str()),
+            ("Tuesday", "# Tuesday\nswim\ncook")
+        );
+        assert_eq!(top.tags, ["week"]);
+        assert_eq!(bottom.tags, ["week"]);
+        assert_eq!(db.get_note_history(top.id).unwrap().len(), 1);
     }
 }
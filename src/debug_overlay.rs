@@ -0,0 +1,46 @@
+use std::time::{Duration, Instant};
+
+/// Frame timing shown on the top line with `F12`; only built with `--features debug-overlay`.
+pub struct DebugOverlay {
+    pub visible: bool,
+    pub last_frame: Duration,
+    events_per_second: u32,
+    events: u32,
+    counting_since: Instant,
+}
+
+impl DebugOverlay {
+    pub fn new() -> DebugOverlay {
+        DebugOverlay {
+            visible: false,
+            last_frame: Duration::ZERO,
+            events_per_second: 0,
+            events: 0,
+            counting_since: Instant::now(),
+        }
+    }
+
+    pub fn count_event(&mut self) {
+        self.events += 1;
+    }
+
+    /// Closes the current one-second window once it's over. Returns whether the rate changed.
+    pub fn roll_window(&mut self) -> bool {
+        if self.counting_since.elapsed() < Duration::from_secs(1) {
+            return false;
+        }
+        let previous = self.events_per_second;
+        self.events_per_second = self.events;
+        self.events = 0;
+        self.counting_since = Instant::now();
+        previous != self.events_per_second
+    }
+
+    pub fn line(&self, notes: usize) -> String {
+        format!(
+            "Frame: {}µs | Events: {}/s | Notes: {notes}",
+            self.last_frame.as_micros(),
+            self.events_per_second
+        )
+    }
+}
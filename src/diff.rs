@@ -0,0 +1,26 @@
+use std::time::Duration;
+
+use similar::{ChangeTag, TextDiff};
+
+/// Past this the diff settles for a coarser result instead of keeping the UI waiting.
+const DIFF_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// A line of a unified diff, without its line break.
+pub struct DiffLine {
+    pub tag: ChangeTag,
+    pub text: String,
+}
+
+/// Every line of `old` and `new` in order, unchanged ones included, so that a view can scroll
+/// through the whole note and only draw the rows on screen.
+pub fn line_diff(old: &str, new: &str) -> Vec<DiffLine> {
+    TextDiff::configure()
+        .timeout(DIFF_TIMEOUT)
+        .diff_lines(old, new)
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            tag: change.tag(),
+            text: change.value().trim_end_matches(['\n', '\r']).to_string(),
+        })
+        .collect()
+}
@@ -0,0 +1,141 @@
+use tui_input::Input;
+
+/// `(shortcode, emoji)`. Only single code points with a default emoji presentation are listed,
+/// so every glyph is two columns wide in any terminal.
+const EMOJI: &[(&str, &str)] = &[
+    ("grinning", "😀"),
+    ("smile", "😄"),
+    ("joy", "😂"),
+    ("wink", "😉"),
+    ("blush", "😊"),
+    ("heart_eyes", "😍"),
+    ("thinking", "🤔"),
+    ("neutral_face", "😐"),
+    ("sweat_smile", "😅"),
+    ("sob", "😭"),
+    ("angry", "😠"),
+    ("sunglasses", "😎"),
+    ("scream", "😱"),
+    ("sleeping", "😴"),
+    ("partying_face", "🥳"),
+    ("thumbsup", "👍"),
+    ("thumbsdown", "👎"),
+    ("clap", "👏"),
+    ("wave", "👋"),
+    ("pray", "🙏"),
+    ("muscle", "💪"),
+    ("eyes", "👀"),
+    ("brain", "🧠"),
+    ("sparkling_heart", "💖"),
+    ("broken_heart", "💔"),
+    ("fire", "🔥"),
+    ("sparkles", "✨"),
+    ("star", "⭐"),
+    ("tada", "🎉"),
+    ("gift", "🎁"),
+    ("rocket", "🚀"),
+    ("zap", "⚡"),
+    ("boom", "💥"),
+    ("bulb", "💡"),
+    ("memo", "📝"),
+    ("book", "📖"),
+    ("books", "📚"),
+    ("bookmark", "🔖"),
+    ("paperclip", "📎"),
+    ("pushpin", "📌"),
+    ("calendar", "📅"),
+    ("clipboard", "📋"),
+    ("file_folder", "📁"),
+    ("package", "📦"),
+    ("email", "📧"),
+    ("phone", "📱"),
+    ("computer", "💻"),
+    ("mag", "🔍"),
+    ("lock", "🔒"),
+    ("unlock", "🔓"),
+    ("key", "🔑"),
+    ("hammer", "🔨"),
+    ("wrench", "🔧"),
+    ("nut_and_bolt", "🔩"),
+    ("bug", "🐛"),
+    ("white_check_mark", "✅"),
+    ("x", "❌"),
+    ("construction", "🚧"),
+    ("question", "❓"),
+    ("exclamation", "❗"),
+    ("no_entry", "⛔"),
+    ("hourglass", "⌛"),
+    ("alarm_clock", "⏰"),
+    ("moneybag", "💰"),
+    ("chart", "📈"),
+    ("trophy", "🏆"),
+    ("dart", "🎯"),
+    ("coffee", "☕"),
+    ("pizza", "🍕"),
+    ("apple", "🍎"),
+    ("cake", "🍰"),
+    ("beer", "🍺"),
+    ("sun_with_face", "🌞"),
+    ("umbrella", "☔"),
+    ("snowman", "⛄"),
+    ("rainbow", "🌈"),
+    ("earth", "🌍"),
+    ("house", "🏠"),
+    ("car", "🚗"),
+    ("airplane_departure", "🛫"),
+    ("cat", "🐱"),
+    ("dog", "🐶"),
+    ("seedling", "🌱"),
+    ("tree", "🌳"),
+    ("rose", "🌹"),
+    ("music", "🎵"),
+    ("art", "🎨"),
+    ("video_game", "🎮"),
+    ("soccer", "⚽"),
+    ("100", "💯"),
+];
+
+/// The popup opened from the content editor: a filter over `EMOJI`, with the emoji picked
+/// this session listed first.
+#[derive(Default)]
+pub struct EmojiPicker {
+    pub input: Input,
+    pub selected: usize,
+    /// Most recent first.
+    recent: Vec<&'static str>,
+}
+
+impl EmojiPicker {
+    /// Clears the filter for a new pick; the recently used emoji are kept.
+    pub fn open(&mut self) {
+        self.input.reset();
+        self.selected = 0;
+    }
+
+    /// `(shortcode, emoji)` of every entry whose shortcode contains the filter, which may be
+    /// typed with its leading `:`.
+    pub fn matches(&self) -> Vec<(&'static str, &'static str)> {
+        let query = self.input.value().trim_start_matches(':').to_lowercase();
+        let mut matches = EMOJI
+            .iter()
+            .copied()
+            .filter(|(name, _)| name.contains(&query))
+            .collect::<Vec<_>>();
+        // Stable, so the rest keep the table order.
+        matches.sort_by_key(|(_, emoji)| {
+            self.recent
+                .iter()
+                .position(|recent| recent == emoji)
+                .unwrap_or(usize::MAX)
+        });
+        matches
+    }
+
+    /// The selected emoji, remembered as the most recently used.
+    pub fn pick(&mut self) -> Option<&'static str> {
+        let (_, emoji) = *self.matches().get(self.selected)?;
+        self.recent.retain(|recent| *recent != emoji);
+        self.recent.insert(0, emoji);
+        Some(emoji)
+    }
+}
@@ -0,0 +1,30 @@
+/// Scores `text` against `query` when every character of the query appears in it in order,
+/// ignoring case. Runs of consecutive characters and matches at the start of a word score
+/// higher, so `mn` ranks "meeting notes" above "common". An empty query matches with 0.
+pub fn score(query: &str, text: &str) -> Option<u32> {
+    let mut query = query.chars().flat_map(char::to_lowercase).peekable();
+    let mut score = 0;
+    let mut previous: Option<char> = None;
+    let mut previous_matched = false;
+
+    for c in text.chars().flat_map(char::to_lowercase) {
+        let Some(&wanted) = query.peek() else {
+            break;
+        };
+        let matched = c == wanted;
+        if matched {
+            query.next();
+            score += 1;
+            if previous_matched {
+                score += 4;
+            }
+            if previous.is_none_or(|previous| !previous.is_alphanumeric()) {
+                score += 3;
+            }
+        }
+        previous_matched = matched;
+        previous = Some(c);
+    }
+
+    query.peek().is_none().then_some(score)
+}
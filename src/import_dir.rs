@@ -0,0 +1,45 @@
+use std::{fs, path::Path};
+
+use color_eyre::eyre::{Result, WrapErr};
+
+use crate::db::Database;
+
+/// Files picked up by `import-dir`; anything else in the directory is left alone.
+const EXTENSIONS: &[&str] = &["md", "txt"];
+
+/// `ratata-notes import-dir DIR`: adds every Markdown and text file directly in `dir` as a note
+/// titled after the file name, skipping files whose content is already stored in a note, so
+/// running it twice on the same folder imports nothing new.
+pub fn run(db: &Database, dir: &Path) -> Result<()> {
+    let mut paths = fs::read_dir(dir)
+        .wrap_err_with(|| format!("Can't read {}", dir.display()))?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.retain(|path| {
+        path.is_file()
+            && path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| EXTENSIONS.contains(&extension))
+    });
+    paths.sort();
+
+    let (mut imported, mut skipped) = (0, 0);
+    for path in paths {
+        if let Some(existing) = db.get_notes_with_content_matching_file(&path)?.first() {
+            println!(
+                "{}: already stored as \"{}\"",
+                path.display(),
+                existing.title
+            );
+            skipped += 1;
+            continue;
+        }
+        let title = path.file_stem().unwrap_or_default().to_string_lossy();
+        db.add_note_with_created_at(&title, &fs::read_to_string(&path)?, None)?;
+        imported += 1;
+    }
+
+    println!("Imported {imported} notes, skipped {skipped} already stored");
+    Ok(())
+}
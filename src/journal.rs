@@ -0,0 +1,43 @@
+use chrono::NaiveTime;
+use serde::Deserialize;
+
+use crate::{
+    db::{Database, DbError, NoteOrDuplicate},
+    models::Note,
+};
+
+/// `[journal]` in the config file.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct JournalConfig {
+    /// Title of the note entries are appended to, created on the first entry.
+    pub title: String,
+}
+
+impl Default for JournalConfig {
+    fn default() -> Self {
+        JournalConfig {
+            title: "Journal".to_string(),
+        }
+    }
+}
+
+/// Appends `- HH:MM text` to the journal note. The append is a single statement, so entries
+/// written by the CLI while the TUI is open are never lost. `true` when the note was created.
+pub fn append(
+    db: &Database,
+    config: &JournalConfig,
+    text: &str,
+    at: NaiveTime,
+) -> Result<(Note, bool), DbError> {
+    let (note, created) = match db.get_note_by_title(&config.title)? {
+        Some(note) => (note, false),
+        None => match db.add_note(&config.title, "", false)? {
+            NoteOrDuplicate::Note(note) => (note, true),
+            NoteOrDuplicate::Duplicate { existing } => (existing, false),
+        },
+    };
+
+    let entry = format!("- {} {}", at.format("%H:%M"), text.trim());
+    Ok((db.append_to_note(note.id, &entry)?, created))
+}
@@ -0,0 +1,421 @@
+use std::{collections::HashMap, fmt};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct KeymapConfig {
+    /// Key substituted for `leader` in sequences, e.g. `"leader d"`.
+    pub leader: String,
+    pub list: HashMap<String, KeySequences>,
+    pub form: HashMap<String, KeySequences>,
+}
+
+impl Default for KeymapConfig {
+    fn default() -> Self {
+        KeymapConfig {
+            leader: "space".to_string(),
+            list: HashMap::new(),
+            form: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum KeySequences {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl KeySequences {
+    fn as_slice(&self) -> Vec<&str> {
+        match self {
+            KeySequences::One(sequence) => vec![sequence.as_str()],
+            KeySequences::Many(sequences) => sequences.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyPress {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyPress {
+    pub fn from_event(key: KeyEvent) -> KeyPress {
+        let mut modifiers = key.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT);
+        if !matches!(key.code, KeyCode::Char(_)) {
+            modifiers |= key.modifiers & KeyModifiers::SHIFT;
+        }
+        KeyPress {
+            code: key.code,
+            modifiers,
+        }
+    }
+
+    fn parse(token: &str, leader: Option<KeyPress>) -> Result<KeyPress, String> {
+        if token == "leader" {
+            return leader.ok_or_else(|| "the leader key can't itself be \"leader\"".to_string());
+        }
+
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = token;
+        loop {
+            if let Some(stripped) = rest.strip_prefix("ctrl-") {
+                modifiers |= KeyModifiers::CONTROL;
+                rest = stripped;
+            } else if let Some(stripped) = rest.strip_prefix("alt-") {
+                modifiers |= KeyModifiers::ALT;
+                rest = stripped;
+            } else if let Some(stripped) = rest.strip_prefix("shift-") {
+                modifiers |= KeyModifiers::SHIFT;
+                rest = stripped;
+            } else {
+                break;
+            }
+        }
+
+        let code = match rest {
+            c if modifiers.contains(KeyModifiers::SHIFT) && c.chars().count() == 1 => {
+                KeyCode::Char(c.chars().next().unwrap().to_ascii_uppercase())
+            }
+            "space" => KeyCode::Char(' '),
+            "enter" => KeyCode::Enter,
+            "esc" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backspace" => KeyCode::Backspace,
+            "delete" => KeyCode::Delete,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            f if f.len() > 1 && f.starts_with('f') && f[1..].parse::<u8>().is_ok() => {
+                KeyCode::F(f[1..].parse().unwrap())
+            }
+            c if c.chars().count() == 1 => KeyCode::Char(c.chars().next().unwrap()),
+            _ => return Err(format!("unknown key \"{token}\"")),
+        };
+
+        Ok(KeyPress::from_event(KeyEvent::new(code, modifiers)))
+    }
+}
+
+impl fmt::Display for KeyPress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            f.write_str("Ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            f.write_str("Alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            f.write_str("Shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("Space"),
+            KeyCode::Char(c) if self.modifiers.contains(KeyModifiers::CONTROL) => {
+                write!(f, "{}", c.to_ascii_uppercase())
+            }
+            KeyCode::Char(c) => write!(f, "{c}"),
+            KeyCode::F(n) => write!(f, "F{n}"),
+            KeyCode::PageUp => f.write_str("PgUp"),
+            KeyCode::PageDown => f.write_str("PgDn"),
+            code => write!(f, "{code}"),
+        }
+    }
+}
+
+/// An action that can be bound to keys on one screen.
+pub trait Command: Copy + PartialEq + 'static {
+    /// Every command with its config name and default key sequences.
+    const ALL: &'static [(Self, &'static str, &'static [&'static str])];
+
+    fn name(self) -> &'static str {
+        Self::ALL
+            .iter()
+            .find(|(command, _, _)| *command == self)
+            .map_or("?", |(_, name, _)| name)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ListCommand {
+    Quit,
+    MoveUp,
+    MoveDown,
+    PageUp,
+    PageDown,
+    FirstNote,
+    LastNote,
+    SelectNote,
+    AddNote,
+    DailyNote,
+    JournalEntry,
+    DeleteNote,
+    ExportArchive,
+    ImportArchive,
+    ExportZip,
+    ImportZip,
+    ExportPdf,
+    AdvancedSearch,
+    Filter,
+    TextSearch,
+    TagBrowser,
+    Cloud,
+    Stats,
+    RandomNote,
+    MarkMerge,
+    MergeInto,
+    ToggleMark,
+    BulkTag,
+    CopyNote,
+    PasteNote,
+    PreviewDown,
+    PreviewUp,
+    MoveNoteDown,
+    MoveNoteUp,
+    FocusNotes,
+    FocusPreview,
+    ToggleCheckbox,
+    SetMark,
+    JumpToMark,
+    ListMarks,
+    RecentNotes,
+    BrokenLinks,
+    LongNotes,
+    SplitNote,
+    History,
+    CycleSort,
+    Archive,
+    ToggleArchived,
+    Color,
+    Undo,
+    Redo,
+}
+
+impl Command for ListCommand {
+    const ALL: &'static [(Self, &'static str, &'static [&'static str])] = &[
+        (ListCommand::Quit, "quit", &["esc", "q"]),
+        (ListCommand::MoveDown, "move_down", &["j", "down", "ctrl-n"]),
+        (ListCommand::MoveUp, "move_up", &["k", "up"]),
+        (ListCommand::PageDown, "page_down", &["pagedown"]),
+        (ListCommand::PageUp, "page_up", &["pageup"]),
+        (ListCommand::FirstNote, "first_note", &["home", "ctrl-a"]),
+        (ListCommand::LastNote, "last_note", &["end"]),
+        (ListCommand::SelectNote, "select_note", &["e", "enter"]),
+        (ListCommand::AddNote, "add_note", &["a", "i"]),
+        (ListCommand::DailyNote, "daily_note", &["D"]),
+        (ListCommand::JournalEntry, "journal_entry", &["L"]),
+        (ListCommand::DeleteNote, "delete_note", &["d"]),
+        (ListCommand::ExportArchive, "export_archive", &["X"]),
+        (ListCommand::ImportArchive, "import_archive", &["I"]),
+        (ListCommand::ExportZip, "export_zip", &["Z"]),
+        (ListCommand::ImportZip, "import_zip", &["U"]),
+        (ListCommand::ExportPdf, "export_pdf", &["P"]),
+        (ListCommand::AdvancedSearch, "advanced_search", &["S"]),
+        (ListCommand::Filter, "filter", &["/"]),
+        (ListCommand::TextSearch, "text_search", &["ctrl-f"]),
+        (ListCommand::TagBrowser, "tag_browser", &["T"]),
+        (ListCommand::Cloud, "cloud", &["C"]),
+        (ListCommand::Stats, "stats", &["s"]),
+        (ListCommand::RandomNote, "random_note", &["~"]),
+        (ListCommand::MarkMerge, "mark_merge", &["m"]),
+        (ListCommand::MergeInto, "merge_into", &["M"]),
+        (ListCommand::ToggleMark, "toggle_mark", &["v"]),
+        (ListCommand::BulkTag, "bulk_tag", &["t"]),
+        (ListCommand::CopyNote, "copy_note", &["y"]),
+        (ListCommand::PasteNote, "paste_note", &["p"]),
+        (ListCommand::PreviewDown, "preview_down", &["J"]),
+        (ListCommand::PreviewUp, "preview_up", &["K"]),
+        // `J` and `K` move the preview cursor.
+        (
+            ListCommand::MoveNoteDown,
+            "move_note_down",
+            &["alt-j", "alt-down"],
+        ),
+        (
+            ListCommand::MoveNoteUp,
+            "move_note_up",
+            &["alt-k", "alt-up"],
+        ),
+        (ListCommand::FocusNotes, "focus_notes", &["h", "left"]),
+        (ListCommand::FocusPreview, "focus_preview", &["l", "right"]),
+        (ListCommand::ToggleCheckbox, "toggle_checkbox", &["x"]),
+        // `m` merges, so setting a mark on the preview cursor goes through the leader.
+        (ListCommand::SetMark, "set_mark", &["leader m"]),
+        (ListCommand::JumpToMark, "jump_to_mark", &["'"]),
+        (ListCommand::ListMarks, "list_marks", &["\""]),
+        (ListCommand::RecentNotes, "recent_notes", &["ctrl-e"]),
+        (ListCommand::BrokenLinks, "broken_links", &["B"]),
+        // `L` adds to the journal.
+        (ListCommand::LongNotes, "long_notes", &["leader l"]),
+        (ListCommand::SplitNote, "split_note", &["leader s"]),
+        (ListCommand::History, "history", &["H"]),
+        // `s` opens the statistics.
+        (ListCommand::CycleSort, "cycle_sort", &["o"]),
+        (ListCommand::Archive, "archive", &["A"]),
+        (ListCommand::ToggleArchived, "toggle_archived", &["z"]),
+        (ListCommand::Color, "color", &["c"]),
+        (ListCommand::Undo, "undo", &["u", "ctrl-z"]),
+        (ListCommand::Redo, "redo", &["ctrl-y", "ctrl-shift-z"]),
+    ];
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FormCommand {
+    Save,
+    Bold,
+    Italic,
+    EmojiPicker,
+    SplitNote,
+    PreviousDay,
+    NextDay,
+    PreviousNote,
+    NextNote,
+    SetMark,
+    JumpToMark,
+    ListMarks,
+    ToggleInput,
+    ToggleToolbar,
+    Exit,
+}
+
+impl Command for FormCommand {
+    const ALL: &'static [(Self, &'static str, &'static [&'static str])] = &[
+        (FormCommand::Save, "save", &["ctrl-s"]),
+        (FormCommand::Bold, "bold", &["ctrl-b"]),
+        // Most terminals send Ctrl+I as Tab; it only arrives on its own with the kitty protocol.
+        (FormCommand::Italic, "italic", &["ctrl-i"]),
+        (FormCommand::EmojiPicker, "emoji_picker", &["ctrl-e"]),
+        // Ctrl+Shift+S only differs from Ctrl+S with the kitty protocol.
+        (
+            FormCommand::SplitNote,
+            "split_note",
+            &["ctrl-shift-s", "ctrl-x s"],
+        ),
+        (FormCommand::PreviousDay, "previous_day", &["ctrl-x ["]),
+        (FormCommand::NextDay, "next_day", &["ctrl-x ]"]),
+        (FormCommand::PreviousNote, "previous_note", &["alt-up"]),
+        (FormCommand::NextNote, "next_note", &["alt-down"]),
+        (FormCommand::SetMark, "set_mark", &["ctrl-x m"]),
+        (FormCommand::JumpToMark, "jump_to_mark", &["ctrl-x '"]),
+        (FormCommand::ListMarks, "list_marks", &["ctrl-x \""]),
+        (FormCommand::ToggleInput, "toggle_input", &["tab"]),
+        (FormCommand::ToggleToolbar, "toggle_toolbar", &["ctrl-t"]),
+        (FormCommand::Exit, "exit", &["esc"]),
+    ];
+}
+
+pub enum Lookup<C> {
+    Matched(C),
+    /// The keys so far are the start of at least one longer sequence.
+    Pending,
+    NoMatch,
+}
+
+pub struct Bindings<C> {
+    entries: Vec<(Vec<KeyPress>, C)>,
+}
+
+impl<C: Command> Bindings<C> {
+    fn new(
+        screen: &str,
+        overrides: &HashMap<String, KeySequences>,
+        leader: KeyPress,
+    ) -> Result<Bindings<C>, String> {
+        for name in overrides.keys() {
+            if !C::ALL.iter().any(|(_, known, _)| known == name) {
+                return Err(format!("keymap.{screen}: unknown action \"{name}\""));
+            }
+        }
+
+        let mut entries: Vec<(Vec<KeyPress>, C)> = Vec::new();
+        for &(command, name, defaults) in C::ALL {
+            let sequences = match overrides.get(name) {
+                Some(sequences) => sequences.as_slice(),
+                None => defaults.to_vec(),
+            };
+
+            for sequence in sequences {
+                let keys = sequence
+                    .split_whitespace()
+                    .map(|token| KeyPress::parse(token, Some(leader)))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| format!("keymap.{screen}.{name}: {e}"))?;
+
+                if keys.is_empty() {
+                    return Err(format!("keymap.{screen}.{name}: empty key sequence"));
+                }
+
+                // A sequence that is a prefix of another would make the longer one unreachable.
+                if let Some((_, other)) = entries.iter().find(|(existing, other)| {
+                    *other != command && (existing.starts_with(&keys) || keys.starts_with(existing))
+                }) {
+                    return Err(format!(
+                        "keymap.{screen}: \"{sequence}\" conflicts between {} and {name}",
+                        other.name()
+                    ));
+                }
+
+                entries.push((keys, command));
+            }
+        }
+
+        Ok(Bindings { entries })
+    }
+
+    pub fn lookup(&self, keys: &[KeyPress]) -> Lookup<C> {
+        if let Some((_, command)) = self.entries.iter().find(|(sequence, _)| sequence == keys) {
+            return Lookup::Matched(*command);
+        }
+        if self
+            .entries
+            .iter()
+            .any(|(sequence, _)| sequence.starts_with(keys))
+        {
+            return Lookup::Pending;
+        }
+        Lookup::NoMatch
+    }
+
+    /// Every sequence bound to `command`, formatted for help lines.
+    pub fn describe(&self, command: C) -> String {
+        self.entries
+            .iter()
+            .filter(|(_, c)| *c == command)
+            .map(|(keys, _)| format_keys(keys))
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+pub struct Keymap {
+    pub list: Bindings<ListCommand>,
+    pub form: Bindings<FormCommand>,
+}
+
+impl Keymap {
+    pub fn new(config: &KeymapConfig) -> Result<Keymap, String> {
+        let leader =
+            KeyPress::parse(&config.leader, None).map_err(|e| format!("keymap.leader: {e}"))?;
+
+        Ok(Keymap {
+            list: Bindings::new("list", &config.list, leader)?,
+            form: Bindings::new("form", &config.form, leader)?,
+        })
+    }
+}
+
+pub fn format_keys(keys: &[KeyPress]) -> String {
+    keys.iter()
+        .map(KeyPress::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
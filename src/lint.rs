@@ -0,0 +1,42 @@
+use color_eyre::eyre::Result;
+
+use crate::{db::Database, models::SortOrder};
+
+/// `ratata-notes lint`: lists the notes updated at least `threshold` times in the last `days`
+/// days, most updated first, then those longer than `long_chars` characters. Notes edited that
+/// often or grown that long tend to mix several topics.
+pub fn run(db: &Database, days: u32, threshold: u32, long_chars: usize) -> Result<()> {
+    let notes = db.get_all_notes_sorted(SortOrder::Created, 0)?;
+    let mut hot = Vec::new();
+    for note in notes.iter() {
+        let updates = db.get_note_update_frequency(note.id, days)?;
+        if updates >= threshold {
+            hot.push((updates, note));
+        }
+    }
+
+    if hot.is_empty() {
+        println!("No note was updated {threshold} times or more in {days} days");
+    }
+    hot.sort_by_key(|&(updates, _)| std::cmp::Reverse(updates));
+    for (updates, note) in hot {
+        println!(
+            "{}\t{}: updated {updates} times in {days} days, consider splitting it by topic",
+            note.id, note.title
+        );
+    }
+
+    let long = db.find_long_notes(long_chars)?;
+    if long.is_empty() {
+        println!("No note is longer than {long_chars} characters");
+    }
+    for note in long {
+        println!(
+            "{}\t{}: {} characters, consider splitting it",
+            note.id,
+            note.title,
+            note.content.chars().count()
+        );
+    }
+    Ok(())
+}
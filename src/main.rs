@@ -1,53 +1,645 @@
+mod archive;
+mod bulk_archive;
+mod checklist;
+mod cli;
+mod cloud;
+mod collation;
+mod config;
+mod daily;
+mod dates;
 mod db;
+#[cfg(feature = "debug-overlay")]
+mod debug_overlay;
+mod diff;
+mod emoji;
+mod fuzzy;
+mod import_dir;
+mod journal;
+mod keymap;
+mod lint;
+mod migrations;
 mod models;
+mod pdf_export;
+mod query_parser;
+mod scripting;
+mod search;
+mod stats;
+mod summary;
+mod tag_edit;
+#[cfg(test)]
+mod test_support;
+mod text_utils;
+mod theme;
+mod wiki_parser;
 
-use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet, VecDeque},
+    hash::{BuildHasher, Hasher, RandomState},
+    io::{self, Read},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{Arc, mpsc},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::Parser;
+use color_eyre::eyre::eyre;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
 use ratatui::{
     DefaultTerminal, Frame,
-    layout::{Constraint, Direction, Layout},
-    style::{Style, Stylize},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Style, Stylize},
     symbols::border,
-    text::{Line, ToSpan},
-    widgets::{Block, List, ListState, Paragraph},
+    text::{Line, Span, Text, ToSpan},
+    widgets::{Bar, BarChart, Block, Clear, List, ListState, Paragraph, Wrap},
 };
+use regex::{Regex, RegexBuilder};
+use similar::ChangeTag;
 use tui_input::{Input, backend::crossterm::EventHandler};
 
 use crate::{
-    db::Database,
-    models::{Note, NoteList},
+    cli::{Cli, Command},
+    collation::TitleCollator,
+    config::{Config, QuitConfirm},
+    daily::Daily,
+    dates::DateDisplay,
+    db::{Database, DbError, NEW_NOTE_DRAFT_ID, NoteOrDuplicate},
+    emoji::EmojiPicker,
+    keymap::{FormCommand, KeyPress, Keymap, ListCommand, Lookup},
+    models::{
+        Draft, Mark, Note, NoteColor, NoteList, NoteListCommand, Revision, SortOrder, TreeNode,
+    },
+    scripting::{Hook, ScriptHost},
+    search::SearchOptions,
+    stats::Stats,
+    tag_edit::{TagEditor, TagEdits},
+    theme::{Theme, ThemeWatcher},
 };
 
+/// Opened relative to the working directory.
+const DB_PATH: &str = "notes.db";
+
 fn main() -> color_eyre::Result<()> {
     color_eyre::install()?;
-    let db = Database::new("notes.db")?;
-    let notes = db.get_all_notes()?;
-    let mut list_state = ListState::default();
+    let cli = Cli::parse();
+
+    if let Some(Command::Completions { shell }) = cli.command {
+        cli::print_completions(shell);
+        return Ok(());
+    }
+    if let Some(Command::Path) = cli.command {
+        println!("{}", std::path::absolute(DB_PATH)?.display());
+        return Ok(());
+    }
+
+    let config = Config::load()?;
+    let keymap = Keymap::new(&config.keymap).map_err(|e| eyre!(e))?;
+    let theme_watcher = config::config_dir().map(|dir| ThemeWatcher::new(dir.join("theme.toml")));
+    let theme = match &theme_watcher {
+        Some(watcher) if watcher.path().exists() => Theme::from_file(watcher.path())?,
+        _ => Theme::new(&config.theme),
+    };
+    let title_collator =
+        TitleCollator::new(&config.notes.locale).map_err(|e| eyre!("invalid notes.locale {e}"))?;
+    let dates = DateDisplay::new(&config.display).map_err(|e| eyre!(e))?;
+    let daily = Daily::new(&config.daily).map_err(|e| eyre!(e))?;
+    let db = Database::new(DB_PATH, title_collator.clone())?;
+
+    if let Some(path) = &cli.export_sqlite {
+        db.export_as_sqlite(path)?;
+        println!("Exported a read-only copy to {}", path.display());
+        return Ok(());
+    }
+    if let Some(path) = &cli.export_roam {
+        db.export_as_roam_json(path)?;
+        println!("Exported the notes for Roam Research to {}", path.display());
+        return Ok(());
+    }
+
+    match &cli.command {
+        Some(Command::Tag { id, tags }) => {
+            require_note(&db, *id)?;
+            for tag in tags {
+                db.add_tag_to_note(*id, tag)?;
+            }
+            return Ok(());
+        }
+        Some(Command::Untag { id, tags }) => {
+            require_note(&db, *id)?;
+            for tag in tags {
+                db.remove_tag_from_note(*id, tag)?;
+            }
+            return Ok(());
+        }
+        Some(Command::DeleteTag { tag, confirm }) => {
+            if !confirm {
+                return Err(eyre!(
+                    "this deletes every note tagged \"{tag}\", pass --confirm to go ahead"
+                ));
+            }
+            let deleted = db.delete_all_notes_in_tag(tag)?;
+            println!("Deleted {deleted} notes tagged \"{tag}\"");
+            return Ok(());
+        }
+        Some(Command::Summary { since }) => {
+            summary::print(&db, &dates, *since)?;
+            return Ok(());
+        }
+        Some(Command::Archive {
+            older_than,
+            yes,
+            to_file,
+            undo: _,
+            restore_archive,
+        }) => {
+            // The `mode` group makes clap require exactly one of the three, so without the
+            // other two it's `--undo`.
+            match (older_than, restore_archive) {
+                (Some(days), _) => {
+                    bulk_archive::run(&db, *days, *yes, to_file.as_deref(), &title_collator)?
+                }
+                (None, Some(path)) => bulk_archive::restore(&db, path, &title_collator)?,
+                (None, None) => bulk_archive::undo(&db)?,
+            }
+            return Ok(());
+        }
+        Some(Command::Add { title, source_url }) => {
+            let mut content = String::new();
+            io::stdin().read_to_string(&mut content)?;
+            let note = db.add_note_with_source(title, content.trim_end(), source_url.as_deref())?;
+            println!("Added \"{}\" ({})", note.title, note.id);
+            return Ok(());
+        }
+        Some(Command::Log { text }) => {
+            let at = dates.local_time(Utc::now());
+            let (note, _) = journal::append(&db, &config.journal, text, at)?;
+            println!("Added to \"{}\"", note.title);
+            return Ok(());
+        }
+        Some(Command::Lint {
+            days,
+            threshold,
+            long_chars,
+        }) => {
+            let long_chars = long_chars.unwrap_or(config.notes.long_note_chars);
+            lint::run(&db, *days, *threshold, long_chars)?;
+            return Ok(());
+        }
+        Some(Command::ImportDir { dir }) => {
+            import_dir::run(&db, dir)?;
+            return Ok(());
+        }
+        Some(Command::Check) => {
+            let broken = db.find_notes_with_broken_links()?;
+            if broken.is_empty() {
+                println!("No broken links");
+            }
+            for (note, targets) in broken {
+                println!("{}\t{}: {}", note.id, note.title, wikilinks(&targets));
+            }
+            return Ok(());
+        }
+        Some(Command::Random) => {
+            let notes = db.get_all_notes_sorted(SortOrder::Created, 0)?;
+            if let Some(note) = random_index(notes.len(), None).map(|index| &notes[index]) {
+                println!("{}\n\n{}", note.title, note.content);
+            }
+            return Ok(());
+        }
+        Some(Command::Tags { id }) => {
+            require_note(&db, *id)?;
+            for tag in db.get_note_tags(*id)? {
+                println!("{tag}");
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    if let Some(Command::CompletionsIds) = cli.command {
+        for note in db.get_all_notes_sorted(SortOrder::Created, 0)?.iter() {
+            println!("{}\t{}", note.id, cli::completion_title(&note.title));
+        }
+        return Ok(());
+    }
+
+    // Created before the notes are loaded, so it is in the list like any other.
+    let today_id = match cli.command {
+        Some(Command::Today) => Some(daily.open(&db, dates.local_date(Utc::now()))?.0.id),
+        _ => None,
+    };
+
+    let mut app = App::new(config, keymap, theme, db, title_collator, dates, daily)?;
+    app.theme_watcher = theme_watcher;
 
-    if !notes.is_empty() {
-        list_state.select(Some(0));
+    if app.config.scripting.enabled
+        && let Some(dir) = config::config_dir().map(|dir| dir.join("scripts"))
+    {
+        let timeout = Duration::from_millis(app.config.scripting.timeout_ms);
+        let (scripts, mut errors) = ScriptHost::load(&dir, timeout);
+        errors.extend(scripts.run_startup());
+        for error in errors {
+            app.notify(error, Level::Error, ERROR_TOAST_DURATION);
+        }
+        app.scripts = Some(scripts);
     }
 
-    let mut app = App {
-        notes: NoteList {
-            items: notes,
-            state: list_state,
+    let open_id = match cli.command {
+        Some(Command::Edit { id }) => Some(id),
+        Some(Command::Today) => today_id,
+        _ => match &cli.note_title {
+            Some(title) => {
+                let note = app
+                    .db
+                    .get_note_by_title(title)?
+                    .ok_or_else(|| eyre!("no note titled \"{title}\""))?;
+                Some(note.id)
+            }
+            None => cli.note_id,
         },
-        db,
-        current_screen: Screen::List,
-        title_input: Input::default(),
-        content_input: Input::default(),
-        focused_input: FocusedInput::Title,
-        should_quit: false,
     };
+    if let Some(id) = open_id {
+        let index = app
+            .notes
+            .items
+            .iter()
+            .position(|n| n.id == id)
+            .ok_or_else(|| eyre!("no note with id {id}"))?;
+        app.notes.state.select(Some(index));
+
+        if let Some(Command::Edit { .. } | Command::Today) = cli.command {
+            let mut action = Some(Action::List(ListAction::SelectNote));
+            while let Some(next) = action {
+                action = app.handle_action(next);
+            }
+        }
+        if let Some(Command::Today) = cli.command {
+            app.focused_input = FocusedInput::Content;
+        }
+    }
+
     ratatui::run(|t| app.run(t))?;
+    // The app only stops through `quit`, after the form has been left, so whatever is still in
+    // the drafts table was deliberately discarded. A failure is printed now that the terminal
+    // is restored, as the next start would otherwise offer the drafts again.
+    app.db
+        .delete_all_drafts()
+        .map_err(|e| eyre!("failed to discard the drafts: {e}"))?;
+    app.db.close()?;
 
     Ok(())
 }
 
+/// A random index below `len`, other than `exclude` unless it's the only one.
+fn random_index(len: usize, exclude: Option<usize>) -> Option<usize> {
+    // `RandomState` is seeded from the OS, which is all the randomness this needs.
+    let random = RandomState::new().build_hasher().finish() as usize;
+    match exclude {
+        Some(excluded) if len > 1 && excluded < len => {
+            Some((excluded + 1 + random % (len - 1)) % len)
+        }
+        _ => (len > 0).then(|| random % len),
+    }
+}
+
+fn require_note(db: &Database, id: i64) -> color_eyre::Result<()> {
+    match db.get_note(id)? {
+        Some(_) => Ok(()),
+        None => Err(eyre!("no note with id {id}")),
+    }
+}
+
+const TICK_RATE: Duration = Duration::from_secs(1);
+const SCREEN_HISTORY_LIMIT: usize = 20;
+const DOUBLE_PRESS_WINDOW: Duration = Duration::from_secs(1);
+const UNDO_DELETE_WINDOW: Duration = Duration::from_secs(5);
+/// Changes kept for undo; older ones are dropped.
+const UNDO_STACK_LIMIT: usize = 50;
+/// How long a notification stays up, unless a key press dismisses it first.
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+/// Errors stay up longer, to be read in full.
+const ERROR_TOAST_DURATION: Duration = Duration::from_secs(8);
+/// Rows of the `[[` title picker, borders included.
+const WIKILINK_PICKER_HEIGHT: u16 = 10;
+/// Results listed by the quick switcher; the best matches are all anyone looks at.
+const QUICK_SWITCHER_LIMIT: usize = 50;
+/// Characters shown on each side of the match in search results.
+const SEARCH_EXCERPT_CONTEXT: usize = 30;
+/// Characters in the bars of the long notes popup.
+const LONG_NOTE_BAR_WIDTH: usize = 10;
+const RECENTLY_VIEWED_LIMIT: usize = 10;
+/// Tags a list item names in full; past that it names two and counts the rest as `+N`.
+const TAG_CHIPS_LIMIT: usize = 3;
+/// Terminal rows from which the tags get their own line under each title.
+const TAG_CHIPS_OWN_LINE_HEIGHT: u16 = 40;
+/// `app_state` key of the recently viewed note ids, most recent first and comma-separated.
+const RECENTLY_VIEWED_KEY: &str = "recently_viewed";
+const ARCHIVE_PATH: &str = "notes.age";
+const ZIP_PATH: &str = "notes.zip";
+
+#[derive(Clone, Copy, PartialEq)]
 enum Screen {
     List,
     Form,
     ExitConfirm,
+    Passphrase(ArchiveOp),
+    AdvancedSearch,
+    RestoreDraft,
+    DuplicateTitle,
+    TagBrowser,
+    EmojiPicker,
+    Stats,
+    MergeConfirm,
+    BulkTag,
+    QuickSwitcher,
+    TextSearch,
+    Marks,
+    RecentNotes,
+    BrokenLinks,
+    LongNotes,
+    SplitNote,
+    History,
+    RevisionDiff,
+    PdfExport,
+    JournalEntry,
+    Cloud,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ArchiveOp {
+    Export,
+    Import,
+}
+
+enum SearchField {
+    Query,
+    Min,
+    Max,
+}
+
+struct AdvancedSearch {
+    query_input: Input,
+    min_input: Input,
+    max_input: Input,
+    focused_input: SearchField,
+    options: SearchOptions,
+    results: NoteList,
+}
+
+/// The `/` filter of the list, which then only shows the notes whose titles contain `input`, or
+/// that the regular expression after a `re:` prefix matches.
+struct ListFilter {
+    input: Input,
+    /// Keys go to the input until Enter, after which the filter stays while moving in the list.
+    typing: bool,
+    /// The note selected when the filter opened, selected again on Esc if nothing else is.
+    return_to: Option<i64>,
+    /// The last pattern that compiled while the input starts with `re:`, kept while the one
+    /// being typed is invalid so that the list doesn't jump back to every note.
+    regex: Option<Regex>,
+    /// Why the current `re:` pattern doesn't compile, shown in the filter box.
+    error: Option<String>,
+}
+
+impl ListFilter {
+    const REGEX_PREFIX: &str = "re:";
+
+    fn pattern(&self) -> Option<&str> {
+        self.input.value().strip_prefix(Self::REGEX_PREFIX)
+    }
+
+    /// Compiles the input again after it changed.
+    fn update_regex(&mut self) {
+        let Some(pattern) = self.pattern() else {
+            self.regex = None;
+            self.error = None;
+            return;
+        };
+        match RegexBuilder::new(pattern).case_insensitive(true).build() {
+            Ok(regex) => {
+                self.regex = Some(regex);
+                self.error = None;
+            }
+            // The full message repeats the pattern with a caret under it; its last line says
+            // what is wrong.
+            Err(e) => {
+                let message = e.to_string();
+                let reason = message.lines().last().unwrap_or_default();
+                self.error = Some(reason.trim_start_matches("error: ").to_string());
+            }
+        }
+    }
+
+    /// Plain text is looked for in titles; a `re:` pattern in titles and contents.
+    fn matches(&self, note: &Note, options: SearchOptions) -> bool {
+        match (self.pattern(), &self.regex) {
+            (None, _) => search::matches(&note.title, self.input.value(), options),
+            (Some(_), Some(regex)) => regex.is_match(&note.title) || regex.is_match(&note.content),
+            (Some(_), None) => true,
+        }
+    }
+
+    fn find_matches(&self, text: &str, options: SearchOptions) -> Vec<Range<usize>> {
+        match (self.pattern(), &self.regex) {
+            (None, _) => search::find_matches(text, self.input.value(), options),
+            (Some(_), Some(regex)) => regex
+                .find_iter(text)
+                .map(|found| found.range())
+                .filter(|range| !range.is_empty())
+                .collect(),
+            (Some(_), None) => Vec::new(),
+        }
+    }
+}
+
+/// Ctrl+F overlay listing the notes whose title or content contains the words of `input`, from
+/// the full-text index.
+struct TextSearch {
+    input: Input,
+    /// Best match first.
+    results: Vec<Note>,
+    selected: usize,
+}
+
+/// Ctrl+P overlay listing the notes whose titles fuzzily match `input`.
+struct QuickSwitcher {
+    input: Input,
+    /// Indexes into the notes list, best match first.
+    results: Vec<usize>,
+    selected: usize,
+    tags: HashMap<i64, Vec<String>>,
+    /// The screen drawn under the overlay and restored on Esc.
+    return_to: Screen,
+}
+
+/// Marks of the note open in `return_to`, listed over it.
+struct MarksPopup {
+    marks: Vec<Mark>,
+    selected: usize,
+    return_to: Screen,
+}
+
+/// What the letter typed after a mark binding does with it.
+#[derive(Clone, Copy)]
+enum MarkOp {
+    Set,
+    Jump,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum CloudMode {
+    Tags,
+    Words,
+}
+
+/// Word counts and the notes they were counted in.
+type WordCounts = (Arc<[Note]>, Vec<(String, usize)>);
+
+/// Tags or significant words by how often they're used, for the `C` screen.
+struct Cloud {
+    mode: CloudMode,
+    /// Most used first, shown in that order.
+    entries: Vec<(String, usize)>,
+    selected: usize,
+    /// Counted off the UI thread, and kept until the notes list changes.
+    words: Option<WordCounts>,
+    counting: Option<mpsc::Receiver<WordCounts>>,
+}
+
+/// Earlier versions of the selected note, listed by `H`, and the diff of the one compared.
+struct History {
+    note_id: i64,
+    revisions: Vec<Revision>,
+    selected: usize,
+    /// From the selected revision to the current content, computed once when it's opened.
+    diff: Vec<diff::DiffLine>,
+    scroll: usize,
+    /// Diff rows that fit on screen at the last draw, which paging moves by.
+    page: usize,
+}
+
+/// The note being split on the split screen, which goes at a line boundary.
+struct SplitView {
+    note_id: i64,
+    /// The first line of the part split off; lines before it stay in the note.
+    line: usize,
+    /// Rows that fit on screen at the last draw, which paging moves the divider by.
+    page: usize,
+}
+
+/// `selected` counts rows of the visible tree: each tag, followed by its notes when expanded.
+struct TagBrowser {
+    nodes: Vec<TreeNode>,
+    selected: usize,
+}
+
+impl TagBrowser {
+    fn rows(node: &TreeNode) -> usize {
+        if node.expanded {
+            1 + node.notes.len()
+        } else {
+            1
+        }
+    }
+
+    fn row_count(&self) -> usize {
+        self.nodes.iter().map(TagBrowser::rows).sum()
+    }
+
+    /// The first row of the node at `index`.
+    fn node_row(&self, index: usize) -> usize {
+        self.nodes[..index].iter().map(TagBrowser::rows).sum()
+    }
+
+    /// Resolves the selected row to a node index, plus a note index when it is a child row.
+    fn locate(&self) -> Option<(usize, Option<usize>)> {
+        let mut start = 0;
+        for (index, node) in self.nodes.iter().enumerate() {
+            let offset = self.selected.checked_sub(start)?;
+            if offset < TagBrowser::rows(node) {
+                return Some((index, offset.checked_sub(1)));
+            }
+            start += TagBrowser::rows(node);
+        }
+        None
+    }
+}
+
+/// A change kept on `undo_stack` for `u` to revert. Reverting one gives the change that redoes
+/// it, kept on `redo_stack`, and the other way around.
+enum UndoableAction {
+    /// A note deleted from the list, put back with its id and tags.
+    DeleteNote(Note),
+    /// A note created since, deleted again.
+    AddNote(i64),
+    /// A save or checkbox toggle, or a new color, that turned `old` into `new`.
+    UpdateNote { id: i64, old: Note, new: Note },
+    /// The note moved from the first index of the list to the next one, `to`.
+    MoveNote { from: usize, to: usize },
+    /// The note archived with `A`, or brought back from the archived view.
+    Archive { id: i64, archived: bool },
+    /// Notes as they were before a merge, split or bulk tag edit, with their tags, and the notes
+    /// the change created. The first of `originals` is selected once they are put back.
+    Notes {
+        originals: Vec<(Note, Vec<String>)>,
+        created: Vec<i64>,
+    },
+}
+
+/// A note removed from the list but not yet from the database, so `u` can bring it back.
+struct PendingDeletion {
+    note: Note,
+    index: usize,
+    deadline: Instant,
+}
+
+/// A message shown in the status line in place of the key hints.
+struct Notification {
+    message: String,
+    level: Level,
+    expires_at: Instant,
+}
+
+#[derive(Clone, Copy)]
+enum Level {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+impl Screen {
+    /// Prompts and confirmations are stepped through, not navigated back to.
+    fn is_navigable(&self) -> bool {
+        matches!(
+            self,
+            Screen::List
+                | Screen::Form
+                | Screen::AdvancedSearch
+                | Screen::TagBrowser
+                | Screen::Cloud
+                | Screen::Stats
+        )
+    }
+}
+
+/// How the note list was last drawn, so that paging moves by what fits on screen.
+#[derive(Clone, Copy, Default)]
+struct ListGeometry {
+    /// Rows inside the borders.
+    rows: u16,
+    /// Columns left for the items once the borders and the `>>` marker are taken out.
+    width: u16,
+    chips_own_line: bool,
+}
+
+/// The half of the list screen that `j`/`k` move in.
+#[derive(Clone, Copy, PartialEq)]
+enum ListPane {
+    Notes,
+    Preview,
 }
 
 enum FocusedInput {
@@ -55,17 +647,76 @@ enum FocusedInput {
     Content,
 }
 enum ListAction {
-    MoveUp,
-    MoveDown,
+    MoveUp(usize),
+    MoveDown(usize),
+    PageUp,
+    PageDown,
+    FirstNote,
+    LastNote,
     AddNote,
+    DailyNote,
+    JournalEntry,
     SelectNote,
     DeleteNote,
+    ExportArchive,
+    ImportArchive,
+    ExportZip,
+    ImportZip,
+    ExportPdf,
+    AdvancedSearch,
+    Filter,
+    TextSearch,
+    TagBrowser,
+    Cloud,
+    Stats,
+    RandomNote,
+    MarkMerge,
+    MergeInto,
+    ToggleMark,
+    BulkTag,
+    CopyNote,
+    PasteNote,
+    PreviewDown,
+    PreviewUp,
+    MoveNoteDown,
+    MoveNoteUp,
+    FocusPane(ListPane),
+    ToggleCheckbox,
+    SetMark,
+    JumpToMark,
+    ListMarks,
+    RecentNotes,
+    BrokenLinks,
+    LongNotes,
+    SplitNote,
+    History,
+    CycleSort,
+    Archive,
+    ToggleArchived,
+    Color,
+    SetColor(NoteColor),
+    ToggleColorFilter,
+    Undo,
+    Redo,
     Quit,
 }
 enum FormAction {
     Save,
+    Bold,
+    Italic,
+    EmojiPicker,
+    SplitNote,
+    PreviousDay,
+    NextDay,
+    PreviousNote,
+    NextNote,
+    SetMark,
+    JumpToMark,
+    ListMarks,
     ToggleInput,
+    ToggleToolbar,
     UpdateInput(Event),
+    RunCommand(char),
     Exit,
 }
 
@@ -74,75 +725,1115 @@ enum ExitAction {
     Cancel,
 }
 
+enum PassphraseAction {
+    Confirm,
+    Cancel,
+    UpdateInput(Event),
+}
+
+enum AdvancedSearchAction {
+    ToggleInput,
+    ToggleAccents,
+    UpdateInput(Event),
+    MoveUp,
+    MoveDown,
+    Open,
+    Exit,
+}
+
+enum TagBrowserAction {
+    MoveUp,
+    MoveDown,
+    Expand,
+    Collapse,
+    Open,
+    Exit,
+}
+
+enum CloudAction {
+    Previous,
+    Next,
+    ToggleMode,
+    Open,
+    Exit,
+}
+
+enum MergeAction {
+    Confirm,
+    Cancel,
+}
+
+enum StatsAction {
+    Exit,
+}
+
+enum FilterAction {
+    UpdateInput(Event),
+    ToggleRegex,
+    MoveUp,
+    MoveDown,
+    Apply,
+    Clear,
+}
+
+enum TextSearchAction {
+    UpdateInput(Event),
+    MoveUp,
+    MoveDown,
+    Jump,
+    Cancel,
+}
+
+enum SwitcherAction {
+    Open,
+    UpdateInput(Event),
+    MoveUp,
+    MoveDown,
+    Jump,
+    Cancel,
+}
+
+enum BulkTagAction {
+    UpdateInput(Event),
+    Complete,
+    Apply,
+    Cancel,
+}
+
+enum WikilinkAction {
+    MoveUp,
+    MoveDown,
+    Insert,
+    Close,
+}
+
+enum JournalAction {
+    UpdateInput(Event),
+    Append,
+    Cancel,
+}
+
+enum PdfAction {
+    UpdateInput(Event),
+    Export,
+    Cancel,
+}
+
+enum RecentAction {
+    MoveUp,
+    MoveDown,
+    Open,
+    Cancel,
+}
+
+enum HistoryAction {
+    MoveUp,
+    MoveDown,
+    Compare,
+    Close,
+}
+
+enum DiffAction {
+    Down,
+    Up,
+    PageDown,
+    PageUp,
+    Restore,
+    Back,
+}
+
+enum SplitAction {
+    Down,
+    Up,
+    PageDown,
+    PageUp,
+    Split,
+    Cancel,
+}
+
+enum LongNotesAction {
+    MoveUp,
+    MoveDown,
+    Select,
+    Split,
+    Close,
+}
+
+enum BrokenLinksAction {
+    MoveUp,
+    MoveDown,
+    Open,
+    Close,
+}
+
+enum MarkAction {
+    Set(char),
+    Jump(char),
+    MoveUp,
+    MoveDown,
+    JumpToSelected,
+    Close,
+}
+
+enum EmojiAction {
+    UpdateInput(Event),
+    MoveUp,
+    MoveDown,
+    Insert,
+    Cancel,
+}
+
+enum DraftAction {
+    Restore,
+    Discard,
+}
+
+enum DuplicateAction {
+    OpenExisting,
+    Rename,
+    SaveAnyway,
+}
+
+enum NavigateAction {
+    Back,
+    Forward,
+}
+
 enum Action {
     List(ListAction),
     Form(FormAction),
     Exit(ExitAction),
+    Passphrase(PassphraseAction),
+    AdvancedSearch(AdvancedSearchAction),
+    Draft(DraftAction),
+    Navigate(NavigateAction),
+    Duplicate(DuplicateAction),
+    TagBrowser(TagBrowserAction),
+    Emoji(EmojiAction),
+    Stats(StatsAction),
+    Merge(MergeAction),
+    BulkTag(BulkTagAction),
+    Switcher(SwitcherAction),
+    Filter(FilterAction),
+    TextSearch(TextSearchAction),
+    Wikilink(WikilinkAction),
+    Mark(MarkAction),
+    Recent(RecentAction),
+    BrokenLinks(BrokenLinksAction),
+    LongNotes(LongNotesAction),
+    Split(SplitAction),
+    History(HistoryAction),
+    Diff(DiffAction),
+    Pdf(PdfAction),
+    Journal(JournalAction),
+    Cloud(CloudAction),
+}
+
+impl Action {
+    /// Whether the action may fire again while its key is held down.
+    fn repeats(&self) -> bool {
+        matches!(
+            self,
+            Action::List(
+                ListAction::MoveUp(_)
+                    | ListAction::MoveDown(_)
+                    | ListAction::PreviewUp
+                    | ListAction::PreviewDown
+            ) | Action::Form(FormAction::UpdateInput(_))
+                | Action::Passphrase(PassphraseAction::UpdateInput(_))
+                | Action::BulkTag(BulkTagAction::UpdateInput(_))
+                | Action::Switcher(
+                    SwitcherAction::MoveUp
+                        | SwitcherAction::MoveDown
+                        | SwitcherAction::UpdateInput(_)
+                )
+                | Action::AdvancedSearch(
+                    AdvancedSearchAction::MoveUp
+                        | AdvancedSearchAction::MoveDown
+                        | AdvancedSearchAction::UpdateInput(_)
+                )
+                | Action::TagBrowser(TagBrowserAction::MoveUp | TagBrowserAction::MoveDown)
+                | Action::Emoji(
+                    EmojiAction::MoveUp | EmojiAction::MoveDown | EmojiAction::UpdateInput(_)
+                )
+        )
+    }
 }
 
 struct App {
+    config: Config,
+    keymap: Keymap,
+    theme: Theme,
+    theme_watcher: Option<ThemeWatcher>,
+    /// Keys typed so far that are the start of a multi-key binding.
+    pending_keys: Vec<KeyPress>,
     db: Database,
     notes: NoteList,
     current_screen: Screen,
     title_input: Input,
     content_input: Input,
     focused_input: FocusedInput,
+    /// The formatting row above the title in the form, hidden with `Ctrl+T`.
+    show_toolbar: bool,
     should_quit: bool,
+    scripts: Option<ScriptHost>,
+    /// Messages for the status line, shown one at a time from the front.
+    notification_queue: VecDeque<Notification>,
+    passphrase_input: Input,
+    key_prefix_buffer: String,
+    advanced_search: AdvancedSearch,
+    tag_browser: TagBrowser,
+    cloud: Cloud,
+    history: History,
+    split_view: SplitView,
+    emoji_picker: EmojiPicker,
+    tag_editor: TagEditor,
+    quick_switcher: QuickSwitcher,
+    text_search: TextSearch,
+    stats: Stats,
+    /// Set when the form changed since the last draft was written.
+    draft_dirty: bool,
+    /// Drafts recovered at startup that the user hasn't restored or discarded yet.
+    pending_drafts: Vec<Draft>,
+    screen_history: VecDeque<Screen>,
+    screen_future: Vec<Screen>,
+    /// The form was edited since it was last loaded or saved.
+    unsaved_changes: bool,
+    /// Something was written to the database during this session.
+    session_changed: bool,
+    last_quit_press: Option<Instant>,
+    /// The form holds a note that hasn't been inserted yet.
+    editing_new: bool,
+    /// The existing note found when saving a new one with a taken title.
+    duplicate_of: Option<Note>,
+    /// Something visible changed since the last frame was drawn.
+    needs_redraw: bool,
+    draw_count: u64,
+    /// Fixes the `SortOrder::Random` shuffle for the whole session.
+    random_seed: u64,
+    /// Starts as the configured `sort_order`, then changes with `o`.
+    sort_order: SortOrder,
+    /// The list shows the archived notes instead, switched with `z`.
+    show_archived: bool,
+    /// A note copied with `y`, pasted as a new note with `p`. Its id is never reused.
+    global_clipboard: Option<Note>,
+    /// Line of the preview that `x` toggles, shown once moved with `J`/`K`.
+    preview_cursor: Option<usize>,
+    /// The preview cursors of notes selected earlier in the session, brought back along with
+    /// the scroll position when the note is selected again.
+    preview_cursor_per_note: HashMap<i64, usize>,
+    focused_pane: ListPane,
+    list_geometry: ListGeometry,
+    /// Changes `u` reverts, the latest last.
+    undo_stack: Vec<UndoableAction>,
+    /// Changes reverted since the last new one, which redo makes again.
+    redo_stack: Vec<UndoableAction>,
+    /// The note marked with `m`, merged into the selected one with `M`.
+    merge_source: Option<i64>,
+    /// Selected row of the title picker opened by typing `[[` in the content, while it is open.
+    wikilink_selected: Option<usize>,
+    /// Notes marked with `v` for bulk actions.
+    marked: HashSet<i64>,
+    /// Set by the mark bindings until the letter naming the mark is typed.
+    pending_mark: Option<MarkOp>,
+    /// Set by `c` until the next key picks a color or, with `c` again, toggles the filter.
+    pending_color: bool,
+    /// Only notes of this color are listed.
+    color_filter: Option<NoteColor>,
+    list_filter: Option<ListFilter>,
+    marks_popup: MarksPopup,
+    /// Notes opened in the form, most recent first, kept across sessions for `Ctrl+E`.
+    recently_viewed: VecDeque<i64>,
+    recent_selected: usize,
+    /// Notes linking to missing titles, with those titles, as found when `B` was pressed.
+    broken_links: Vec<(Note, Vec<String>)>,
+    broken_selected: usize,
+    /// Notes over `long_note_chars`, longest first, as found when the popup was opened.
+    long_notes: Vec<Note>,
+    long_selected: usize,
+    /// The entry typed in the `L` popup, appended to the journal note.
+    journal_input: Input,
+    /// Where the selected note is converted to, asked for before each PDF export.
+    pdf_path_input: Input,
+    /// Conversions report back here, as they run beside the UI.
+    pdf_exports: (
+        mpsc::Sender<pdf_export::Outcome>,
+        mpsc::Receiver<pdf_export::Outcome>,
+    ),
+    pending_deletion: Option<PendingDeletion>,
+    title_collator: TitleCollator,
+    dates: DateDisplay,
+    daily: Daily,
+    #[cfg(feature = "debug-overlay")]
+    debug_overlay: debug_overlay::DebugOverlay,
 }
 
 impl App {
-    pub fn run(&mut self, terminal: &mut DefaultTerminal) -> std::io::Result<()> {
-        while !self.should_quit {
-            terminal.draw(|f| self.render(f))?;
-            let event = crossterm::event::read()?;
+    /// The app on the list of the notes in `db`, or on the drafts left by a crash. It has no
+    /// theme watcher or scripts yet; `main` adds those.
+    fn new(
+        config: Config,
+        keymap: Keymap,
+        theme: Theme,
+        db: Database,
+        title_collator: TitleCollator,
+        dates: DateDisplay,
+        daily: Daily,
+    ) -> Result<App, DbError> {
+        let random_seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let sort_order = config.notes.sort_order;
+        let search_options = SearchOptions {
+            fold_accents: config.search.fold_accents,
+        };
+        let notes = db.get_all_notes_sorted(sort_order, random_seed)?;
 
-            if let crossterm::event::Event::Key(key) = event {
-                let mut action = self.handle_key(key, event);
+        let drafts = db.get_drafts()?;
+        let recently_viewed = db
+            .get_state(RECENTLY_VIEWED_KEY)?
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|id| id.parse().ok())
+            .collect();
+        let mut list_state = ListState::default();
 
-                while action.is_some() {
-                    action = self.handle_action(action.unwrap());
-                }
-            }
+        if !notes.is_empty() {
+            list_state.select(Some(0));
         }
-        Ok(())
-    }
 
-    fn render(&mut self, frame: &mut Frame) {
-        match self.current_screen {
-            Screen::List => {
-                self.render_list(frame);
-            }
-            Screen::Form => {
-                self.render_form(frame);
-            }
-            Screen::ExitConfirm => {
-                self.render_exit(frame);
-            }
-        }
+        let current_screen = if drafts.is_empty() {
+            Screen::List
+        } else {
+            Screen::RestoreDraft
+        };
+
+        Ok(App {
+            config,
+            keymap,
+            theme,
+            theme_watcher: None,
+            pending_keys: Vec::new(),
+            notes: NoteList {
+                items: notes,
+                state: list_state,
+            },
+            db,
+            current_screen,
+            title_input: Input::default(),
+            content_input: Input::default(),
+            focused_input: FocusedInput::Title,
+            show_toolbar: true,
+            should_quit: false,
+            scripts: None,
+            notification_queue: VecDeque::new(),
+            passphrase_input: Input::default(),
+            key_prefix_buffer: String::new(),
+            advanced_search: AdvancedSearch {
+                query_input: Input::default(),
+                min_input: Input::default(),
+                max_input: Input::default(),
+                focused_input: SearchField::Query,
+                options: search_options,
+                results: NoteList {
+                    items: Arc::from([]),
+                    state: ListState::default(),
+                },
+            },
+            tag_browser: TagBrowser {
+                nodes: Vec::new(),
+                selected: 0,
+            },
+            emoji_picker: EmojiPicker::default(),
+            tag_editor: TagEditor::default(),
+            quick_switcher: QuickSwitcher {
+                input: Input::default(),
+                results: Vec::new(),
+                selected: 0,
+                tags: HashMap::new(),
+                return_to: Screen::List,
+            },
+            text_search: TextSearch {
+                input: Input::default(),
+                results: Vec::new(),
+                selected: 0,
+            },
+            marks_popup: MarksPopup {
+                marks: Vec::new(),
+                selected: 0,
+                return_to: Screen::List,
+            },
+            pending_mark: None,
+            pending_color: false,
+            color_filter: None,
+            list_filter: None,
+            recently_viewed,
+            recent_selected: 0,
+            broken_links: Vec::new(),
+            broken_selected: 0,
+            long_notes: Vec::new(),
+            long_selected: 0,
+            journal_input: Input::default(),
+            pdf_path_input: Input::default(),
+            pdf_exports: mpsc::channel(),
+            cloud: Cloud {
+                mode: CloudMode::Tags,
+                entries: Vec::new(),
+                selected: 0,
+                words: None,
+                counting: None,
+            },
+            stats: Stats::default(),
+            split_view: SplitView {
+                note_id: 0,
+                line: 1,
+                page: 1,
+            },
+            history: History {
+                note_id: 0,
+                revisions: Vec::new(),
+                selected: 0,
+                diff: Vec::new(),
+                scroll: 0,
+                page: 1,
+            },
+            draft_dirty: false,
+            pending_drafts: drafts,
+            screen_history: VecDeque::new(),
+            screen_future: Vec::new(),
+            unsaved_changes: false,
+            session_changed: false,
+            last_quit_press: None,
+            editing_new: false,
+            duplicate_of: None,
+            needs_redraw: true,
+            draw_count: 0,
+            random_seed,
+            sort_order,
+            show_archived: false,
+            global_clipboard: None,
+            preview_cursor: None,
+            preview_cursor_per_note: HashMap::new(),
+            focused_pane: ListPane::Notes,
+            list_geometry: ListGeometry::default(),
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            merge_source: None,
+            wikilink_selected: None,
+            marked: HashSet::new(),
+            pending_deletion: None,
+            title_collator,
+            dates,
+            daily,
+            #[cfg(feature = "debug-overlay")]
+            debug_overlay: debug_overlay::DebugOverlay::new(),
+        })
+    }
+
+    pub fn run(&mut self, terminal: &mut DefaultTerminal) -> std::io::Result<()> {
+        let mut last_tick = Instant::now();
+
+        while !self.should_quit {
+            if self.take_redraw() {
+                self.draw_count += 1;
+                #[cfg(feature = "debug-overlay")]
+                let frame_start = Instant::now();
+                terminal.draw(|f| self.render(f))?;
+                #[cfg(feature = "debug-overlay")]
+                {
+                    self.debug_overlay.last_frame = frame_start.elapsed();
+                }
+            }
+
+            let timeout = TICK_RATE.saturating_sub(last_tick.elapsed());
+            if event::poll(timeout)? {
+                // Everything already queued is handled before the next frame, so a burst of keys
+                // costs a single redraw.
+                loop {
+                    self.handle_event(event::read()?);
+                    if self.should_quit || !event::poll(Duration::ZERO)? {
+                        break;
+                    }
+                }
+            }
+
+            let tick_due = last_tick.elapsed() >= TICK_RATE;
+            self.update(tick_due);
+            if tick_due {
+                last_tick = Instant::now();
+            }
+        }
+        Ok(())
+    }
+
+    /// What happens between frames besides input: background work reporting back, and the tick
+    /// when `tick_due`. Anything that changes the screen asks for a redraw.
+    fn update(&mut self, tick_due: bool) {
+        #[cfg(feature = "debug-overlay")]
+        {
+            self.needs_redraw |= self.debug_overlay.roll_window() && self.debug_overlay.visible;
+        }
+
+        self.poll_pdf_exports();
+        self.poll_word_count();
+
+        if tick_due {
+            self.on_tick();
+        }
+    }
+
+    /// Whether a frame should be drawn, which clears the request. An idle app never asks.
+    fn take_redraw(&mut self) -> bool {
+        std::mem::take(&mut self.needs_redraw)
+    }
+
+    fn handle_event(&mut self, event: Event) {
+        #[cfg(feature = "debug-overlay")]
+        {
+            self.debug_overlay.count_event();
+            if let Event::Key(key) = event
+                && key.code == KeyCode::F(12)
+            {
+                if key.kind == KeyEventKind::Press {
+                    self.debug_overlay.visible = !self.debug_overlay.visible;
+                    self.needs_redraw = true;
+                }
+                return;
+            }
+        }
+
+        match event {
+            Event::Key(key) => {
+                // Terminals that report releases (Windows, the kitty protocol) would otherwise
+                // trigger every action twice. Filtering here covers every screen at once.
+                if key.kind == KeyEventKind::Release {
+                    return;
+                }
+
+                // A held key never continues a binding or count it started: its repeats are
+                // dropped before `handle_key`, which is what would extend them.
+                if key.kind == KeyEventKind::Repeat && self.key_sequence_pending() {
+                    return;
+                }
+
+                // A key press dismisses what was on show, and the next one takes its place.
+                self.notification_queue.pop_front();
+                self.needs_redraw = true;
+                let mut action = self.handle_key(key, event);
+
+                // Holding a key repeats movement and typing, but never toggles or confirmations.
+                if key.kind == KeyEventKind::Repeat && !action.as_ref().is_some_and(Action::repeats)
+                {
+                    return;
+                }
+
+                while action.is_some() {
+                    action = self.handle_action(action.unwrap());
+                }
+            }
+            Event::Resize(_, _) => self.needs_redraw = true,
+            _ => {}
+        }
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        self.expire_notifications();
+        let screen = self.current_screen;
+        match self.popup_base(screen) {
+            Some(base) => {
+                self.render_screen(frame, base);
+                self.render_popup(frame, screen);
+            }
+            None => self.render_screen(frame, screen),
+        }
+        self.render_overlays(frame);
+    }
+
+    /// The screen a popup is drawn over, or `None` for screens that fill the terminal.
+    fn popup_base(&self, screen: Screen) -> Option<Screen> {
+        match screen {
+            Screen::ExitConfirm
+            | Screen::MergeConfirm
+            | Screen::BulkTag
+            | Screen::RecentNotes
+            | Screen::BrokenLinks
+            | Screen::LongNotes
+            | Screen::History
+            | Screen::PdfExport
+            | Screen::JournalEntry
+            | Screen::TextSearch => Some(Screen::List),
+            Screen::DuplicateTitle | Screen::EmojiPicker => Some(Screen::Form),
+            Screen::QuickSwitcher => Some(self.quick_switcher.return_to),
+            Screen::Marks => Some(self.marks_popup.return_to),
+            Screen::List
+            | Screen::Form
+            | Screen::Passphrase(_)
+            | Screen::AdvancedSearch
+            | Screen::RestoreDraft
+            | Screen::TagBrowser
+            | Screen::Stats
+            | Screen::Cloud
+            | Screen::RevisionDiff
+            | Screen::SplitNote => None,
+        }
+    }
+
+    fn render_screen(&mut self, frame: &mut Frame, screen: Screen) {
+        match screen {
+            Screen::List => {
+                self.render_list(frame);
+            }
+            Screen::Form => {
+                self.render_form(frame);
+            }
+            Screen::Passphrase(op) => {
+                self.render_passphrase(frame, op);
+            }
+            Screen::AdvancedSearch => {
+                self.render_advanced_search(frame);
+            }
+            Screen::RestoreDraft => {
+                self.render_restore_draft(frame);
+            }
+            Screen::TagBrowser => {
+                self.render_tag_browser(frame);
+            }
+            Screen::Stats => {
+                self.render_stats(frame);
+            }
+            Screen::Cloud => {
+                self.render_cloud(frame);
+            }
+            Screen::RevisionDiff => {
+                self.render_revision_diff(frame);
+            }
+            Screen::SplitNote => {
+                self.render_split_note(frame);
+            }
+            // Popups never serve as a base; `render` draws them over theirs.
+            _ => {}
+        }
+    }
+
+    /// Draws `screen` over what is already on the frame, when it is a popup.
+    fn render_popup(&mut self, frame: &mut Frame, screen: Screen) {
+        match screen {
+            Screen::ExitConfirm => self.render_exit(frame),
+            Screen::DuplicateTitle => self.render_duplicate_title(frame),
+            Screen::EmojiPicker => self.render_emoji_picker(frame),
+            Screen::MergeConfirm => self.render_merge_confirm(frame),
+            Screen::BulkTag => self.render_bulk_tag(frame),
+            Screen::RecentNotes => self.render_recent_notes(frame),
+            Screen::BrokenLinks => self.render_broken_links(frame),
+            Screen::LongNotes => self.render_long_notes(frame),
+            Screen::History => self.render_history(frame),
+            Screen::PdfExport => self.render_pdf_export(frame),
+            Screen::JournalEntry => self.render_journal_entry(frame),
+            Screen::QuickSwitcher => self.render_quick_switcher(frame),
+            Screen::TextSearch => self.render_text_search(frame),
+            Screen::Marks => self.render_marks(frame),
+            _ => {}
+        }
+    }
+
+    /// Debugging aids drawn over every screen.
+    fn render_overlays(&self, frame: &mut Frame) {
+        #[cfg(feature = "debug-overlay")]
+        if self.debug_overlay.visible {
+            let overlay = Line::from(self.debug_overlay.line(self.notes.items.len()));
+            let area = frame.area();
+            frame.render_widget(Clear, Rect { height: 1, ..area });
+            frame.render_widget(overlay.reversed(), Rect { height: 1, ..area });
+        }
+
+        if self.config.debug.draw_counter {
+            let counter = Line::from(format!(" draws: {} ", self.draw_count)).right_aligned();
+            let area = frame.area();
+            frame.render_widget(counter.reversed(), Rect { height: 1, ..area });
+        }
     }
 
     fn handle_key(&mut self, key: event::KeyEvent, event: Event) -> Option<Action> {
-        match self.current_screen {
-            Screen::List => match key.code {
-                KeyCode::Char('q') | KeyCode::Esc => Some(Action::List(ListAction::Quit)),
-                KeyCode::Char('j') | KeyCode::Down => Some(Action::List(ListAction::MoveDown)),
-                KeyCode::Char('k') | KeyCode::Up => Some(Action::List(ListAction::MoveUp)),
-                KeyCode::Enter | KeyCode::Char('e') => Some(Action::List(ListAction::SelectNote)),
-                KeyCode::Char('a') | KeyCode::Char('i') => Some(Action::List(ListAction::AddNote)),
-                KeyCode::Char('d') => Some(Action::List(ListAction::DeleteNote)),
+        if std::mem::take(&mut self.pending_color) {
+            return match key.code {
+                KeyCode::Char('c') => Some(Action::List(ListAction::ToggleColorFilter)),
+                KeyCode::Char(key) => {
+                    NoteColor::from_key(key).map(|color| Action::List(ListAction::SetColor(color)))
+                }
                 _ => None,
-            },
-            Screen::Form => match (key.modifiers, key.code) {
-                (KeyModifiers::CONTROL, KeyCode::Char('s')) => Some(Action::Form(FormAction::Save)),
-                (_, KeyCode::Tab) => Some(Action::Form(FormAction::ToggleInput)),
-                (_, KeyCode::Esc) => Some(Action::Form(FormAction::Exit)),
-                _ => Some(Action::Form(FormAction::UpdateInput(event))),
-            },
+            };
+        }
+        if let Some(op) = self.pending_mark.take() {
+            return match key.code {
+                KeyCode::Char(name)
+                    if name.is_ascii_alphabetic()
+                        && !key
+                            .modifiers
+                            .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+                {
+                    Some(Action::Mark(match op {
+                        MarkOp::Set => MarkAction::Set(name),
+                        MarkOp::Jump => MarkAction::Jump(name),
+                    }))
+                }
+                _ => None,
+            };
+        }
+
+        if self.current_screen.is_navigable() {
+            match (key.modifiers, key.code) {
+                (KeyModifiers::CONTROL, KeyCode::Char('p')) => {
+                    return Some(Action::Switcher(SwitcherAction::Open));
+                }
+                (KeyModifiers::ALT, KeyCode::Left)
+                | (KeyModifiers::CONTROL, KeyCode::Char('o')) => {
+                    return Some(Action::Navigate(NavigateAction::Back));
+                }
+                (KeyModifiers::ALT, KeyCode::Right) => {
+                    return Some(Action::Navigate(NavigateAction::Forward));
+                }
+                _ => {}
+            }
+        }
+
+        match self.current_screen {
+            Screen::List
+                if self
+                    .list_filter
+                    .as_ref()
+                    .is_some_and(|filter| filter.typing) =>
+            {
+                match key.code {
+                    KeyCode::Esc => Some(Action::Filter(FilterAction::Clear)),
+                    KeyCode::Enter => Some(Action::Filter(FilterAction::Apply)),
+                    KeyCode::Up => Some(Action::Filter(FilterAction::MoveUp)),
+                    KeyCode::Down => Some(Action::Filter(FilterAction::MoveDown)),
+                    KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        Some(Action::Filter(FilterAction::ToggleRegex))
+                    }
+                    _ => Some(Action::Filter(FilterAction::UpdateInput(event))),
+                }
+            }
+            // Esc leaves the filtered list before it can quit.
+            Screen::List if self.list_filter.is_some() && key.code == KeyCode::Esc => {
+                Some(Action::Filter(FilterAction::Clear))
+            }
+            Screen::List => {
+                if self.pending_keys.is_empty()
+                    && let KeyCode::Char(digit @ '0'..='9') = key.code
+                {
+                    self.key_prefix_buffer.push(digit);
+                    return None;
+                }
+
+                let command = match lookup_sequence(&self.keymap.list, &mut self.pending_keys, key)
+                {
+                    Lookup::Matched(command) => command,
+                    Lookup::Pending => return None,
+                    Lookup::NoMatch => {
+                        self.key_prefix_buffer.clear();
+                        return None;
+                    }
+                };
+
+                let count = self.take_count();
+                let list_action = match command {
+                    ListCommand::Quit => ListAction::Quit,
+                    ListCommand::MoveDown if self.focused_pane == ListPane::Preview => {
+                        ListAction::PreviewDown
+                    }
+                    ListCommand::MoveUp if self.focused_pane == ListPane::Preview => {
+                        ListAction::PreviewUp
+                    }
+                    ListCommand::MoveDown => ListAction::MoveDown(count),
+                    ListCommand::MoveUp => ListAction::MoveUp(count),
+                    ListCommand::PageDown => ListAction::PageDown,
+                    ListCommand::PageUp => ListAction::PageUp,
+                    ListCommand::FirstNote => ListAction::FirstNote,
+                    ListCommand::LastNote => ListAction::LastNote,
+                    ListCommand::SelectNote => ListAction::SelectNote,
+                    ListCommand::AddNote => ListAction::AddNote,
+                    ListCommand::DailyNote => ListAction::DailyNote,
+                    ListCommand::JournalEntry => ListAction::JournalEntry,
+                    ListCommand::DeleteNote => ListAction::DeleteNote,
+                    ListCommand::ExportArchive => ListAction::ExportArchive,
+                    ListCommand::ImportArchive => ListAction::ImportArchive,
+                    ListCommand::ExportZip => ListAction::ExportZip,
+                    ListCommand::ImportZip => ListAction::ImportZip,
+                    ListCommand::ExportPdf => ListAction::ExportPdf,
+                    ListCommand::AdvancedSearch => ListAction::AdvancedSearch,
+                    ListCommand::Filter => ListAction::Filter,
+                    ListCommand::TextSearch => ListAction::TextSearch,
+                    ListCommand::TagBrowser => ListAction::TagBrowser,
+                    ListCommand::Cloud => ListAction::Cloud,
+                    ListCommand::Stats => ListAction::Stats,
+                    ListCommand::RandomNote => ListAction::RandomNote,
+                    ListCommand::MarkMerge => ListAction::MarkMerge,
+                    ListCommand::MergeInto => ListAction::MergeInto,
+                    ListCommand::ToggleMark => ListAction::ToggleMark,
+                    ListCommand::BulkTag => ListAction::BulkTag,
+                    ListCommand::CopyNote => ListAction::CopyNote,
+                    ListCommand::PasteNote => ListAction::PasteNote,
+                    ListCommand::PreviewDown => ListAction::PreviewDown,
+                    ListCommand::PreviewUp => ListAction::PreviewUp,
+                    ListCommand::MoveNoteDown => ListAction::MoveNoteDown,
+                    ListCommand::MoveNoteUp => ListAction::MoveNoteUp,
+                    ListCommand::FocusNotes => ListAction::FocusPane(ListPane::Notes),
+                    ListCommand::FocusPreview => ListAction::FocusPane(ListPane::Preview),
+                    ListCommand::ToggleCheckbox => ListAction::ToggleCheckbox,
+                    ListCommand::SetMark => ListAction::SetMark,
+                    ListCommand::JumpToMark => ListAction::JumpToMark,
+                    ListCommand::ListMarks => ListAction::ListMarks,
+                    ListCommand::RecentNotes => ListAction::RecentNotes,
+                    ListCommand::BrokenLinks => ListAction::BrokenLinks,
+                    ListCommand::LongNotes => ListAction::LongNotes,
+                    ListCommand::SplitNote => ListAction::SplitNote,
+                    ListCommand::History => ListAction::History,
+                    ListCommand::CycleSort => ListAction::CycleSort,
+                    ListCommand::Archive => ListAction::Archive,
+                    ListCommand::ToggleArchived => ListAction::ToggleArchived,
+                    ListCommand::Color => ListAction::Color,
+                    ListCommand::Undo => ListAction::Undo,
+                    ListCommand::Redo => ListAction::Redo,
+                };
+                Some(Action::List(list_action))
+            }
+            Screen::Form => {
+                if self.wikilink_selected.is_some() {
+                    let wikilink_action = match key.code {
+                        KeyCode::Up => Some(WikilinkAction::MoveUp),
+                        KeyCode::Down => Some(WikilinkAction::MoveDown),
+                        KeyCode::Enter => Some(WikilinkAction::Insert),
+                        KeyCode::Esc => Some(WikilinkAction::Close),
+                        _ => None,
+                    };
+                    if let Some(wikilink_action) = wikilink_action {
+                        return Some(Action::Wikilink(wikilink_action));
+                    }
+                }
+
+                let form_action =
+                    match lookup_sequence(&self.keymap.form, &mut self.pending_keys, key) {
+                        Lookup::Matched(FormCommand::Save) => FormAction::Save,
+                        Lookup::Matched(FormCommand::ToggleInput) => FormAction::ToggleInput,
+                        Lookup::Matched(FormCommand::ToggleToolbar) => FormAction::ToggleToolbar,
+                        Lookup::Matched(FormCommand::Exit) => FormAction::Exit,
+                        Lookup::Matched(FormCommand::Bold) => FormAction::Bold,
+                        Lookup::Matched(FormCommand::Italic) => FormAction::Italic,
+                        Lookup::Matched(FormCommand::EmojiPicker)
+                            if matches!(self.focused_input, FocusedInput::Content) =>
+                        {
+                            FormAction::EmojiPicker
+                        }
+                        Lookup::Matched(FormCommand::EmojiPicker) => return None,
+                        Lookup::Matched(FormCommand::SplitNote) => FormAction::SplitNote,
+                        Lookup::Matched(FormCommand::PreviousDay) => FormAction::PreviousDay,
+                        Lookup::Matched(FormCommand::NextDay) => FormAction::NextDay,
+                        Lookup::Matched(FormCommand::PreviousNote) => FormAction::PreviousNote,
+                        Lookup::Matched(FormCommand::NextNote) => FormAction::NextNote,
+                        Lookup::Matched(FormCommand::SetMark) => FormAction::SetMark,
+                        Lookup::Matched(FormCommand::JumpToMark) => FormAction::JumpToMark,
+                        Lookup::Matched(FormCommand::ListMarks) => FormAction::ListMarks,
+                        Lookup::Pending => return None,
+                        Lookup::NoMatch => match (key.modifiers, key.code) {
+                            (KeyModifiers::ALT, KeyCode::Char(c))
+                                if self.scripts.as_ref().is_some_and(|s| s.has_command(c)) =>
+                            {
+                                FormAction::RunCommand(c)
+                            }
+                            _ => FormAction::UpdateInput(event),
+                        },
+                    };
+                Some(Action::Form(form_action))
+            }
             Screen::ExitConfirm => match key.code {
                 KeyCode::Esc | KeyCode::Char('n') => Some(Action::Exit(ExitAction::Cancel)),
                 KeyCode::Char('y') => Some(Action::Exit(ExitAction::Confirm)),
                 _ => None,
             },
+            Screen::Passphrase(_) => match key.code {
+                KeyCode::Enter => Some(Action::Passphrase(PassphraseAction::Confirm)),
+                KeyCode::Esc => Some(Action::Passphrase(PassphraseAction::Cancel)),
+                _ => Some(Action::Passphrase(PassphraseAction::UpdateInput(event))),
+            },
+            Screen::AdvancedSearch => match key.code {
+                KeyCode::Char('a') if key.modifiers == KeyModifiers::ALT => {
+                    Some(Action::AdvancedSearch(AdvancedSearchAction::ToggleAccents))
+                }
+                KeyCode::Esc => Some(Action::AdvancedSearch(AdvancedSearchAction::Exit)),
+                KeyCode::Tab => Some(Action::AdvancedSearch(AdvancedSearchAction::ToggleInput)),
+                KeyCode::Up => Some(Action::AdvancedSearch(AdvancedSearchAction::MoveUp)),
+                KeyCode::Down => Some(Action::AdvancedSearch(AdvancedSearchAction::MoveDown)),
+                KeyCode::Enter => Some(Action::AdvancedSearch(AdvancedSearchAction::Open)),
+                KeyCode::Char(c)
+                    if !c.is_ascii_digit()
+                        && !matches!(self.advanced_search.focused_input, SearchField::Query) =>
+                {
+                    None
+                }
+                _ => Some(Action::AdvancedSearch(AdvancedSearchAction::UpdateInput(
+                    event,
+                ))),
+            },
+            Screen::DuplicateTitle => match key.code {
+                KeyCode::Char('o') => Some(Action::Duplicate(DuplicateAction::OpenExisting)),
+                KeyCode::Char('r') | KeyCode::Esc => {
+                    Some(Action::Duplicate(DuplicateAction::Rename))
+                }
+                KeyCode::Char('s') if !self.config.notes.unique_titles => {
+                    Some(Action::Duplicate(DuplicateAction::SaveAnyway))
+                }
+                _ => None,
+            },
+            Screen::TagBrowser => match key.code {
+                KeyCode::Up | KeyCode::Char('k') => {
+                    Some(Action::TagBrowser(TagBrowserAction::MoveUp))
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    Some(Action::TagBrowser(TagBrowserAction::MoveDown))
+                }
+                KeyCode::Right => Some(Action::TagBrowser(TagBrowserAction::Expand)),
+                KeyCode::Left => Some(Action::TagBrowser(TagBrowserAction::Collapse)),
+                KeyCode::Enter => Some(Action::TagBrowser(TagBrowserAction::Open)),
+                KeyCode::Esc | KeyCode::Char('q') => {
+                    Some(Action::TagBrowser(TagBrowserAction::Exit))
+                }
+                _ => None,
+            },
+            Screen::Cloud => match key.code {
+                KeyCode::Left | KeyCode::Up | KeyCode::Char('h' | 'k') => {
+                    Some(Action::Cloud(CloudAction::Previous))
+                }
+                KeyCode::Right | KeyCode::Down | KeyCode::Char('l' | 'j') => {
+                    Some(Action::Cloud(CloudAction::Next))
+                }
+                KeyCode::Tab => Some(Action::Cloud(CloudAction::ToggleMode)),
+                KeyCode::Enter => Some(Action::Cloud(CloudAction::Open)),
+                KeyCode::Esc | KeyCode::Char('q') => Some(Action::Cloud(CloudAction::Exit)),
+                _ => None,
+            },
+            Screen::MergeConfirm => match key.code {
+                KeyCode::Char('y') | KeyCode::Enter => Some(Action::Merge(MergeAction::Confirm)),
+                KeyCode::Char('n') | KeyCode::Esc => Some(Action::Merge(MergeAction::Cancel)),
+                _ => None,
+            },
+            Screen::Stats => match key.code {
+                KeyCode::Esc | KeyCode::Char('q') => Some(Action::Stats(StatsAction::Exit)),
+                _ => None,
+            },
+            Screen::TextSearch => match key.code {
+                KeyCode::Esc => Some(Action::TextSearch(TextSearchAction::Cancel)),
+                KeyCode::Enter => Some(Action::TextSearch(TextSearchAction::Jump)),
+                KeyCode::Up => Some(Action::TextSearch(TextSearchAction::MoveUp)),
+                KeyCode::Down => Some(Action::TextSearch(TextSearchAction::MoveDown)),
+                _ => Some(Action::TextSearch(TextSearchAction::UpdateInput(event))),
+            },
+            Screen::QuickSwitcher => match key.code {
+                KeyCode::Esc => Some(Action::Switcher(SwitcherAction::Cancel)),
+                KeyCode::Enter => Some(Action::Switcher(SwitcherAction::Jump)),
+                KeyCode::Up => Some(Action::Switcher(SwitcherAction::MoveUp)),
+                KeyCode::Down => Some(Action::Switcher(SwitcherAction::MoveDown)),
+                _ => Some(Action::Switcher(SwitcherAction::UpdateInput(event))),
+            },
+            Screen::RecentNotes => match key.code {
+                KeyCode::Esc | KeyCode::Char('q') => Some(Action::Recent(RecentAction::Cancel)),
+                KeyCode::Enter => Some(Action::Recent(RecentAction::Open)),
+                KeyCode::Up | KeyCode::Char('k') => Some(Action::Recent(RecentAction::MoveUp)),
+                KeyCode::Down | KeyCode::Char('j') => Some(Action::Recent(RecentAction::MoveDown)),
+                _ => None,
+            },
+            Screen::History => match key.code {
+                KeyCode::Esc | KeyCode::Char('q') => Some(Action::History(HistoryAction::Close)),
+                KeyCode::Enter => Some(Action::History(HistoryAction::Compare)),
+                KeyCode::Up | KeyCode::Char('k') => Some(Action::History(HistoryAction::MoveUp)),
+                KeyCode::Down | KeyCode::Char('j') => {
+                    Some(Action::History(HistoryAction::MoveDown))
+                }
+                _ => None,
+            },
+            Screen::RevisionDiff => match key.code {
+                KeyCode::Esc | KeyCode::Char('q') => Some(Action::Diff(DiffAction::Back)),
+                KeyCode::Down | KeyCode::Char('j') => Some(Action::Diff(DiffAction::Down)),
+                KeyCode::Up | KeyCode::Char('k') => Some(Action::Diff(DiffAction::Up)),
+                KeyCode::PageDown | KeyCode::Char(' ') => Some(Action::Diff(DiffAction::PageDown)),
+                KeyCode::PageUp => Some(Action::Diff(DiffAction::PageUp)),
+                KeyCode::Char('r') => Some(Action::Diff(DiffAction::Restore)),
+                _ => None,
+            },
+            Screen::SplitNote => match key.code {
+                KeyCode::Esc | KeyCode::Char('q') => Some(Action::Split(SplitAction::Cancel)),
+                KeyCode::Enter => Some(Action::Split(SplitAction::Split)),
+                KeyCode::Down | KeyCode::Char('j') => Some(Action::Split(SplitAction::Down)),
+                KeyCode::Up | KeyCode::Char('k') => Some(Action::Split(SplitAction::Up)),
+                KeyCode::PageDown => Some(Action::Split(SplitAction::PageDown)),
+                KeyCode::PageUp => Some(Action::Split(SplitAction::PageUp)),
+                _ => None,
+            },
+            Screen::LongNotes => match key.code {
+                KeyCode::Esc | KeyCode::Char('q') => {
+                    Some(Action::LongNotes(LongNotesAction::Close))
+                }
+                KeyCode::Enter => Some(Action::LongNotes(LongNotesAction::Select)),
+                KeyCode::Char('s') => Some(Action::LongNotes(LongNotesAction::Split)),
+                KeyCode::Up | KeyCode::Char('k') => {
+                    Some(Action::LongNotes(LongNotesAction::MoveUp))
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    Some(Action::LongNotes(LongNotesAction::MoveDown))
+                }
+                _ => None,
+            },
+            Screen::BrokenLinks => match key.code {
+                KeyCode::Esc | KeyCode::Char('q') => {
+                    Some(Action::BrokenLinks(BrokenLinksAction::Close))
+                }
+                KeyCode::Enter => Some(Action::BrokenLinks(BrokenLinksAction::Open)),
+                KeyCode::Up | KeyCode::Char('k') => {
+                    Some(Action::BrokenLinks(BrokenLinksAction::MoveUp))
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    Some(Action::BrokenLinks(BrokenLinksAction::MoveDown))
+                }
+                _ => None,
+            },
+            Screen::Marks => match key.code {
+                KeyCode::Esc => Some(Action::Mark(MarkAction::Close)),
+                KeyCode::Enter => Some(Action::Mark(MarkAction::JumpToSelected)),
+                KeyCode::Up => Some(Action::Mark(MarkAction::MoveUp)),
+                KeyCode::Down => Some(Action::Mark(MarkAction::MoveDown)),
+                KeyCode::Char(name) if name.is_ascii_alphabetic() => {
+                    Some(Action::Mark(MarkAction::Jump(name)))
+                }
+                _ => None,
+            },
+            Screen::JournalEntry => match key.code {
+                KeyCode::Esc => Some(Action::Journal(JournalAction::Cancel)),
+                KeyCode::Enter => Some(Action::Journal(JournalAction::Append)),
+                _ => Some(Action::Journal(JournalAction::UpdateInput(event))),
+            },
+            Screen::PdfExport => match key.code {
+                KeyCode::Esc => Some(Action::Pdf(PdfAction::Cancel)),
+                KeyCode::Enter => Some(Action::Pdf(PdfAction::Export)),
+                _ => Some(Action::Pdf(PdfAction::UpdateInput(event))),
+            },
+            Screen::BulkTag => match key.code {
+                KeyCode::Esc => Some(Action::BulkTag(BulkTagAction::Cancel)),
+                KeyCode::Enter => Some(Action::BulkTag(BulkTagAction::Apply)),
+                KeyCode::Tab => Some(Action::BulkTag(BulkTagAction::Complete)),
+                _ => Some(Action::BulkTag(BulkTagAction::UpdateInput(event))),
+            },
+            Screen::EmojiPicker => match key.code {
+                KeyCode::Esc => Some(Action::Emoji(EmojiAction::Cancel)),
+                KeyCode::Enter => Some(Action::Emoji(EmojiAction::Insert)),
+                KeyCode::Up => Some(Action::Emoji(EmojiAction::MoveUp)),
+                KeyCode::Down => Some(Action::Emoji(EmojiAction::MoveDown)),
+                _ => Some(Action::Emoji(EmojiAction::UpdateInput(event))),
+            },
+            Screen::RestoreDraft => match key.code {
+                KeyCode::Char('r') | KeyCode::Enter => Some(Action::Draft(DraftAction::Restore)),
+                KeyCode::Char('d') | KeyCode::Esc => Some(Action::Draft(DraftAction::Discard)),
+                _ => None,
+            },
         }
     }
 
@@ -150,32 +1841,228 @@ impl App {
         match action {
             Action::List(list_action) => match list_action {
                 ListAction::Quit => {
-                    self.current_screen = Screen::ExitConfirm;
+                    self.request_quit();
+                }
+                ListAction::MoveUp(count) => {
+                    self.move_selection(|app| {
+                        for _ in 0..count.min(app.notes.items.len()) {
+                            app.notes.state.select_previous();
+                        }
+                    });
+                }
+                ListAction::MoveDown(count) => {
+                    self.move_selection(|app| {
+                        for _ in 0..count.min(app.notes.items.len()) {
+                            app.notes.state.select_next();
+                        }
+                    });
+                }
+                ListAction::PageUp => {
+                    self.move_selection(|app| app.page_list(false));
+                }
+                ListAction::PageDown => {
+                    self.move_selection(|app| app.page_list(true));
+                }
+                ListAction::FirstNote => {
+                    self.move_selection(|app| app.notes.state.select_first());
+                }
+                ListAction::LastNote => {
+                    self.move_selection(|app| app.notes.state.select_last());
+                }
+                ListAction::PreviewDown => {
+                    let lines = self
+                        .selected_note()
+                        .map_or(0, |n| n.content.split('\n').count());
+                    self.preview_cursor = match self.preview_cursor {
+                        Some(line) => Some((line + 1).min(lines.saturating_sub(1))),
+                        None => Some(0),
+                    };
+                }
+                ListAction::PreviewUp => {
+                    self.preview_cursor = Some(self.preview_cursor.unwrap_or(0).saturating_sub(1));
+                }
+                ListAction::MoveNoteDown => {
+                    self.move_note(true);
+                }
+                ListAction::MoveNoteUp => {
+                    self.move_note(false);
+                }
+                ListAction::SetMark => {
+                    self.pending_mark = Some(MarkOp::Set);
+                }
+                ListAction::JumpToMark => {
+                    self.pending_mark = Some(MarkOp::Jump);
+                }
+                ListAction::ListMarks => {
+                    self.open_marks();
+                }
+                ListAction::RecentNotes => {
+                    if self.recent_notes().is_empty() {
+                        self.notify("No recently viewed notes", Level::Info, TOAST_DURATION);
+                    } else {
+                        self.recent_selected = 0;
+                        self.set_screen(Screen::RecentNotes);
+                    }
+                }
+                ListAction::BrokenLinks => {
+                    self.open_broken_links();
                 }
-                ListAction::MoveUp => {
-                    self.notes.state.select_previous();
+                ListAction::LongNotes => {
+                    self.open_long_notes();
                 }
-                ListAction::MoveDown => {
-                    self.notes.state.select_next();
+                ListAction::SplitNote => {
+                    self.open_split_view();
+                }
+                ListAction::History => {
+                    self.open_history();
+                }
+                ListAction::CycleSort => {
+                    self.cycle_sort();
+                }
+                ListAction::Archive => {
+                    self.archive_note();
+                }
+                ListAction::ToggleArchived => {
+                    self.toggle_archived_view();
+                }
+                ListAction::Color => {
+                    self.pending_color = true;
+                    self.notify(
+                        "r red, g green, b blue, y yellow, n none, c show only this color",
+                        Level::Info,
+                        TOAST_DURATION,
+                    );
+                }
+                ListAction::SetColor(color) => {
+                    self.set_note_color(color);
+                }
+                ListAction::ToggleColorFilter => {
+                    self.toggle_color_filter();
+                }
+                ListAction::FocusPane(pane) => {
+                    self.focused_pane = pane;
+                    if pane == ListPane::Preview && self.preview_cursor.is_none() {
+                        self.preview_cursor = Some(0);
+                    }
+                }
+                ListAction::ToggleCheckbox => {
+                    self.toggle_checkbox();
+                }
+                ListAction::Undo => {
+                    self.undo();
+                }
+                ListAction::Redo => {
+                    self.redo();
                 }
                 ListAction::AddNote => {
-                    self.add_note();
+                    self.wikilink_selected = None;
+                    self.draft_dirty = false;
+                    self.unsaved_changes = false;
+                    self.editing_new = true;
                     self.title_input.reset();
                     self.content_input.reset();
-                    self.current_screen = Screen::Form;
+                    self.focused_input = FocusedInput::Title;
+                    self.set_screen(Screen::Form);
+                }
+                ListAction::DailyNote => {
+                    self.open_daily_note(self.dates.local_date(Utc::now()));
+                }
+                ListAction::JournalEntry => {
+                    self.journal_input.reset();
+                    self.set_screen(Screen::JournalEntry);
                 }
                 ListAction::DeleteNote => {
                     self.delete_note();
                 }
-                ListAction::SelectNote => {
-                    self.current_screen = Screen::Form;
-                    if let Some(index) = self.notes.state.selected() {
-                        let current_note = self.notes.items[index].clone();
-                        self.title_input = self.title_input.clone().with_value(current_note.title);
-                        self.content_input =
-                            self.content_input.clone().with_value(current_note.content);
+                ListAction::CopyNote => {
+                    self.copy_note();
+                }
+                ListAction::PasteNote => {
+                    self.paste_note();
+                }
+                ListAction::ExportArchive => {
+                    self.passphrase_input.reset();
+                    self.set_screen(Screen::Passphrase(ArchiveOp::Export));
+                }
+                ListAction::ImportArchive => {
+                    self.passphrase_input.reset();
+                    self.set_screen(Screen::Passphrase(ArchiveOp::Import));
+                }
+                ListAction::ExportZip => {
+                    self.export_zip();
+                }
+                ListAction::ImportZip => {
+                    self.import_zip();
+                }
+                ListAction::ExportPdf => {
+                    if let Some(note) = self.selected_note() {
+                        self.pdf_path_input = Input::new(pdf_file_name(&note.title));
+                        self.set_screen(Screen::PdfExport);
+                    }
+                }
+                ListAction::AdvancedSearch => {
+                    self.set_screen(Screen::AdvancedSearch);
+                    self.refresh_advanced_search();
+                }
+                ListAction::Filter => {
+                    self.open_list_filter();
+                }
+                ListAction::TextSearch => {
+                    self.open_text_search();
+                }
+                ListAction::TagBrowser => {
+                    self.set_screen(Screen::TagBrowser);
+                    self.refresh_tag_browser();
+                }
+                ListAction::Cloud => {
+                    self.set_screen(Screen::Cloud);
+                    self.refresh_cloud();
+                }
+                ListAction::RandomNote => {
+                    let current = self.notes.state.selected();
+                    if let Some(index) = random_index(self.notes.items.len(), current) {
+                        self.move_selection(|app| app.notes.state.select(Some(index)));
+                    }
+                }
+                ListAction::MarkMerge => {
+                    self.mark_merge_source();
+                }
+                ListAction::MergeInto => match self.merge_pair() {
+                    Ok(_) => self.set_screen(Screen::MergeConfirm),
+                    Err(e) => self.notify(e.to_string(), Level::Error, ERROR_TOAST_DURATION),
+                },
+                ListAction::ToggleMark => {
+                    if let Some(id) = self.selected_note().map(|note| note.id)
+                        && !self.marked.remove(&id)
+                    {
+                        self.marked.insert(id);
+                    }
+                }
+                ListAction::BulkTag => {
+                    if !self.bulk_tag_targets().is_empty() {
+                        match self.db.get_tag_names() {
+                            Ok(tags) => {
+                                self.tag_editor.open(tags);
+                                self.set_screen(Screen::BulkTag);
+                            }
+                            Err(e) => {
+                                self.notify(
+                                    format!("Failed to load tags: {e}"),
+                                    Level::Error,
+                                    ERROR_TOAST_DURATION,
+                                );
+                            }
+                        }
                     }
                 }
+                ListAction::Stats => {
+                    self.set_screen(Screen::Stats);
+                    self.refresh_stats();
+                }
+                ListAction::SelectNote => {
+                    self.set_screen(Screen::Form);
+                    self.load_form();
+                }
             },
             Action::Form(form_action) => match form_action {
                 FormAction::Save => {
@@ -184,75 +2071,828 @@ impl App {
                 FormAction::ToggleInput => {
                     self.toggle_input();
                 }
+                FormAction::ToggleToolbar => {
+                    self.show_toolbar = !self.show_toolbar;
+                }
                 FormAction::UpdateInput(event) => {
+                    self.draft_dirty = true;
+                    self.unsaved_changes = true;
                     match self.focused_input {
                         FocusedInput::Title => {
                             self.title_input.handle_event(&event);
                         }
                         FocusedInput::Content => {
                             self.content_input.handle_event(&event);
+                            self.update_wikilink_picker();
                         }
                     };
                 }
+                FormAction::Bold | FormAction::Italic => {
+                    let marker = match form_action {
+                        FormAction::Bold => "**",
+                        _ => "*",
+                    };
+                    self.draft_dirty = true;
+                    self.unsaved_changes = true;
+                    let input = match self.focused_input {
+                        FocusedInput::Title => &mut self.title_input,
+                        FocusedInput::Content => &mut self.content_input,
+                    };
+                    text_utils::wrap_selection(input, marker, marker);
+                }
+                FormAction::SplitNote => {
+                    self.split_note();
+                }
+                FormAction::PreviousDay => {
+                    self.step_daily_note(-1);
+                }
+                FormAction::PreviousNote => {
+                    self.step_note(false);
+                }
+                FormAction::NextNote => {
+                    self.step_note(true);
+                }
+                FormAction::NextDay => {
+                    self.step_daily_note(1);
+                }
+                FormAction::SetMark => {
+                    self.pending_mark = Some(MarkOp::Set);
+                }
+                FormAction::JumpToMark => {
+                    self.pending_mark = Some(MarkOp::Jump);
+                }
+                FormAction::ListMarks => {
+                    self.open_marks();
+                }
+                FormAction::EmojiPicker => {
+                    self.emoji_picker.open();
+                    self.set_screen(Screen::EmojiPicker);
+                }
+                FormAction::RunCommand(key) => {
+                    self.draft_dirty = true;
+                    self.unsaved_changes = true;
+                    self.run_command(key);
+                }
                 FormAction::Exit => {
-                    self.current_screen = Screen::List;
+                    if self.pending_drafts.is_empty() {
+                        self.set_screen(Screen::List);
+                    } else {
+                        self.set_screen(Screen::RestoreDraft);
+                    }
                 }
             },
             Action::Exit(exit_action) => match exit_action {
-                ExitAction::Confirm => self.should_quit = true,
-                ExitAction::Cancel => self.current_screen = Screen::List,
+                ExitAction::Confirm => {
+                    self.quit();
+                }
+                ExitAction::Cancel => self.set_screen(Screen::List),
             },
-        }
-        None
-    }
+            Action::Passphrase(passphrase_action) => match passphrase_action {
+                PassphraseAction::Confirm => {
+                    if let Screen::Passphrase(op) = self.current_screen {
+                        self.run_archive_op(op);
+                    }
+                    self.passphrase_input.reset();
+                    self.set_screen(Screen::List);
+                }
+                PassphraseAction::Cancel => {
+                    self.passphrase_input.reset();
+                    self.set_screen(Screen::List);
+                }
+                PassphraseAction::UpdateInput(event) => {
+                    self.passphrase_input.handle_event(&event);
+                }
+            },
+            Action::Draft(draft_action) => {
+                let draft = self.pending_drafts.remove(0);
+                if self.pending_drafts.is_empty() {
+                    self.set_screen(Screen::List);
+                }
 
-    fn render_list(&mut self, frame: &mut Frame) {
-        let layout = Layout::default()
+                match draft_action {
+                    DraftAction::Restore => {
+                        let index = self.notes.items.iter().position(|n| n.id == draft.note_id);
+                        if index.is_some() || draft.note_id == NEW_NOTE_DRAFT_ID {
+                            self.editing_new = index.is_none();
+                            if index.is_some() {
+                                self.notes.state.select(index);
+                            }
+                            self.title_input = self.title_input.clone().with_value(draft.title);
+                            self.content_input =
+                                self.content_input.clone().with_value(draft.content);
+                            self.draft_dirty = true;
+                            self.unsaved_changes = true;
+                            self.set_screen(Screen::Form);
+                        }
+                    }
+                    DraftAction::Discard => {
+                        if let Err(e) = self.db.delete_draft(draft.note_id) {
+                            self.notify(
+                                format!("Discard failed: {e}"),
+                                Level::Error,
+                                ERROR_TOAST_DURATION,
+                            );
+                        }
+                    }
+                }
+            }
+            Action::Duplicate(duplicate_action) => match duplicate_action {
+                DuplicateAction::OpenExisting => {
+                    let index = self.duplicate_of.take().and_then(|existing| {
+                        self.notes.items.iter().position(|n| n.id == existing.id)
+                    });
+                    self.set_screen(Screen::Form);
+                    if index.is_some() {
+                        self.notes.state.select(index);
+                        self.load_form();
+                    }
+                }
+                DuplicateAction::Rename => {
+                    self.duplicate_of = None;
+                    self.focused_input = FocusedInput::Title;
+                    self.set_screen(Screen::Form);
+                }
+                DuplicateAction::SaveAnyway => {
+                    self.duplicate_of = None;
+                    self.set_screen(Screen::Form);
+                    self.insert_new_note(false);
+                }
+            },
+            Action::Navigate(navigate_action) => match navigate_action {
+                NavigateAction::Back => self.navigate_back(),
+                NavigateAction::Forward => self.navigate_forward(),
+            },
+            Action::AdvancedSearch(search_action) => match search_action {
+                AdvancedSearchAction::ToggleInput => {
+                    self.advanced_search.focused_input = match self.advanced_search.focused_input {
+                        SearchField::Query => SearchField::Min,
+                        SearchField::Min => SearchField::Max,
+                        SearchField::Max => SearchField::Query,
+                    };
+                }
+                AdvancedSearchAction::ToggleAccents => {
+                    let options = &mut self.advanced_search.options;
+                    options.fold_accents = !options.fold_accents;
+                    self.refresh_advanced_search();
+                }
+                AdvancedSearchAction::UpdateInput(event) => {
+                    let search = &mut self.advanced_search;
+                    match search.focused_input {
+                        SearchField::Query => search.query_input.handle_event(&event),
+                        SearchField::Min => search.min_input.handle_event(&event),
+                        SearchField::Max => search.max_input.handle_event(&event),
+                    };
+                    self.refresh_advanced_search();
+                }
+                AdvancedSearchAction::MoveUp => {
+                    self.advanced_search.results.state.select_previous();
+                }
+                AdvancedSearchAction::MoveDown => {
+                    self.advanced_search.results.state.select_next();
+                }
+                AdvancedSearchAction::Open => {
+                    let results = &self.advanced_search.results;
+                    let index = results
+                        .state
+                        .selected()
+                        .and_then(|selected| results.items.get(selected))
+                        .and_then(|note| self.notes.items.iter().position(|n| n.id == note.id));
+
+                    if let Some(index) = index {
+                        self.notes.state.select(Some(index));
+                        return Some(Action::List(ListAction::SelectNote));
+                    }
+                }
+                AdvancedSearchAction::Exit => {
+                    self.set_screen(Screen::List);
+                }
+            },
+            Action::TagBrowser(tag_action) => {
+                let browser = &mut self.tag_browser;
+                match tag_action {
+                    TagBrowserAction::MoveUp => {
+                        browser.selected = browser.selected.saturating_sub(1);
+                    }
+                    TagBrowserAction::MoveDown => {
+                        if browser.selected + 1 < browser.row_count() {
+                            browser.selected += 1;
+                        }
+                    }
+                    TagBrowserAction::Expand => {
+                        if let Some((node, None)) = browser.locate() {
+                            browser.nodes[node].expanded = true;
+                        }
+                    }
+                    TagBrowserAction::Collapse => {
+                        if let Some((node, _)) = browser.locate() {
+                            browser.nodes[node].expanded = false;
+                            browser.selected = browser.node_row(node);
+                        }
+                    }
+                    TagBrowserAction::Open => match browser.locate() {
+                        Some((node, None)) => browser.nodes[node].expanded = true,
+                        Some((node, Some(note))) => {
+                            let id = browser.nodes[node].notes[note].id;
+                            if let Some(index) = self.notes.items.iter().position(|n| n.id == id) {
+                                self.notes.state.select(Some(index));
+                                return Some(Action::List(ListAction::SelectNote));
+                            }
+                        }
+                        None => {}
+                    },
+                    TagBrowserAction::Exit => self.set_screen(Screen::List),
+                }
+            }
+            Action::Cloud(cloud_action) => match cloud_action {
+                CloudAction::Previous => {
+                    self.cloud.selected = self.cloud.selected.saturating_sub(1);
+                }
+                CloudAction::Next => {
+                    let cloud = &mut self.cloud;
+                    if cloud.selected + 1 < cloud.entries.len() {
+                        cloud.selected += 1;
+                    }
+                }
+                CloudAction::ToggleMode => {
+                    self.cloud.mode = match self.cloud.mode {
+                        CloudMode::Tags => CloudMode::Words,
+                        CloudMode::Words => CloudMode::Tags,
+                    };
+                    self.cloud.selected = 0;
+                    self.refresh_cloud();
+                }
+                CloudAction::Open => {
+                    self.open_cloud_entry();
+                }
+                CloudAction::Exit => {
+                    self.set_screen(Screen::List);
+                }
+            },
+            Action::Merge(merge_action) => {
+                if let MergeAction::Confirm = merge_action {
+                    self.merge_notes();
+                }
+                self.set_screen(Screen::List);
+            }
+            Action::Stats(StatsAction::Exit) => {
+                self.set_screen(Screen::List);
+            }
+            Action::Wikilink(wikilink_action) => match wikilink_action {
+                WikilinkAction::MoveUp => {
+                    self.wikilink_selected = self.wikilink_selected.map(|s| s.saturating_sub(1));
+                }
+                WikilinkAction::MoveDown => {
+                    let last = self.wikilink_matches().len().saturating_sub(1);
+                    self.wikilink_selected = self.wikilink_selected.map(|s| (s + 1).min(last));
+                }
+                WikilinkAction::Insert => {
+                    self.insert_wikilink();
+                }
+                WikilinkAction::Close => {
+                    self.wikilink_selected = None;
+                }
+            },
+            Action::Filter(filter_action) => match filter_action {
+                FilterAction::UpdateInput(event) => {
+                    if let Some(filter) = &mut self.list_filter {
+                        filter.input.handle_event(&event);
+                        filter.update_regex();
+                        self.refresh_list_filter();
+                    }
+                }
+                FilterAction::ToggleRegex => {
+                    if let Some(filter) = &mut self.list_filter {
+                        let value = match filter.pattern() {
+                            Some(pattern) => pattern.to_string(),
+                            None => format!("{}{}", ListFilter::REGEX_PREFIX, filter.input.value()),
+                        };
+                        filter.input = Input::new(value);
+                        filter.update_regex();
+                        self.refresh_list_filter();
+                    }
+                }
+                FilterAction::MoveUp => {
+                    self.move_selection(|app| app.notes.state.select_previous());
+                }
+                FilterAction::MoveDown => {
+                    self.move_selection(|app| app.notes.state.select_next());
+                }
+                FilterAction::Apply => {
+                    if let Some(filter) = &mut self.list_filter {
+                        filter.typing = false;
+                    }
+                }
+                FilterAction::Clear => {
+                    self.clear_list_filter();
+                }
+            },
+            Action::TextSearch(search_action) => match search_action {
+                TextSearchAction::UpdateInput(event) => {
+                    self.text_search.input.handle_event(&event);
+                    self.refresh_text_search();
+                }
+                TextSearchAction::MoveUp => {
+                    let search = &mut self.text_search;
+                    search.selected = search.selected.saturating_sub(1);
+                }
+                TextSearchAction::MoveDown => {
+                    let search = &mut self.text_search;
+                    if search.selected + 1 < search.results.len() {
+                        search.selected += 1;
+                    }
+                }
+                TextSearchAction::Jump => {
+                    self.jump_to_search_result();
+                }
+                TextSearchAction::Cancel => {
+                    self.set_screen(Screen::List);
+                }
+            },
+            Action::Switcher(switcher_action) => match switcher_action {
+                SwitcherAction::Open => {
+                    self.open_quick_switcher();
+                }
+                SwitcherAction::UpdateInput(event) => {
+                    self.quick_switcher.input.handle_event(&event);
+                    self.refresh_quick_switcher();
+                }
+                SwitcherAction::MoveUp => {
+                    let switcher = &mut self.quick_switcher;
+                    switcher.selected = switcher.selected.saturating_sub(1);
+                }
+                SwitcherAction::MoveDown => {
+                    let switcher = &mut self.quick_switcher;
+                    if switcher.selected + 1 < switcher.results.len() {
+                        switcher.selected += 1;
+                    }
+                }
+                SwitcherAction::Jump => {
+                    let switcher = &self.quick_switcher;
+                    if let Some(&index) = switcher.results.get(switcher.selected) {
+                        self.current_screen = switcher.return_to;
+                        self.move_selection(|app| app.notes.state.select(Some(index)));
+                        self.set_screen(Screen::List);
+                    }
+                }
+                SwitcherAction::Cancel => {
+                    self.current_screen = self.quick_switcher.return_to;
+                }
+            },
+            Action::Journal(journal_action) => match journal_action {
+                JournalAction::UpdateInput(event) => {
+                    self.journal_input.handle_event(&event);
+                }
+                JournalAction::Append => {
+                    self.append_journal_entry();
+                }
+                JournalAction::Cancel => {
+                    self.set_screen(Screen::List);
+                }
+            },
+            Action::Pdf(pdf_action) => match pdf_action {
+                PdfAction::UpdateInput(event) => {
+                    self.pdf_path_input.handle_event(&event);
+                }
+                PdfAction::Export => {
+                    self.export_pdf();
+                }
+                PdfAction::Cancel => {
+                    self.set_screen(Screen::List);
+                }
+            },
+            Action::Recent(recent_action) => match recent_action {
+                RecentAction::MoveUp => {
+                    self.recent_selected = self.recent_selected.saturating_sub(1);
+                }
+                RecentAction::MoveDown => {
+                    if self.recent_selected + 1 < self.recent_notes().len() {
+                        self.recent_selected += 1;
+                    }
+                }
+                RecentAction::Open => {
+                    if let Some(&(index, _)) = self.recent_notes().get(self.recent_selected) {
+                        self.current_screen = Screen::List;
+                        self.move_selection(|app| app.notes.state.select(Some(index)));
+                        self.set_screen(Screen::Form);
+                        self.load_form();
+                    }
+                }
+                RecentAction::Cancel => {
+                    self.set_screen(Screen::List);
+                }
+            },
+            Action::History(history_action) => match history_action {
+                HistoryAction::MoveUp => {
+                    self.history.selected = self.history.selected.saturating_sub(1);
+                }
+                HistoryAction::MoveDown => {
+                    if self.history.selected + 1 < self.history.revisions.len() {
+                        self.history.selected += 1;
+                    }
+                }
+                HistoryAction::Compare => {
+                    self.compare_revision();
+                }
+                HistoryAction::Close => {
+                    self.set_screen(Screen::List);
+                }
+            },
+            Action::Diff(diff_action) => {
+                let history = &mut self.history;
+                match diff_action {
+                    DiffAction::Down => history.scroll += 1,
+                    DiffAction::Up => history.scroll = history.scroll.saturating_sub(1),
+                    DiffAction::PageDown => history.scroll += history.page,
+                    DiffAction::PageUp => {
+                        history.scroll = history.scroll.saturating_sub(history.page);
+                    }
+                    DiffAction::Restore => self.restore_revision(),
+                    DiffAction::Back => self.set_screen(Screen::History),
+                }
+                // Past the end the scroll is clamped when drawn; clamp before it can pile up.
+                let history = &mut self.history;
+                history.scroll = history
+                    .scroll
+                    .min(history.diff.len().saturating_sub(history.page));
+            }
+            Action::Split(split_action) => {
+                let last_line = self
+                    .selected_note()
+                    .map_or(0, |note| note.content.matches('\n').count());
+                let view = &mut self.split_view;
+                match split_action {
+                    SplitAction::Down => view.line += 1,
+                    SplitAction::Up => view.line = view.line.saturating_sub(1),
+                    SplitAction::PageDown => view.line += view.page,
+                    SplitAction::PageUp => view.line = view.line.saturating_sub(view.page),
+                    SplitAction::Split => self.split_at_divider(),
+                    SplitAction::Cancel => self.set_screen(Screen::List),
+                }
+                // Both parts keep at least one line.
+                let view = &mut self.split_view;
+                view.line = view.line.clamp(1, last_line.max(1));
+            }
+            Action::LongNotes(long_action) => match long_action {
+                LongNotesAction::MoveUp => {
+                    self.long_selected = self.long_selected.saturating_sub(1);
+                }
+                LongNotesAction::MoveDown => {
+                    if self.long_selected + 1 < self.long_notes.len() {
+                        self.long_selected += 1;
+                    }
+                }
+                LongNotesAction::Select => {
+                    self.open_long_note(false);
+                }
+                LongNotesAction::Split => {
+                    self.open_long_note(true);
+                }
+                LongNotesAction::Close => {
+                    self.set_screen(Screen::List);
+                }
+            },
+            Action::BrokenLinks(broken_action) => match broken_action {
+                BrokenLinksAction::MoveUp => {
+                    self.broken_selected = self.broken_selected.saturating_sub(1);
+                }
+                BrokenLinksAction::MoveDown => {
+                    if self.broken_selected + 1 < self.broken_links.len() {
+                        self.broken_selected += 1;
+                    }
+                }
+                BrokenLinksAction::Open => {
+                    self.open_broken_link();
+                }
+                BrokenLinksAction::Close => {
+                    self.set_screen(Screen::List);
+                }
+            },
+            Action::Mark(mark_action) => match mark_action {
+                MarkAction::Set(name) => {
+                    self.set_mark(name);
+                }
+                MarkAction::Jump(name) => {
+                    self.close_marks();
+                    self.jump_to_mark(name);
+                }
+                MarkAction::MoveUp => {
+                    let popup = &mut self.marks_popup;
+                    popup.selected = popup.selected.saturating_sub(1);
+                }
+                MarkAction::MoveDown => {
+                    let popup = &mut self.marks_popup;
+                    if popup.selected + 1 < popup.marks.len() {
+                        popup.selected += 1;
+                    }
+                }
+                MarkAction::JumpToSelected => {
+                    let popup = &self.marks_popup;
+                    if let Some(name) = popup.marks.get(popup.selected).map(|mark| mark.name) {
+                        self.close_marks();
+                        self.jump_to_mark(name);
+                    }
+                }
+                MarkAction::Close => {
+                    self.close_marks();
+                }
+            },
+            Action::BulkTag(bulk_tag_action) => match bulk_tag_action {
+                BulkTagAction::UpdateInput(event) => {
+                    self.tag_editor.input.handle_event(&event);
+                }
+                BulkTagAction::Complete => {
+                    self.tag_editor.complete();
+                }
+                BulkTagAction::Apply => {
+                    self.apply_tag_edits();
+                }
+                BulkTagAction::Cancel => {
+                    self.set_screen(Screen::List);
+                }
+            },
+            Action::Emoji(emoji_action) => match emoji_action {
+                EmojiAction::UpdateInput(event) => {
+                    self.emoji_picker.input.handle_event(&event);
+                    self.emoji_picker.selected = 0;
+                }
+                EmojiAction::MoveUp => {
+                    self.emoji_picker.selected = self.emoji_picker.selected.saturating_sub(1);
+                }
+                EmojiAction::MoveDown => {
+                    if self.emoji_picker.selected + 1 < self.emoji_picker.matches().len() {
+                        self.emoji_picker.selected += 1;
+                    }
+                }
+                EmojiAction::Insert => {
+                    if let Some(emoji) = self.emoji_picker.pick() {
+                        self.draft_dirty = true;
+                        self.unsaved_changes = true;
+                        text_utils::insert_at_cursor(&mut self.content_input, emoji, 1);
+                        self.set_screen(Screen::Form);
+                    }
+                }
+                EmojiAction::Cancel => {
+                    self.set_screen(Screen::Form);
+                }
+            },
+        }
+        None
+    }
+
+    /// Switches screens, remembering where we came from for `navigate_back`. Prompts are
+    /// overlays rather than destinations, so opening or closing one isn't recorded.
+    fn set_screen(&mut self, screen: Screen) {
+        // Other screens read straight from the database, where the note still exists.
+        if screen.is_navigable() && screen != Screen::List {
+            self.flush_deletion();
+        }
+        let previous = std::mem::replace(&mut self.current_screen, screen);
+        if previous.is_navigable() && screen.is_navigable() && previous != screen {
+            self.push_history(previous);
+            self.screen_future.clear();
+        }
+    }
+
+    fn push_history(&mut self, screen: Screen) {
+        if self.screen_history.len() == SCREEN_HISTORY_LIMIT {
+            self.screen_history.pop_front();
+        }
+        self.screen_history.push_back(screen);
+    }
+
+    fn navigate_back(&mut self) {
+        let Some(screen) = self.screen_history.pop_back() else {
+            return;
+        };
+        let current = std::mem::replace(&mut self.current_screen, screen);
+        self.screen_future.push(current);
+        self.enter_screen();
+    }
+
+    fn navigate_forward(&mut self) {
+        let Some(screen) = self.screen_future.pop() else {
+            return;
+        };
+        let current = std::mem::replace(&mut self.current_screen, screen);
+        self.push_history(current);
+        self.enter_screen();
+    }
+
+    /// Refreshes the state a screen renders from when it is revisited through the history.
+    fn enter_screen(&mut self) {
+        match self.current_screen {
+            Screen::Form => self.load_form(),
+            Screen::AdvancedSearch => self.refresh_advanced_search(),
+            Screen::TagBrowser => self.refresh_tag_browser(),
+            Screen::Cloud => self.refresh_cloud(),
+            Screen::Stats => self.refresh_stats(),
+            _ => {}
+        }
+    }
+
+    fn load_form(&mut self) {
+        self.wikilink_selected = None;
+        self.editing_new = false;
+        self.draft_dirty = false;
+        self.unsaved_changes = false;
+        if let Some(index) = self.notes.state.selected() {
+            let current_note = self.notes.items[index].clone();
+            self.remember_viewed(current_note.id);
+            self.title_input = self.title_input.clone().with_value(current_note.title);
+            self.content_input = self.content_input.clone().with_value(current_note.content);
+            self.run_hook(Hook::OnOpen);
+        }
+    }
+
+    fn render_list(&mut self, frame: &mut Frame) {
+        let layout = Layout::default()
             .direction(ratatui::layout::Direction::Horizontal)
             .constraints(vec![Constraint::Percentage(30), Constraint::Min(1)])
             .split(frame.area());
 
+        let filter_height = if self.list_filter.is_some() { 3 } else { 0 };
         let inner_list_layout = Layout::default()
             .direction(Direction::Vertical)
-            .constraints(vec![Constraint::Min(1), Constraint::Length(1)])
+            .constraints(vec![
+                Constraint::Min(1),
+                Constraint::Length(filter_height),
+                Constraint::Length(1),
+            ])
             .split(layout[0]);
 
+        let (list_border, preview_border) = match self.focused_pane {
+            ListPane::Notes => (self.theme.focused_border(), Style::new().dim()),
+            ListPane::Preview => (Style::new().dim(), self.theme.focused_border()),
+        };
+        let name = if self.show_archived {
+            "Archived notes"
+        } else {
+            "My Notes"
+        };
+        let count = self.notes.items.len();
+        let title = match self.color_filter {
+            Some(color) => format!("{name} {} ({count})", color.symbol()),
+            None if self.show_archived || self.list_filter.is_some() => format!("{name} ({count})"),
+            None => name.to_string(),
+        };
         let block = Block::bordered()
-            .title(Line::raw("My Notes").centered())
-            .border_set(border::THICK);
+            .title(Line::raw(title).centered())
+            .title_bottom(
+                Line::from(format!(" {} ", self.sort_order.label()).dim()).right_aligned(),
+            )
+            .border_set(border::THICK)
+            .border_style(list_border);
 
+        let now = Utc::now();
+        self.list_geometry = ListGeometry {
+            rows: inner_list_layout[0].height.saturating_sub(2),
+            width: inner_list_layout[0].width.saturating_sub(4),
+            chips_own_line: frame.area().height >= TAG_CHIPS_OWN_LINE_HEIGHT,
+        };
         let notes_list_items = self
             .notes
             .items
             .iter()
-            .map(|note| note.title.clone())
+            .map(|note| self.list_item(note, now))
             .collect::<List>()
             .block(block)
-            .style(Style::new().white())
-            .highlight_style(Style::new().black().on_white())
+            .style(Style::new().fg(self.theme.text))
+            .highlight_style(self.theme.selection())
             .highlight_symbol(">>")
             .direction(ratatui::widgets::ListDirection::TopToBottom);
 
-        let note_details = self
+        let selected_note = self
             .notes
             .state
             .selected()
-            .and_then(|selected_index| self.notes.items.get(selected_index))
-            .map(|n| Paragraph::new(n.content.as_str()).block(Block::bordered()));
+            .and_then(|selected_index| self.notes.items.get(selected_index));
+        let width = layout[1].width.saturating_sub(2) as usize;
+        let visible = layout[1].height.saturating_sub(2) as usize;
+        // While filtering, the preview shows the content around the first match, as much as fills
+        // the pane. Focusing the preview brings back the whole note to scroll through.
+        let excerpt = selected_note
+            .zip(self.list_filter.as_ref())
+            .filter(|_| self.focused_pane == ListPane::Notes)
+            .and_then(|(n, filter)| {
+                let matched = filter.find_matches(&n.content, self.advanced_search.options);
+                Some(n.excerpt_around(matched.into_iter().next()?, width * visible / 2))
+            });
+        let note_details = selected_note.map(|n| {
+            let header = format!(
+                " Created {} · Updated {} ",
+                self.dates.absolute(n.created_at),
+                self.dates.relative(n.updated_at, now)
+            );
+            let text = excerpt.as_deref().unwrap_or(&n.content);
+            // Wrapped here rather than by the paragraph, so that the scroll position and the
+            // indicator count the rows actually drawn.
+            let rows = text_utils::soft_wrap(text, width);
+            let cursor = self
+                .preview_cursor
+                .filter(|_| excerpt.is_none())
+                .map(|cursor| cursor.min(n.content.matches('\n').count()));
+            let scroll = cursor
+                .and_then(|cursor| rows.iter().rposition(|&(line, _)| line == cursor))
+                .map_or(0, |last_row| (last_row + 1).saturating_sub(visible));
+            // Found in the whole text, so that a match wrapped over two rows is still
+            // highlighted on both.
+            let matches = self
+                .list_filter
+                .as_ref()
+                .map(|filter| filter.find_matches(text, self.advanced_search.options))
+                .unwrap_or_default();
+            let lines = rows
+                .iter()
+                .map(|&(line, row)| {
+                    let start = text_utils::offset_in(text, row);
+                    let row = highlight_ranges(row, start, &matches, self.theme.accent);
+                    if cursor == Some(line) {
+                        row.style(self.theme.selection())
+                    } else {
+                        row
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            let mut block = Block::bordered().title(header).border_style(preview_border);
+            if let Some(url) = &n.source_url {
+                block = block.title(
+                    Line::from(format!(" {url} ").fg(self.theme.accent).underlined())
+                        .right_aligned(),
+                );
+            }
+            if rows.len() > visible {
+                let last = (scroll + visible).min(rows.len());
+                block = block.title_bottom(
+                    Line::raw(format!(
+                        " {}–{last} of {} lines ({}%) ",
+                        scroll + 1,
+                        rows.len(),
+                        last * 100 / rows.len()
+                    ))
+                    .right_aligned(),
+                );
+            }
+            Paragraph::new(lines)
+                .scroll((scroll as u16, 0))
+                .block(block)
+        });
 
+        let keys = &self.keymap.list;
         let help_message = Line::from_iter([
-            "Esc/q".bold().yellow(),
+            self.theme.key(keys.describe(ListCommand::Quit)),
             " exit, ".to_span(),
-            "e".bold().yellow(),
+            self.theme.key(keys.describe(ListCommand::SelectNote)),
             " edit, ".to_span(),
-            "a".bold().yellow(),
+            self.theme.key(keys.describe(ListCommand::AddNote)),
             " add, ".to_span(),
-            "d".bold().red(),
+            self.theme
+                .destructive_key(&keys.describe(ListCommand::DeleteNote)),
             " delete".to_span(),
         ])
         .centered();
 
-        frame.render_widget(help_message, inner_list_layout[1]);
+        let status_line = if self.key_prefix_buffer.is_empty() && self.pending_keys.is_empty() {
+            self.toast_or(help_message)
+        } else {
+            let mut pending = self.key_prefix_buffer.clone();
+            if !self.pending_keys.is_empty() {
+                pending.push_str(&keymap::format_keys(&self.pending_keys));
+                pending.push('-');
+            }
+            Line::from(self.theme.key(pending)).right_aligned()
+        };
+
+        if self.notes.items.is_empty() && self.list_filter.is_none() && !self.show_archived {
+            let empty_layout = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(vec![Constraint::Min(1), Constraint::Length(1)])
+                .split(frame.area());
+            render_empty_state(frame, empty_layout[0]);
+            frame.render_widget(status_line, empty_layout[1]);
+            return;
+        }
+
+        if let Some(filter) = &self.list_filter {
+            let area = inner_list_layout[1];
+            let border = if filter.typing {
+                self.theme.focused_border()
+            } else {
+                Style::new().dim()
+            };
+            let mut block = Block::bordered().title(" Filter ").border_style(border);
+            if let Some(error) = &filter.error {
+                block = block
+                    .title(Line::from(format!(" {error} ").fg(self.theme.danger)).right_aligned());
+            }
+            let input = Paragraph::new(filter.input.value()).block(block);
+            frame.render_widget(input, area);
+            if filter.typing {
+                let x = filter.input.visual_cursor() as u16 + 1;
+                frame.set_cursor_position((area.x + x, area.y + 1));
+            }
+        }
+        frame.render_widget(status_line, inner_list_layout[2]);
         frame.render_stateful_widget(
             notes_list_items,
             inner_list_layout[0],
@@ -261,112 +2901,3844 @@ impl App {
         frame.render_widget(note_details, layout[1]);
     }
 
+    /// One note of the list as drawn in the current `ListGeometry`. Items take one or two rows,
+    /// and paging counts them with this same function.
+    fn list_item<'a>(&self, note: &'a Note, now: DateTime<Utc>) -> Text<'a> {
+        let mut details = vec![self.dates.relative(note.updated_at, now).dim()];
+        if note.color != NoteColor::None {
+            details.insert(0, Span::raw(format!("{} ", note.color.symbol())));
+        }
+        if self.merge_source == Some(note.id) {
+            details.push(" (merging)".fg(self.theme.accent));
+        }
+        if self.marked.contains(&note.id) {
+            details.push(" (marked)".fg(self.theme.accent));
+        }
+        let chips = tag_chips(&note.tags);
+
+        if self.config.notes.wrap_titles {
+            // The title goes on as far as the second row has room for beside the details, which
+            // are pushed to its right edge.
+            let width = self.list_geometry.width as usize;
+            let first = text_utils::soft_wrap(&note.title, width)[0].1;
+            details.extend(chips.map(|chips| [" ".into(), chips]).into_iter().flatten());
+            let details_width = details.iter().map(Span::width).sum::<usize>();
+            let room = width.saturating_sub(details_width + 1);
+            let rest = note.title[first.len()..].trim_start();
+            let rest = match text_utils::soft_wrap(rest, room).as_slice() {
+                [] | [_] => rest.to_string(),
+                _ => format!(
+                    "{}…",
+                    text_utils::soft_wrap(rest, room.saturating_sub(1))[0].1
+                ),
+            };
+            let padding = width.saturating_sub(Span::raw(&rest).width() + details_width);
+            let second = Line::from_iter(
+                [Span::raw(rest), Span::raw(" ".repeat(padding))]
+                    .into_iter()
+                    .chain(details),
+            );
+            return Text::from_iter([Line::raw(first), second]);
+        }
+
+        let mut line = Line::from_iter([note.title.to_span(), " ".to_span()]);
+        line.spans.extend(details);
+        let Some(chips) = chips else {
+            return Text::from(line);
+        };
+        if self.list_geometry.chips_own_line {
+            Text::from_iter([line, Line::from_iter(["  ".into(), chips])])
+        } else {
+            line.push_span(" ");
+            line.push_span(chips);
+            Text::from(line)
+        }
+    }
+
+    /// Moves the selection by as many notes as fit in the list, `forward` or back.
+    fn page_list(&mut self, forward: bool) {
+        let Some(selected) = self.notes.state.selected() else {
+            return;
+        };
+        let now = Utc::now();
+        let rows = self.list_geometry.rows.max(1) as usize;
+        let mut used = 0;
+        let mut target = selected;
+        loop {
+            let next = if forward {
+                target + 1
+            } else {
+                match target.checked_sub(1) {
+                    Some(next) => next,
+                    None => break,
+                }
+            };
+            let Some(note) = self.notes.items.get(next) else {
+                break;
+            };
+            used += self.list_item(note, now).height();
+            if used > rows && target != selected {
+                break;
+            }
+            target = next;
+        }
+        self.notes.state.select(Some(target));
+    }
+
     fn render_form(&self, frame: &mut Frame) {
-        let layout = Layout::default()
+        let [toolbar_area, title_area, content_area] = Layout::default()
             .direction(ratatui::layout::Direction::Vertical)
-            .constraints(vec![Constraint::Max(4), Constraint::Min(1)])
-            .split(frame.area());
+            .constraints(vec![
+                Constraint::Length(u16::from(self.show_toolbar)),
+                Constraint::Max(4),
+                Constraint::Min(1),
+            ])
+            .areas(frame.area());
 
         let inner_content_layout = Layout::default()
             .direction(ratatui::layout::Direction::Vertical)
             .constraints(vec![Constraint::Min(1), Constraint::Max(1)])
-            .split(layout[1]);
+            .split(content_area);
 
+        let keys = &self.keymap.form;
         let help_message = Line::from_iter([
-            "Esc".bold().yellow(),
+            self.theme.key(keys.describe(FormCommand::Exit)),
             " exit, ".to_span(),
-            "Ctrl+S".bold().yellow(),
+            self.theme.key(keys.describe(FormCommand::Save)),
             " save, ".to_span(),
-            "Tab".bold().yellow(),
+            self.theme.key(keys.describe(FormCommand::ToggleInput)),
             " switch input focus.".to_span(),
         ])
         .centered();
 
-        let mut title_input =
-            Paragraph::new(self.title_input.value()).style(Style::default().bold());
+        // An empty title is made up from the content when a new note is saved.
+        let mut title_input = if self.editing_new && self.title_input.value().is_empty() {
+            Paragraph::new("(auto from content)".dim())
+        } else {
+            Paragraph::new(self.title_input.value()).style(Style::default().bold())
+        };
 
         let mut content_input = Paragraph::new(self.content_input.value());
         let mut input_block = Block::bordered().title("Title");
         let mut content_block = Block::bordered().title("Content");
 
+        let mut content_cursor = None;
         match self.focused_input {
             FocusedInput::Title => {
-                input_block = input_block.border_style(Style::new().yellow());
-                let width = layout[0].width.max(3) - 3;
+                input_block = input_block.border_style(self.theme.focused_border());
+                let width = title_area.width.max(3) - 3;
                 let scroll = self.title_input.visual_scroll(width as usize);
                 title_input = title_input.scroll((0, scroll as u16));
 
                 let x = self.title_input.visual_cursor().max(scroll) - scroll + 1;
-                frame.set_cursor_position((layout[0].x + x as u16, layout[0].y + 1));
+                frame.set_cursor_position((title_area.x + x as u16, title_area.y + 1));
             }
             FocusedInput::Content => {
-                content_block = content_block.border_style(Style::new().yellow());
-                let width = layout[1].width.max(3) - 3;
+                content_block = content_block.border_style(self.theme.focused_border());
+                let width = content_area.width.max(3) - 3;
                 let scroll = self.content_input.visual_scroll(width as usize);
                 content_input = content_input.scroll((0, scroll as u16));
 
                 let x = self.content_input.visual_cursor().max(scroll) - scroll + 1;
-                frame.set_cursor_position((layout[1].x + x as u16, layout[1].y + 1));
+                let cursor = (content_area.x + x as u16, content_area.y + 1);
+                frame.set_cursor_position(cursor);
+                content_cursor = Some(cursor);
             }
         }
 
-        frame.render_widget(title_input.block(input_block), layout[0]);
+        if self.show_toolbar {
+            frame.render_widget(self.form_toolbar(), toolbar_area);
+        }
+        frame.render_widget(title_input.block(input_block), title_area);
         frame.render_widget(content_input.block(content_block), inner_content_layout[0]);
-        frame.render_widget(help_message, inner_content_layout[1]);
+        frame.render_widget(self.toast_or(help_message), inner_content_layout[1]);
+
+        if let Some(selected) = self.wikilink_selected
+            && let Some((x, y)) = content_cursor
+        {
+            let matches = self.wikilink_matches();
+            let content = inner_content_layout[0];
+            let width = 40.min(content.width);
+            let height = (matches.len().max(1) as u16 + 2).min(WIKILINK_PICKER_HEIGHT);
+            let area = Rect {
+                x: x.min(content.right().saturating_sub(width)),
+                y: y + 1,
+                width,
+                height: height.min(content.bottom().saturating_sub(y + 1)),
+            };
+            render_wikilink_picker(frame, area, &matches, selected, &self.theme);
+        }
+    }
+    /// The formatting the content supports, each lit while the cursor is in it.
+    fn form_toolbar(&self) -> Paragraph<'_> {
+        let formatting = match self.focused_input {
+            FocusedInput::Title => text_utils::Formatting::default(),
+            FocusedInput::Content => text_utils::formatting_at_cursor(&self.content_input),
+        };
+        let icons = [
+            ("B".bold(), formatting.bold),
+            ("I".italic(), formatting.italic),
+            ("U".underlined(), formatting.underline),
+            ("[[".into(), formatting.wikilink),
+            ("#".into(), formatting.header),
+            ("---".into(), formatting.horizontal_rule),
+        ];
+        let spans = icons.into_iter().flat_map(|(icon, active)| {
+            let icon = if active {
+                icon.fg(self.theme.accent)
+            } else {
+                icon.dim()
+            };
+            [" ".into(), icon]
+        });
+        Paragraph::new(Line::from_iter(spans))
     }
-    fn render_exit(&self, frame: &mut Frame) {
-        let layout = Layout::default()
-            .direction(ratatui::layout::Direction::Vertical)
-            .constraints(vec![Constraint::Max(2), Constraint::Max(2)])
-            .split(frame.area());
 
+    fn render_exit(&self, frame: &mut Frame) {
         let help_message = Line::from_iter([
-            "y".bold().yellow(),
-            " Yes, ".to_span(),
-            "n".bold().yellow(),
-            " No, ".to_span(),
+            self.theme.key("y"),
+            " yes, ".to_span(),
+            self.theme.key("n/Esc"),
+            " no".to_span(),
         ])
         .centered();
 
-        let title = Paragraph::new("Wanna quit ?").style(Style::default().bold());
-
-        frame.render_widget(title, layout[0]);
-        frame.render_widget(help_message, layout[1]);
+        render_modal(
+            frame,
+            vec![Line::from("Wanna quit ?").bold().centered(), help_message],
+            30,
+        );
     }
 
-    fn save_note(&mut self) {
-        if let Some(selected_index) = self.notes.state.selected() {
-            let updated_note = self
-                .db
-                .update_note(
-                    self.notes.items[selected_index].id,
-                    self.title_input.value(),
-                    self.content_input.value(),
-                )
-                .unwrap();
-            self.notes.items[selected_index] = updated_note;
-        }
+    fn render_emoji_picker(&self, frame: &mut Frame) {
+        let area = frame.area();
+        frame.buffer_mut().set_style(area, Style::new().dim());
+
+        let picker_area = area.centered(Constraint::Length(40), Constraint::Length(14));
+        let block = Block::bordered()
+            .border_set(border::THICK)
+            .title(" Emoji ")
+            .title_bottom(
+                Line::from_iter([
+                    self.theme.key("Enter"),
+                    " insert, ".to_span(),
+                    self.theme.key("Esc"),
+                    " cancel".to_span(),
+                ])
+                .centered(),
+            );
+        let inner = block.inner(picker_area);
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![Constraint::Length(1), Constraint::Min(1)])
+            .split(inner);
+
+        let picker = &self.emoji_picker;
+        let filter = Line::from(format!(":{}", picker.input.value()));
+        let x = picker.input.visual_cursor() as u16 + 1;
+        frame.set_cursor_position((layout[0].x + x, layout[0].y));
+
+        let matches = picker
+            .matches()
+            .into_iter()
+            .map(|(name, emoji)| Line::from(format!("{emoji} {name}")))
+            .collect::<List>()
+            .highlight_style(self.theme.selection())
+            .highlight_symbol(">>");
+        let mut state = ListState::default().with_selected(Some(picker.selected));
+
+        frame.render_widget(Clear, picker_area);
+        frame.render_widget(block, picker_area);
+        frame.render_widget(filter, layout[0]);
+        frame.render_stateful_widget(matches, layout[1], &mut state);
     }
-    fn toggle_input(&mut self) {
-        self.focused_input = match self.focused_input {
-            FocusedInput::Title => FocusedInput::Content,
-            FocusedInput::Content => FocusedInput::Title,
-        };
+
+    fn render_quick_switcher(&self, frame: &mut Frame) {
+        let area = frame.area();
+        frame.buffer_mut().set_style(area, Style::new().dim());
+
+        let switcher_area = area.centered(Constraint::Percentage(60), Constraint::Length(16));
+        let block = Block::bordered()
+            .border_set(border::THICK)
+            .title(" Go to note ")
+            .title_bottom(
+                Line::from_iter([
+                    self.theme.key("Enter"),
+                    " jump, ".to_span(),
+                    self.theme.key("Esc"),
+                    " cancel".to_span(),
+                ])
+                .centered(),
+            );
+        let inner = block.inner(switcher_area);
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![Constraint::Length(1), Constraint::Min(1)])
+            .split(inner);
+
+        let switcher = &self.quick_switcher;
+        let input = Line::from(format!("> {}", switcher.input.value()));
+        let x = switcher.input.visual_cursor() as u16 + 2;
+        frame.set_cursor_position((layout[0].x + x, layout[0].y));
+
+        let results = switcher
+            .results
+            .iter()
+            .map(|&index| {
+                let note = &self.notes.items[index];
+                let mut line = Line::from(note.title.as_str());
+                if let Some(tags) = switcher.tags.get(&note.id) {
+                    let tags = tags
+                        .iter()
+                        .map(|tag| format!(" #{tag}"))
+                        .collect::<String>();
+                    line.push_span(tags.dim());
+                }
+                line
+            })
+            .collect::<List>()
+            .highlight_style(self.theme.selection())
+            .highlight_symbol(">>");
+        let mut state = ListState::default().with_selected(Some(switcher.selected));
+
+        frame.render_widget(Clear, switcher_area);
+        frame.render_widget(block, switcher_area);
+        frame.render_widget(input, layout[0]);
+        frame.render_stateful_widget(results, layout[1], &mut state);
     }
-    fn add_note(&mut self) {
-        let new_note = self.db.add_note("New note", "").unwrap();
-        self.notes.items.push(new_note);
-        self.notes.state.select(Some(self.notes.items.len() - 1));
+
+    fn render_text_search(&self, frame: &mut Frame) {
+        let area = frame.area();
+        frame.buffer_mut().set_style(area, Style::new().dim());
+
+        let search_area = area.centered(Constraint::Percentage(60), Constraint::Length(18));
+        let search = &self.text_search;
+        let block = Block::bordered()
+            .border_set(border::THICK)
+            .title(" Search note contents ")
+            .title_bottom(
+                Line::from_iter([
+                    self.theme.key("Enter"),
+                    " jump, ".to_span(),
+                    self.theme.key("Esc"),
+                    " cancel".to_span(),
+                ])
+                .centered(),
+            );
+        let inner = block.inner(search_area);
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![Constraint::Length(1), Constraint::Min(1)])
+            .split(inner);
+
+        let input = Line::from(format!("> {}", search.input.value()));
+        let x = search.input.visual_cursor() as u16 + 2;
+        frame.set_cursor_position((layout[0].x + x, layout[0].y));
+
+        // The index matches whole words with accents ignored; the excerpt is centred on the
+        // last word, which is likely the one typed to narrow the results down.
+        let options = SearchOptions { fold_accents: true };
+        let word = search.input.value().split_whitespace().last().unwrap_or("");
+        let excerpts = search
+            .results
+            .iter()
+            .map(|note| note.excerpt(word, SEARCH_EXCERPT_CONTEXT, options))
+            .collect::<Vec<_>>();
+        let results = search
+            .results
+            .iter()
+            .zip(&excerpts)
+            .map(|(note, excerpt)| {
+                let title = highlight_matches(&note.title, word, options, self.theme.accent);
+                let mut excerpt = highlight_matches(excerpt, word, options, self.theme.accent);
+                excerpt.spans.insert(0, "  ".into());
+                Text::from(vec![title, excerpt.dim()])
+            })
+            .collect::<List>()
+            .highlight_style(self.theme.selection())
+            .highlight_symbol(">>");
+        let mut state = ListState::default().with_selected(Some(search.selected));
+
+        frame.render_widget(Clear, search_area);
+        frame.render_widget(block, search_area);
+        frame.render_widget(input, layout[0]);
+        frame.render_stateful_widget(results, layout[1], &mut state);
     }
-    fn delete_note(&mut self) {
-        if let Some(selected_index) = self.notes.state.selected() {
-            self.db
-                .delete_note(self.notes.items[selected_index].id)
-                .unwrap();
-            self.notes.items.remove(selected_index);
-            if selected_index != 0 {
-                self.notes.state.select(Some(selected_index - 1));
-            }
-        }
+
+    fn render_marks(&self, frame: &mut Frame) {
+        let popup = &self.marks_popup;
+        let lines = self
+            .marked_content(popup.return_to)
+            .split('\n')
+            .collect::<Vec<_>>();
+        let last_line = lines.len() - 1;
+
+        let height = popup.marks.len().min(12) as u16 + 2;
+        let area = frame
+            .area()
+            .centered(Constraint::Percentage(60), Constraint::Length(height));
+        let block = Block::bordered()
+            .border_set(border::THICK)
+            .title(" Marks ")
+            .title_bottom(
+                Line::from_iter([
+                    self.theme.key("Enter"),
+                    "/".to_span(),
+                    self.theme.key("letter"),
+                    " jump, ".to_span(),
+                    self.theme.key("Esc"),
+                    " close".to_span(),
+                ])
+                .centered(),
+            );
+
+        let marks = popup
+            .marks
+            .iter()
+            .map(|mark| {
+                let line = mark.line.min(last_line);
+                Line::from_iter([
+                    self.theme.key(format!("{} ", mark.name)),
+                    Span::from(format!("{:>5}  ", line + 1)).dim(),
+                    Span::raw(lines[line].trim()),
+                ])
+            })
+            .collect::<List>()
+            .block(block)
+            .highlight_style(self.theme.selection())
+            .highlight_symbol(">>");
+        let mut state = ListState::default().with_selected(Some(popup.selected));
+
+        frame.render_widget(Clear, area);
+        frame.render_stateful_widget(marks, area, &mut state);
+    }
+
+    fn render_bulk_tag(&self, frame: &mut Frame) {
+        let count = self.bulk_tag_targets().len();
+        let editor = &self.tag_editor;
+        let completions = editor.completions();
+        let completions = if completions.is_empty() {
+            Line::default()
+        } else {
+            Line::from_iter([
+                self.theme.key("Tab"),
+                format!(" {}", completions.join(", ")).dim(),
+            ])
+        };
+        let help_message = Line::from_iter([
+            self.theme.key("Enter"),
+            " apply, ".to_span(),
+            self.theme.key("Esc"),
+            " cancel".to_span(),
+        ])
+        .centered();
+
+        let width = 60;
+        render_modal(
+            frame,
+            vec![
+                Line::from(format!("Tag {count} notes: +tag adds, -tag removes"))
+                    .bold()
+                    .centered(),
+                Line::from(format!("> {}", editor.input.value())),
+                completions,
+                help_message,
+            ],
+            width,
+        );
+
+        // The input sits on the second line of the modal, after the border and "> ".
+        let modal = frame
+            .area()
+            .centered(Constraint::Length(width), Constraint::Length(6));
+        let x = editor.input.visual_cursor() as u16 + 3;
+        frame.set_cursor_position((modal.x + x, modal.y + 2));
+    }
+
+    fn render_journal_entry(&self, frame: &mut Frame) {
+        let help_message = Line::from_iter([
+            self.theme.key("Enter"),
+            " add, ".to_span(),
+            self.theme.key("Esc"),
+            " cancel".to_span(),
+        ])
+        .centered();
+
+        let width = 60;
+        render_modal(
+            frame,
+            vec![
+                Line::from(format!("Add to \"{}\"", self.config.journal.title))
+                    .bold()
+                    .centered(),
+                Line::from(format!("> {}", self.journal_input.value())),
+                help_message,
+            ],
+            width,
+        );
+
+        // The input sits on the second line of the modal, after the border and "> ".
+        let modal = frame
+            .area()
+            .centered(Constraint::Length(width), Constraint::Length(5));
+        let x = self.journal_input.visual_cursor() as u16 + 3;
+        frame.set_cursor_position((modal.x + x, modal.y + 2));
+    }
+
+    fn render_pdf_export(&self, frame: &mut Frame) {
+        let help_message = Line::from_iter([
+            self.theme.key("Enter"),
+            " export, ".to_span(),
+            self.theme.key("Esc"),
+            " cancel".to_span(),
+        ])
+        .centered();
+
+        let width = 60;
+        render_modal(
+            frame,
+            vec![
+                Line::from("Export as PDF to").bold().centered(),
+                Line::from(format!("> {}", self.pdf_path_input.value())),
+                help_message,
+            ],
+            width,
+        );
+
+        // The input sits on the second line of the modal, after the border and "> ".
+        let modal = frame
+            .area()
+            .centered(Constraint::Length(width), Constraint::Length(5));
+        let x = self.pdf_path_input.visual_cursor() as u16 + 3;
+        frame.set_cursor_position((modal.x + x, modal.y + 2));
+    }
+
+    fn render_recent_notes(&self, frame: &mut Frame) {
+        let now = Utc::now();
+        let notes = self.recent_notes();
+
+        let height = notes.len() as u16 + 2;
+        let area = frame
+            .area()
+            .centered(Constraint::Percentage(60), Constraint::Length(height));
+        let block = Block::bordered()
+            .border_set(border::THICK)
+            .title(" Recently viewed ")
+            .title_bottom(
+                Line::from_iter([
+                    self.theme.key("Enter"),
+                    " open, ".to_span(),
+                    self.theme.key("Esc"),
+                    " cancel".to_span(),
+                ])
+                .centered(),
+            );
+
+        let list = notes
+            .iter()
+            .map(|(_, note)| {
+                Line::from_iter([
+                    Span::raw(note.title.as_str()),
+                    format!(" · edited {}", dates::time_ago(note.updated_at, now)).dim(),
+                ])
+            })
+            .collect::<List>()
+            .block(block)
+            .highlight_style(self.theme.selection())
+            .highlight_symbol(">>");
+        let mut state = ListState::default().with_selected(Some(self.recent_selected));
+
+        frame.render_widget(Clear, area);
+        frame.render_stateful_widget(list, area, &mut state);
+    }
+
+    fn render_history(&self, frame: &mut Frame) {
+        let now = Utc::now();
+        let history = &self.history;
+        let current = self.notes.items.iter().find(|n| n.id == history.note_id);
+
+        let height = (history.revisions.len() as u16 + 2).min(frame.area().height);
+        let area = frame
+            .area()
+            .centered(Constraint::Percentage(60), Constraint::Length(height));
+        let block = Block::bordered()
+            .border_set(border::THICK)
+            .title(" Earlier versions ")
+            .title_bottom(
+                Line::from_iter([
+                    self.theme.key("Enter"),
+                    " compare with now, ".to_span(),
+                    self.theme.key("Esc"),
+                    " close".to_span(),
+                ])
+                .centered(),
+            );
+
+        let list = history
+            .revisions
+            .iter()
+            .map(|revision| {
+                let mut line = Line::from_iter([
+                    Span::raw(self.dates.relative(revision.saved_at, now)),
+                    Span::from(format!(" · {} lines", revision.content.split('\n').count())).dim(),
+                ]);
+                // Versions that came in with a merge are already labelled with their note's title.
+                if current.is_some_and(|note| note.title != revision.title)
+                    && revision.merged_from.as_ref() != Some(&revision.title)
+                {
+                    line.push_span(Span::from(format!(" · \"{}\"", revision.title)).dim());
+                }
+                if let Some(from) = &revision.merged_from {
+                    line.push_span(format!(" · from \"{from}\"").fg(self.theme.accent));
+                }
+                line
+            })
+            .collect::<List>()
+            .block(block)
+            .highlight_style(self.theme.selection())
+            .highlight_symbol(">>");
+        let mut state = ListState::default().with_selected(Some(history.selected));
+
+        frame.render_widget(Clear, area);
+        frame.render_stateful_widget(list, area, &mut state);
+    }
+
+    /// Only the rows on screen are turned into lines, so long notes scroll as fast as short ones.
+    fn render_split_note(&mut self, frame: &mut Frame) {
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![Constraint::Min(1), Constraint::Length(1)])
+            .split(frame.area());
+        let Some(note) = self
+            .notes
+            .items
+            .iter()
+            .find(|note| note.id == self.split_view.note_id)
+        else {
+            return;
+        };
+
+        let rows = text_utils::soft_wrap(&note.content, layout[0].width.saturating_sub(2).into());
+        let visible = layout[0].height.saturating_sub(2) as usize;
+        let divider_row = rows
+            .iter()
+            .position(|&(line, _)| line == self.split_view.line)
+            .unwrap_or(rows.len());
+        // The divider stays in the middle of the screen as it moves.
+        let scroll = divider_row.saturating_sub(visible / 2);
+        let divider = Line::from(
+            format!(
+                "{:─^width$}",
+                " split here ",
+                width = layout[0].width.saturating_sub(2).into()
+            )
+            .fg(self.theme.accent),
+        );
+        let mut lines = rows
+            .iter()
+            .map(|&(_, row)| Line::raw(row))
+            .collect::<Vec<_>>();
+        lines.insert(divider_row, divider);
+        let lines = lines
+            .into_iter()
+            .skip(scroll)
+            .take(visible)
+            .collect::<Vec<_>>();
+
+        let block = Block::bordered()
+            .border_set(border::THICK)
+            .title(Line::raw(format!(" Split \"{}\" ", note.title)).centered());
+        let help_message = Line::from_iter([
+            self.theme.key("j/k"),
+            " move the divider, ".to_span(),
+            self.theme.key("Enter"),
+            " split, ".to_span(),
+            self.theme.key("Esc"),
+            " cancel".to_span(),
+        ])
+        .centered();
+
+        frame.render_widget(Paragraph::new(lines).block(block), layout[0]);
+        frame.render_widget(self.toast_or(help_message), layout[1]);
+        self.split_view.page = visible.max(1);
+    }
+
+    fn render_revision_diff(&mut self, frame: &mut Frame) {
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![Constraint::Min(1), Constraint::Length(1)])
+            .split(frame.area());
+
+        let history = &mut self.history;
+        history.page = layout[0].height.saturating_sub(2).max(1) as usize;
+        history.scroll = history
+            .scroll
+            .min(history.diff.len().saturating_sub(history.page));
+        let history = &self.history;
+
+        let saved_at = history
+            .revisions
+            .get(history.selected)
+            .map(|revision| self.dates.relative(revision.saved_at, Utc::now()))
+            .unwrap_or_default();
+        let end = (history.scroll + history.page).min(history.diff.len());
+        let block = Block::bordered()
+            .border_set(border::THICK)
+            .title(Line::raw(format!(" Version of {saved_at} → now ")).centered())
+            .title_bottom(
+                Line::raw(format!(
+                    " {}–{end} of {} lines ",
+                    (history.scroll + 1).min(end),
+                    history.diff.len()
+                ))
+                .right_aligned(),
+            );
+
+        let lines = history.diff[history.scroll..end]
+            .iter()
+            .map(|line| match line.tag {
+                ChangeTag::Insert => Line::from(format!("+ {}", line.text)).fg(self.theme.success),
+                ChangeTag::Delete => Line::from(format!("- {}", line.text)).fg(self.theme.danger),
+                ChangeTag::Equal => Line::from(format!("  {}", line.text)),
+            })
+            .collect::<Vec<_>>();
+
+        let help_message = Line::from_iter([
+            self.theme.key("j/k"),
+            " scroll, ".to_span(),
+            self.theme.key("r"),
+            " restore this version, ".to_span(),
+            self.theme.key("Esc"),
+            " back".to_span(),
+        ])
+        .centered();
+
+        frame.render_widget(Paragraph::new(lines).block(block), layout[0]);
+        frame.render_widget(self.toast_or(help_message), layout[1]);
+    }
+
+    fn render_broken_links(&self, frame: &mut Frame) {
+        let height = (self.broken_links.len() as u16 + 2).min(frame.area().height);
+        let area = frame
+            .area()
+            .centered(Constraint::Percentage(60), Constraint::Length(height));
+        let block = Block::bordered()
+            .border_set(border::THICK)
+            .title(" Broken links ")
+            .title_bottom(
+                Line::from_iter([
+                    self.theme.key("Enter"),
+                    " go to the link, ".to_span(),
+                    self.theme.key("Esc"),
+                    " close".to_span(),
+                ])
+                .centered(),
+            );
+
+        let list = self
+            .broken_links
+            .iter()
+            .map(|(note, targets)| {
+                Line::from_iter([
+                    Span::raw(note.title.as_str()),
+                    Span::from(format!(" → {}", wikilinks(targets))).fg(self.theme.danger),
+                ])
+            })
+            .collect::<List>()
+            .block(block)
+            .highlight_style(self.theme.selection())
+            .highlight_symbol(">>");
+        let mut state = ListState::default().with_selected(Some(self.broken_selected));
+
+        frame.render_widget(Clear, area);
+        frame.render_stateful_widget(list, area, &mut state);
+    }
+
+    fn render_long_notes(&self, frame: &mut Frame) {
+        let height = (self.long_notes.len() as u16 + 2).min(frame.area().height);
+        let area = frame
+            .area()
+            .centered(Constraint::Percentage(60), Constraint::Length(height));
+        let block = Block::bordered()
+            .border_set(border::THICK)
+            .title(format!(
+                " Longer than {} characters ",
+                self.config.notes.long_note_chars
+            ))
+            .title_bottom(
+                Line::from_iter([
+                    self.theme.key("Enter"),
+                    " select, ".to_span(),
+                    self.theme.key("s"),
+                    " split, ".to_span(),
+                    self.theme.key("Esc"),
+                    " close".to_span(),
+                ])
+                .centered(),
+            );
+
+        // Bars are relative to the longest note, which comes first.
+        let lengths = self
+            .long_notes
+            .iter()
+            .map(|note| note.content.chars().count())
+            .collect::<Vec<_>>();
+        let longest = lengths.first().copied().unwrap_or(1).max(1);
+        let list = self
+            .long_notes
+            .iter()
+            .zip(&lengths)
+            .map(|(note, &length)| {
+                let filled = (length * LONG_NOTE_BAR_WIDTH).div_ceil(longest);
+                Line::from_iter([
+                    Span::raw("█".repeat(filled)).fg(self.theme.accent),
+                    Span::raw("░".repeat(LONG_NOTE_BAR_WIDTH - filled)).dim(),
+                    Span::from(format!(" {length:>7} ")).dim(),
+                    Span::raw(note.title.as_str()),
+                ])
+            })
+            .collect::<List>()
+            .block(block)
+            .highlight_style(self.theme.selection())
+            .highlight_symbol(">>");
+        let mut state = ListState::default().with_selected(Some(self.long_selected));
+
+        frame.render_widget(Clear, area);
+        frame.render_stateful_widget(list, area, &mut state);
+    }
+
+    fn render_merge_confirm(&self, frame: &mut Frame) {
+        let Ok((source, target)) = self.merge_pair() else {
+            return;
+        };
+        let length = db::merged_content(target, source).chars().count();
+        let help_message = Line::from_iter([
+            self.theme.key("y"),
+            " merge, ".to_span(),
+            self.theme.key("n/Esc"),
+            " cancel".to_span(),
+        ])
+        .centered();
+
+        render_modal(
+            frame,
+            vec![
+                Line::from(format!(
+                    "Merge \"{}\" into \"{}\"?",
+                    source.title, target.title
+                ))
+                .bold()
+                .centered(),
+                Line::from(format!(
+                    "{length} chars once merged, \"{}\" is deleted",
+                    source.title
+                ))
+                .centered(),
+                help_message,
+            ],
+            60,
+        );
+    }
+
+    fn render_duplicate_title(&self, frame: &mut Frame) {
+        let mut help = vec![
+            self.theme.key("o"),
+            " open existing, ".to_span(),
+            self.theme.key("r"),
+            " rename".to_span(),
+        ];
+        if !self.config.notes.unique_titles {
+            help.extend([
+                ", ".to_span(),
+                self.theme.key("s"),
+                " save anyway".to_span(),
+            ]);
+        }
+
+        render_modal(
+            frame,
+            vec![
+                Line::from("A note with this title already exists")
+                    .bold()
+                    .centered(),
+                Line::from_iter(help).centered(),
+            ],
+            50,
+        );
+    }
+
+    fn render_passphrase(&self, frame: &mut Frame, op: ArchiveOp) {
+        let layout = Layout::default()
+            .direction(ratatui::layout::Direction::Vertical)
+            .constraints(vec![
+                Constraint::Max(2),
+                Constraint::Max(3),
+                Constraint::Max(1),
+            ])
+            .split(frame.area());
+
+        let prompt = match op {
+            ArchiveOp::Export => format!("Passphrase to encrypt {ARCHIVE_PATH}"),
+            ArchiveOp::Import => format!("Passphrase to decrypt {ARCHIVE_PATH}"),
+        };
+        let title = Paragraph::new(prompt).style(Style::default().bold());
+
+        let masked = "*".repeat(self.passphrase_input.value().chars().count());
+        let input = Paragraph::new(masked).block(
+            Block::bordered()
+                .title("Passphrase")
+                .border_style(self.theme.focused_border()),
+        );
+
+        let help_message = Line::from_iter([
+            self.theme.key("Enter"),
+            " confirm, ".to_span(),
+            self.theme.key("Esc"),
+            " cancel".to_span(),
+        ])
+        .centered();
+
+        let x = self.passphrase_input.visual_cursor() as u16 + 1;
+        frame.set_cursor_position((layout[1].x + x, layout[1].y + 1));
+
+        frame.render_widget(title, layout[0]);
+        frame.render_widget(input, layout[1]);
+        frame.render_widget(help_message, layout[2]);
+    }
+
+    fn request_quit(&mut self) {
+        match self.config.quit.confirm {
+            QuitConfirm::Always => self.set_screen(Screen::ExitConfirm),
+            QuitConfirm::Never => self.quit(),
+            QuitConfirm::OnChanges => {
+                if self.unsaved_changes || self.session_changed {
+                    self.set_screen(Screen::ExitConfirm);
+                } else {
+                    self.quit();
+                }
+            }
+            QuitConfirm::DoublePress => {
+                if self
+                    .last_quit_press
+                    .is_some_and(|at| at.elapsed() < DOUBLE_PRESS_WINDOW)
+                {
+                    self.quit();
+                } else {
+                    self.last_quit_press = Some(Instant::now());
+                    self.notify("Press q again to quit", Level::Info, TOAST_DURATION);
+                }
+            }
+        }
+    }
+
+    fn quit(&mut self) {
+        self.flush_deletion();
+        self.should_quit = true;
+    }
+
+    /// Whether a count or the first keys of a multi-key binding have been typed. These are the
+    /// only state `handle_key` changes.
+    fn key_sequence_pending(&self) -> bool {
+        self.pending_color
+            || self.pending_mark.is_some()
+            || !self.pending_keys.is_empty()
+            || !self.key_prefix_buffer.is_empty()
+    }
+
+    /// Consumes the digits typed so far as a repeat count, defaulting to 1.
+    fn take_count(&mut self) -> usize {
+        let count = match self.key_prefix_buffer.as_str() {
+            "" => 1,
+            digits => digits.parse().unwrap_or(usize::MAX),
+        };
+        self.key_prefix_buffer.clear();
+        count.max(1)
+    }
+
+    fn render_advanced_search(&mut self, frame: &mut Frame) {
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![
+                Constraint::Length(3),
+                Constraint::Min(1),
+                Constraint::Length(1),
+            ])
+            .split(frame.area());
+
+        let inputs_layout = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(vec![
+                Constraint::Percentage(50),
+                Constraint::Percentage(25),
+                Constraint::Percentage(25),
+            ])
+            .split(layout[0]);
+
+        let search = &mut self.advanced_search;
+        let mut query_block = Block::bordered().title(if search.options.fold_accents {
+            "Contains (ignoring accents)"
+        } else {
+            "Contains"
+        });
+        let mut min_block = Block::bordered().title("Min chars");
+        let mut max_block = Block::bordered().title("Max chars");
+
+        let (focused_area, focused_input) = match search.focused_input {
+            SearchField::Query => {
+                query_block = query_block.border_style(self.theme.focused_border());
+                (inputs_layout[0], &search.query_input)
+            }
+            SearchField::Min => {
+                min_block = min_block.border_style(self.theme.focused_border());
+                (inputs_layout[1], &search.min_input)
+            }
+            SearchField::Max => {
+                max_block = max_block.border_style(self.theme.focused_border());
+                (inputs_layout[2], &search.max_input)
+            }
+        };
+        let x = focused_input.visual_cursor() as u16 + 1;
+        frame.set_cursor_position((focused_area.x + x, focused_area.y + 1));
+
+        // Excluded terms are in none of the results, so only the text is highlighted.
+        let query = query_parser::parse(search.query_input.value()).text;
+        let query = query.as_str();
+        let excerpts = search
+            .results
+            .items
+            .iter()
+            .map(|note| note.excerpt(query, SEARCH_EXCERPT_CONTEXT, search.options))
+            .collect::<Vec<_>>();
+        let results = search
+            .results
+            .items
+            .iter()
+            .zip(&excerpts)
+            .map(|(note, excerpt)| {
+                let mut title =
+                    highlight_matches(&note.title, query, search.options, self.theme.accent);
+                title.push_span(format!(" ({} chars)", note.content.chars().count()));
+                let mut excerpt =
+                    highlight_matches(excerpt, query, search.options, self.theme.accent);
+                excerpt.spans.insert(0, "  ".into());
+                Text::from(vec![title, excerpt.dim()])
+            })
+            .collect::<List>()
+            .block(Block::bordered().title(format!("Results ({})", search.results.items.len())))
+            .highlight_style(self.theme.selection())
+            .highlight_symbol(">>");
+
+        let help_message = Line::from_iter([
+            self.theme.key("Esc"),
+            " back, ".to_span(),
+            self.theme.key("Tab"),
+            " switch input, ".to_span(),
+            self.theme.key("Alt+a"),
+            " toggle accents, ".to_span(),
+            self.theme.key("Enter"),
+            " open".to_span(),
+        ])
+        .centered();
+
+        frame.render_widget(
+            Paragraph::new(search.query_input.value()).block(query_block),
+            inputs_layout[0],
+        );
+        frame.render_widget(
+            Paragraph::new(search.min_input.value()).block(min_block),
+            inputs_layout[1],
+        );
+        frame.render_widget(
+            Paragraph::new(search.max_input.value()).block(max_block),
+            inputs_layout[2],
+        );
+        frame.render_stateful_widget(results, layout[1], &mut search.results.state);
+        frame.render_widget(self.toast_or(help_message), layout[2]);
+    }
+
+    fn render_tag_browser(&self, frame: &mut Frame) {
+        let layout = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(vec![Constraint::Percentage(30), Constraint::Min(1)])
+            .split(frame.area());
+
+        let inner_tree_layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![Constraint::Min(1), Constraint::Length(1)])
+            .split(layout[0]);
+
+        let block = Block::bordered()
+            .title(Line::raw("Tags").centered())
+            .border_set(border::THICK);
+        let tree_area = block.inner(inner_tree_layout[0]);
+        frame.render_widget(block, inner_tree_layout[0]);
+
+        let browser = &self.tag_browser;
+        if browser.nodes.is_empty() {
+            frame.render_widget(
+                Paragraph::new("No tagged notes yet.").centered().dim(),
+                tree_area,
+            );
+        }
+
+        // Scroll by whole tags so the selected row stays on screen.
+        let selected_node = browser.locate().map_or(0, |(node, _)| node);
+        let mut first = 0;
+        while first < selected_node
+            && browser.selected + 1 - browser.node_row(first) > tree_area.height as usize
+        {
+            first += 1;
+        }
+
+        let mut y = tree_area.y;
+        for (index, node) in browser.nodes.iter().enumerate().skip(first) {
+            if y >= tree_area.bottom() {
+                break;
+            }
+            let start = browser.node_row(index);
+            let selected = browser
+                .selected
+                .checked_sub(start)
+                .filter(|&row| row < TagBrowser::rows(node));
+            let area = Rect {
+                y,
+                height: tree_area.bottom() - y,
+                ..tree_area
+            };
+            y += render_tree_node(node, 0, frame, area, selected, &self.theme);
+        }
+
+        let details = match browser.locate() {
+            Some((node, Some(note))) => {
+                let note = &browser.nodes[node].notes[note];
+                Paragraph::new(note.content.as_str())
+                    .block(Block::bordered().title(note.title.as_str()))
+            }
+            Some((node, None)) => {
+                let node = &browser.nodes[node];
+                Paragraph::new(Text::from_iter(
+                    node.notes.iter().map(|note| Line::raw(note.title.as_str())),
+                ))
+                .block(Block::bordered().title(format!("#{}", node.tag)))
+            }
+            None => Paragraph::default().block(Block::bordered()),
+        };
+
+        let help_message = Line::from_iter([
+            self.theme.key("Enter/→"),
+            " expand/open, ".to_span(),
+            self.theme.key("←"),
+            " collapse, ".to_span(),
+            self.theme.key("Esc"),
+            " back".to_span(),
+        ])
+        .centered();
+
+        frame.render_widget(self.toast_or(help_message), inner_tree_layout[1]);
+        frame.render_widget(details, layout[1]);
+    }
+
+    fn render_cloud(&self, frame: &mut Frame) {
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![Constraint::Min(1), Constraint::Length(1)])
+            .split(frame.area());
+
+        let cloud = &self.cloud;
+        let (title, empty) = match cloud.mode {
+            CloudMode::Tags => ("Tags", "No tagged notes yet."),
+            CloudMode::Words => ("Words", "No words yet."),
+        };
+        let title = match cloud.entries.get(cloud.selected) {
+            Some((name, count)) => format!(" {title} · {name}: {count} "),
+            None => format!(" {title} "),
+        };
+        let block = Block::bordered()
+            .title(Line::raw(title).centered())
+            .border_set(border::THICK);
+
+        let most = cloud.entries.first().map_or(1, |&(_, count)| count.max(1));
+        let cloud_text = if cloud.counting.is_some() && cloud.entries.is_empty() {
+            Paragraph::new("Counting words…").centered().dim()
+        } else if cloud.entries.is_empty() {
+            Paragraph::new(empty).centered().dim()
+        } else {
+            // Terminals only have one text size: the most used entries stand out by weight and
+            // color, the least used are dimmed.
+            let spans = cloud
+                .entries
+                .iter()
+                .enumerate()
+                .flat_map(|(index, (name, count))| {
+                    let name = match cloud.mode {
+                        CloudMode::Tags => format!("#{name}"),
+                        CloudMode::Words => name.clone(),
+                    };
+                    let style = if index == cloud.selected {
+                        self.theme.selection()
+                    } else if count * 3 > most * 2 {
+                        Style::new().bold().fg(self.theme.accent)
+                    } else if count * 3 > most {
+                        Style::new().fg(self.theme.text)
+                    } else {
+                        Style::new().dim()
+                    };
+                    [Span::styled(name, style), Span::raw("  ")]
+                });
+            Paragraph::new(Line::from_iter(spans)).wrap(Wrap { trim: true })
+        };
+
+        let help_message = Line::from_iter([
+            self.theme.key("Tab"),
+            " tags/words, ".to_span(),
+            self.theme.key("←/→"),
+            " select, ".to_span(),
+            self.theme.key("Enter"),
+            " show notes, ".to_span(),
+            self.theme.key("Esc"),
+            " back".to_span(),
+        ])
+        .centered();
+
+        frame.render_widget(cloud_text.block(block), layout[0]);
+        frame.render_widget(self.toast_or(help_message), layout[1]);
+    }
+
+    fn render_stats(&self, frame: &mut Frame) {
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![
+                Constraint::Length(3),
+                Constraint::Length(10),
+                Constraint::Min(4),
+                Constraint::Length(1),
+            ])
+            .split(frame.area());
+
+        let lists_layout = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(vec![Constraint::Percentage(40), Constraint::Min(1)])
+            .split(layout[2]);
+
+        let stats = &self.stats;
+        let today = Utc::now().date_naive();
+        let average = stats
+            .average_length
+            .map_or("-".to_string(), |average| format!("{average:.0} chars"));
+        let summary = Line::from_iter([
+            "Notes ".to_span(),
+            self.theme.key(stats.note_count.to_string()),
+            "  ·  Average length ".to_span(),
+            self.theme.key(average),
+            "  ·  Edit streak ".to_span(),
+            self.theme.key(format!("{} days", stats.streak(today))),
+        ])
+        .centered();
+
+        const MONTHS: u32 = 12;
+        let chart_block = Block::bordered().title("Notes over the last 12 months");
+        let gaps = MONTHS as u16 - 1;
+        let bar_width =
+            (chart_block.inner(layout[1]).width.saturating_sub(gaps) / MONTHS as u16).max(1);
+        let bars = stats
+            .totals_by_month(today, MONTHS)
+            .into_iter()
+            .map(|(month, total)| Bar::with_label(month, u64::from(total)))
+            .collect::<Vec<_>>();
+        let chart = BarChart::vertical(bars)
+            .block(chart_block)
+            .bar_width(bar_width)
+            .bar_gap(1)
+            .bar_style(Style::new().fg(self.theme.accent));
+
+        let top_tags = counted_list(&stats.top_tags, "").block(Block::bordered().title("Top tags"));
+        let largest_notes = counted_list(&stats.largest_notes, " chars")
+            .block(Block::bordered().title("Largest notes"));
+
+        let help_message = Line::from_iter([self.theme.key("Esc/q"), " back".to_span()]).centered();
+
+        frame.render_widget(Paragraph::new(summary).block(Block::bordered()), layout[0]);
+        frame.render_widget(chart, layout[1]);
+        frame.render_widget(top_tags, lists_layout[0]);
+        frame.render_widget(largest_notes, lists_layout[1]);
+        frame.render_widget(self.toast_or(help_message), layout[3]);
+    }
+
+    fn render_restore_draft(&self, frame: &mut Frame) {
+        let Some(draft) = self.pending_drafts.first() else {
+            return;
+        };
+
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![
+                Constraint::Length(2),
+                Constraint::Min(1),
+                Constraint::Length(1),
+            ])
+            .split(frame.area());
+
+        let message = match self.notes.items.iter().find(|n| n.id == draft.note_id) {
+            Some(note) => format!("Recovered unsaved changes to \"{}\"", note.title),
+            None => "Recovered an unsaved new note".to_string(),
+        };
+        let title = Paragraph::new(message).style(Style::default().bold());
+
+        let preview = Paragraph::new(draft.content.as_str())
+            .block(Block::bordered().title(draft.title.as_str()));
+
+        let help_message = Line::from_iter([
+            self.theme.key("r"),
+            " restore, ".to_span(),
+            self.theme.destructive_key("d"),
+            " discard".to_span(),
+        ])
+        .centered();
+
+        frame.render_widget(title, layout[0]);
+        frame.render_widget(preview, layout[1]);
+        frame.render_widget(help_message, layout[2]);
+    }
+
+    /// Queues `message` to show for `duration` once the notifications ahead of it are done.
+    fn notify(&mut self, message: impl Into<String>, level: Level, duration: Duration) {
+        let start = self
+            .notification_queue
+            .back()
+            .map_or(Instant::now(), |last| last.expires_at.max(Instant::now()));
+        self.notification_queue.push_back(Notification {
+            message: message.into(),
+            level,
+            expires_at: start + duration,
+        });
+    }
+
+    /// Drops the notifications whose time is up. Returns whether any were.
+    fn expire_notifications(&mut self) -> bool {
+        let now = Instant::now();
+        let before = self.notification_queue.len();
+        while self
+            .notification_queue
+            .front()
+            .is_some_and(|notification| notification.expires_at <= now)
+        {
+            self.notification_queue.pop_front();
+        }
+        self.notification_queue.len() != before
+    }
+
+    fn toast_or<'a>(&'a self, help_message: Line<'a>) -> Line<'a> {
+        let Some(notification) = self.notification_queue.front() else {
+            return match &self.pending_deletion {
+                Some(pending) => {
+                    let left = pending.deadline.saturating_duration_since(Instant::now());
+                    Line::from_iter([
+                        Span::raw(format!("Deleted \"{}\", ", pending.note.title)),
+                        self.theme.key(self.keymap.list.describe(ListCommand::Undo)),
+                        Span::raw(format!(" undo ({}s)", left.as_millis().div_ceil(1000))),
+                    ])
+                    .centered()
+                }
+                None => help_message,
+            };
+        };
+        let message = notification.message.as_str();
+        let span = match notification.level {
+            Level::Info => message.into(),
+            Level::Success => message.fg(self.theme.success),
+            Level::Warning => message.fg(self.theme.danger),
+            Level::Error => self.theme.error_message(message),
+        };
+        Line::from(span).centered()
+    }
+
+    fn save_note(&mut self) {
+        self.run_hook(Hook::OnSave);
+        if self.editing_new {
+            let notes_config = &self.config.notes;
+            self.insert_new_note(notes_config.unique_titles || notes_config.warn_duplicate_titles);
+        } else if let Some(selected_index) = self.notes.state.selected() {
+            let id = self.notes.items[selected_index].id;
+            let content = self.content_to_save(selected_index);
+            // The list only takes the note the database returned, so a failed write leaves both
+            // as they were and the form still holds the unsaved edit.
+            match self.db.update_note(id, self.title_input.value(), &content) {
+                Ok((updated_note, written)) => {
+                    // Saving an unchanged note wrote nothing, so there is nothing to undo.
+                    if written {
+                        if let NoteListCommand::Update(_, previous) = self.notes.apply(
+                            NoteListCommand::Update(selected_index, updated_note.clone()),
+                        ) {
+                            self.push_undo(UndoableAction::UpdateNote {
+                                id,
+                                old: previous,
+                                new: updated_note,
+                            });
+                        }
+                        self.session_changed = true;
+                    }
+                    self.unsaved_changes = false;
+                    if let Err(e) = self.db.delete_draft(id) {
+                        self.notify(
+                            format!("Failed to clear draft: {e}"),
+                            Level::Error,
+                            ERROR_TOAST_DURATION,
+                        );
+                    }
+                    self.draft_dirty = false;
+                }
+                Err(e) => self.notify(
+                    format!("Save failed: {e}"),
+                    Level::Error,
+                    ERROR_TOAST_DURATION,
+                ),
+            }
+        }
+    }
+    /// The form content, plus whatever was appended to the note elsewhere since it was loaded,
+    /// such as `ratata-notes log` entries written while the journal was open. Any other change
+    /// made meanwhile is saved over; the note history still has it.
+    fn content_to_save(&mut self, index: usize) -> String {
+        let loaded = &self.notes.items[index];
+        let content = self.content_input.value().to_string();
+        let stored = match self.db.get_note(loaded.id) {
+            Ok(Some(stored)) if stored.content != loaded.content => stored,
+            _ => return content,
+        };
+
+        match stored.content.strip_prefix(loaded.content.as_str()) {
+            Some(appended) => {
+                self.notify(
+                    "Kept the lines added to this note while it was open",
+                    Level::Info,
+                    TOAST_DURATION,
+                );
+                let merged = format!("{content}{appended}");
+                self.content_input =
+                    Input::new(merged.clone()).with_cursor(self.content_input.cursor());
+                merged
+            }
+            None => {
+                self.notify(
+                    "The note was changed elsewhere and has been overwritten",
+                    Level::Warning,
+                    TOAST_DURATION,
+                );
+                content
+            }
+        }
+    }
+
+    /// Moves the content from the cursor on into a new note titled after its first line.
+    fn split_note(&mut self) {
+        if self.editing_new {
+            self.notify(
+                "Save the note before splitting it",
+                Level::Warning,
+                TOAST_DURATION,
+            );
+            return;
+        }
+        let Some(index) = self.notes.state.selected() else {
+            return;
+        };
+
+        let content = self.content_input.value().to_string();
+        let split = content
+            .char_indices()
+            .nth(self.content_input.cursor())
+            .map_or(content.len(), |(index, _)| index);
+        let title = self.title_input.value().to_string();
+        let Some(updated) = self.split_content(index, &title, &content, split) else {
+            return;
+        };
+
+        self.content_input = Input::new(updated.content);
+        self.unsaved_changes = false;
+        self.draft_dirty = false;
+        if let Err(e) = self.db.delete_draft(updated.id) {
+            self.notify(
+                format!("Failed to clear draft: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            );
+        }
+    }
+
+    /// Saves the note at `index` as `title` and `content` up to the byte `split`, and moves the
+    /// rest into a new note titled after its first line. Returns the note as saved, or `None`
+    /// when it wasn't split, which the toast explains.
+    fn split_content(
+        &mut self,
+        index: usize,
+        title: &str,
+        content: &str,
+        split: usize,
+    ) -> Option<Note> {
+        let (head, tail) = content.split_at(split);
+        let new_title = tail.lines().next().unwrap_or_default().trim();
+        if new_title.is_empty() {
+            self.notify(
+                "The part split off needs a first line to be titled after",
+                Level::Warning,
+                TOAST_DURATION,
+            );
+            return None;
+        }
+        if self.config.notes.unique_titles
+            && !matches!(self.db.get_note_by_title(new_title), Ok(None))
+        {
+            self.notify(
+                format!("\"{new_title}\" is already taken"),
+                Level::Warning,
+                TOAST_DURATION,
+            );
+            return None;
+        }
+
+        let head = if self.config.notes.link_split_notes {
+            format!("{head}[[{new_title}]]")
+        } else {
+            head.to_string()
+        };
+        let original = self.notes.items[index].clone();
+        let result = self.db.get_note_tags(original.id).and_then(|tags| {
+            let notes = self
+                .db
+                .split_off_note(original.id, title, &head, new_title, tail)?;
+            Ok((notes, tags))
+        });
+
+        match result {
+            Ok(((updated, created), tags)) => {
+                self.push_undo(UndoableAction::Notes {
+                    originals: vec![(original, tags)],
+                    created: vec![created.id],
+                });
+                self.notify(
+                    format!("Split off \"{}\"", created.title),
+                    Level::Success,
+                    TOAST_DURATION,
+                );
+                self.notes
+                    .apply(NoteListCommand::Update(index, updated.clone()));
+                self.insert_sorted(created);
+                self.select_note_id(updated.id);
+                self.session_changed = true;
+                Some(updated)
+            }
+            Err(e) => {
+                self.notify(
+                    format!("Split failed: {e}"),
+                    Level::Error,
+                    ERROR_TOAST_DURATION,
+                );
+                None
+            }
+        }
+    }
+
+    fn insert_new_note(&mut self, detect_duplicates: bool) {
+        let result = self.db.add_note(
+            self.title_input.value(),
+            self.content_input.value(),
+            detect_duplicates,
+        );
+
+        match result {
+            Ok(NoteOrDuplicate::Note(note)) => {
+                self.push_undo(UndoableAction::AddNote(note.id));
+                if self.title_input.value().trim().is_empty() {
+                    self.title_input = Input::new(note.title.clone());
+                }
+                let index = self.insert_sorted(note);
+                self.notes.state.select(Some(index));
+                self.editing_new = false;
+                self.session_changed = true;
+                self.unsaved_changes = false;
+                self.draft_dirty = false;
+                if let Err(e) = self.db.delete_draft(NEW_NOTE_DRAFT_ID) {
+                    self.notify(
+                        format!("Failed to clear draft: {e}"),
+                        Level::Error,
+                        ERROR_TOAST_DURATION,
+                    );
+                }
+            }
+            Ok(NoteOrDuplicate::Duplicate { existing }) => {
+                self.duplicate_of = Some(existing);
+                self.set_screen(Screen::DuplicateTitle);
+            }
+            Err(e) => self.notify(
+                format!("Save failed: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            ),
+        }
+    }
+    fn on_tick(&mut self) {
+        self.needs_redraw |= self.expire_notifications();
+        self.reload_theme();
+        if let Some(pending) = &self.pending_deletion {
+            if pending.deadline <= Instant::now() {
+                self.flush_deletion();
+            }
+            // Redraws the countdown, or clears it once the delete went through.
+            self.needs_redraw = true;
+        }
+        if self.draft_dirty && matches!(self.current_screen, Screen::Form) {
+            self.save_draft();
+            // Only a failure is visible; a successful draft write changes nothing on screen.
+            self.needs_redraw |= !self.notification_queue.is_empty();
+        }
+    }
+    /// Applies `theme.toml` again once it changed, or `[theme]` once it was removed. A file that
+    /// doesn't parse leaves the colors as they were.
+    fn reload_theme(&mut self) {
+        let Some(watcher) = &mut self.theme_watcher else {
+            return;
+        };
+        if !watcher.changed() {
+            return;
+        }
+        let theme = if watcher.path().exists() {
+            Theme::from_file(watcher.path())
+        } else {
+            Ok(Theme::new(&self.config.theme))
+        };
+        match theme {
+            Ok(theme) => self.theme = theme,
+            // TOML errors go on to quote the offending line; the first one says where it is.
+            Err(e) => self.notify(
+                format!(
+                    "Theme not reloaded: {}",
+                    e.to_string().lines().next().unwrap_or_default()
+                ),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            ),
+        }
+        self.needs_redraw = true;
+    }
+    fn save_draft(&mut self) {
+        let note_id = if self.editing_new {
+            NEW_NOTE_DRAFT_ID
+        } else {
+            match self
+                .notes
+                .state
+                .selected()
+                .and_then(|index| self.notes.items.get(index))
+            {
+                Some(note) => note.id,
+                None => return,
+            }
+        };
+
+        match self.db.save_draft(
+            note_id,
+            self.title_input.value(),
+            self.content_input.value(),
+        ) {
+            Ok(()) => self.draft_dirty = false,
+            Err(e) => self.notify(
+                format!("Failed to save draft: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            ),
+        }
+    }
+    fn run_hook(&mut self, hook: Hook) {
+        let Some(scripts) = &self.scripts else {
+            return;
+        };
+        match scripts.run_hook(hook, self.title_input.value(), self.content_input.value()) {
+            Ok(Some(content)) => {
+                self.content_input = self.content_input.clone().with_value(content)
+            }
+            Ok(None) => {}
+            Err(e) => self.notify(e, Level::Error, ERROR_TOAST_DURATION),
+        }
+    }
+    fn run_command(&mut self, key: char) {
+        let Some(scripts) = &self.scripts else {
+            return;
+        };
+        match scripts.run_command(key, self.title_input.value(), self.content_input.value()) {
+            Ok(Some(content)) => {
+                self.content_input = self.content_input.clone().with_value(content)
+            }
+            Ok(None) => {}
+            Err(e) => self.notify(e, Level::Error, ERROR_TOAST_DURATION),
+        }
+    }
+    fn run_archive_op(&mut self, op: ArchiveOp) {
+        self.flush_deletion();
+        let path = Path::new(ARCHIVE_PATH);
+        let passphrase = self.passphrase_input.value();
+
+        // Exports carry archived notes too, so a restored backup doesn't lose them.
+        let result = match op {
+            ArchiveOp::Export => self
+                .db
+                .get_all_notes()
+                .map_err(color_eyre::Report::from)
+                .and_then(|notes| {
+                    archive::export_encrypted(&notes, path, passphrase)?;
+                    Ok(format!("Exported {} notes to {ARCHIVE_PATH}", notes.len()))
+                }),
+            ArchiveOp::Import => archive::import_encrypted(path, passphrase)
+                .and_then(|records| Ok(self.db.import_notes(&records)?))
+                .and_then(|imported| {
+                    self.reload_notes()?;
+                    Ok(format!("Imported {imported} notes from {ARCHIVE_PATH}"))
+                }),
+        };
+
+        match result {
+            Ok(message) => self.notify(message, Level::Success, TOAST_DURATION),
+            Err(e) => self.notify(
+                format!("Archive failed: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            ),
+        }
+    }
+    fn export_zip(&mut self) {
+        let result = self
+            .db
+            .get_all_notes()
+            .map_err(color_eyre::Report::from)
+            .and_then(|notes| {
+                archive::export_zip(&notes, Path::new(ZIP_PATH))?;
+                Ok(notes.len())
+            });
+        match result {
+            Ok(exported) => self.notify(
+                format!("Exported {exported} notes to {ZIP_PATH}"),
+                Level::Success,
+                TOAST_DURATION,
+            ),
+            Err(e) => self.notify(
+                format!("Zip export failed: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            ),
+        }
+    }
+    fn import_zip(&mut self) {
+        self.flush_deletion();
+        let result = archive::import_zip(Path::new(ZIP_PATH))
+            .and_then(|records| Ok(self.db.import_notes(&records)?))
+            .and_then(|imported| {
+                self.reload_notes()?;
+                Ok(imported)
+            });
+
+        match result {
+            Ok(imported) => self.notify(
+                format!("Imported {imported} notes from {ZIP_PATH}"),
+                Level::Success,
+                TOAST_DURATION,
+            ),
+            Err(e) => self.notify(
+                format!("Zip import failed: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            ),
+        }
+    }
+    fn refresh_advanced_search(&mut self) {
+        let search = &mut self.advanced_search;
+        let min = search.min_input.value().parse().unwrap_or(0);
+        let max = search.max_input.value().parse().unwrap_or(usize::MAX);
+
+        match self.db.get_notes_by_content_length_range(min, max) {
+            Ok(mut notes) => {
+                let query = query_parser::parse(search.query_input.value());
+                notes.retain(|note| query.matches(&note.title, &note.content, search.options));
+                search
+                    .results
+                    .state
+                    .select((!notes.is_empty()).then_some(0));
+                search.results.items = notes.into();
+            }
+            Err(e) => self.notify(
+                format!("Search failed: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            ),
+        }
+    }
+    /// Reloads tags from the database, keeping expanded tags open.
+    fn refresh_tag_browser(&mut self) {
+        let tags = match self.db.get_notes_by_tag() {
+            Ok(tags) => tags,
+            Err(e) => {
+                self.notify(
+                    format!("Failed to load tags: {e}"),
+                    Level::Error,
+                    ERROR_TOAST_DURATION,
+                );
+                return;
+            }
+        };
+
+        let browser = &mut self.tag_browser;
+        browser.nodes = tags
+            .into_iter()
+            .map(|(tag, notes)| TreeNode {
+                expanded: browser.nodes.iter().any(|n| n.tag == tag && n.expanded),
+                tag,
+                notes,
+            })
+            .collect();
+        browser.selected = browser.selected.min(browser.row_count().saturating_sub(1));
+    }
+    /// Loads tag counts, or reuses the word counts when the notes haven't changed since they
+    /// were counted. Otherwise the words are counted in the background.
+    fn refresh_cloud(&mut self) {
+        let mut failure = None;
+        let cloud = &mut self.cloud;
+        cloud.entries = match cloud.mode {
+            CloudMode::Tags => match self.db.get_notes_by_tag() {
+                Ok(tags) => {
+                    let mut tags = tags
+                        .into_iter()
+                        .map(|(tag, notes)| (tag, notes.len()))
+                        .collect::<Vec<_>>();
+                    tags.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+                    tags.truncate(cloud::CLOUD_LIMIT);
+                    tags
+                }
+                Err(e) => {
+                    failure = Some(format!("Failed to load tags: {e}"));
+                    Vec::new()
+                }
+            },
+            CloudMode::Words => match &cloud.words {
+                Some((notes, words)) if Arc::ptr_eq(notes, &self.notes.items) => words.clone(),
+                _ => {
+                    if cloud.counting.is_none() {
+                        let (done, counting) = mpsc::channel();
+                        let notes = Arc::clone(&self.notes.items);
+                        std::thread::spawn(move || {
+                            let words = cloud::word_frequencies(&notes);
+                            done.send((notes, words)).ok();
+                        });
+                        cloud.counting = Some(counting);
+                    }
+                    Vec::new()
+                }
+            },
+        };
+        cloud.selected = cloud.selected.min(cloud.entries.len().saturating_sub(1));
+        if let Some(message) = failure {
+            self.notify(message, Level::Error, ERROR_TOAST_DURATION);
+        }
+    }
+
+    fn poll_word_count(&mut self) {
+        let Some(counting) = &self.cloud.counting else {
+            return;
+        };
+        if let Ok(counted) = counting.try_recv() {
+            self.cloud.counting = None;
+            self.cloud.words = Some(counted);
+            if self.current_screen == Screen::Cloud {
+                self.refresh_cloud();
+                self.needs_redraw = true;
+            }
+        }
+    }
+
+    /// Shows the notes behind the selected entry: the tag expanded in the tag browser, or a
+    /// search for the word.
+    fn open_cloud_entry(&mut self) {
+        let Some((name, _)) = self.cloud.entries.get(self.cloud.selected).cloned() else {
+            return;
+        };
+        match self.cloud.mode {
+            CloudMode::Tags => {
+                self.set_screen(Screen::TagBrowser);
+                self.refresh_tag_browser();
+                let browser = &mut self.tag_browser;
+                if let Some(index) = browser.nodes.iter().position(|node| node.tag == name) {
+                    browser.nodes[index].expanded = true;
+                    browser.selected = browser.node_row(index);
+                }
+            }
+            CloudMode::Words => {
+                let search = &mut self.advanced_search;
+                search.query_input = Input::new(name);
+                search.min_input.reset();
+                search.max_input.reset();
+                search.focused_input = SearchField::Query;
+                self.set_screen(Screen::AdvancedSearch);
+                self.refresh_advanced_search();
+            }
+        }
+    }
+
+    fn refresh_stats(&mut self) {
+        match self.db.get_stats() {
+            Ok(stats) => self.stats = stats,
+            Err(e) => self.notify(
+                format!("Failed to load statistics: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            ),
+        }
+    }
+
+    fn reload_notes(&mut self) -> Result<(), DbError> {
+        self.session_changed = true;
+        self.notes.items = if self.show_archived {
+            self.db
+                .get_archived_notes(self.sort_order, self.random_seed)?
+                .iter()
+                .filter(|note| self.color_filter.is_none_or(|color| note.color == color))
+                .cloned()
+                .collect()
+        } else {
+            match self.color_filter {
+                Some(color) => self.db.get_notes_by_color(color)?.into(),
+                None => self
+                    .db
+                    .get_all_notes_sorted(self.sort_order, self.random_seed)?,
+            }
+        };
+        if let Some(filter) = &self.list_filter {
+            self.notes.items = self
+                .notes
+                .items
+                .iter()
+                .filter(|note| filter.matches(note, self.advanced_search.options))
+                .cloned()
+                .collect();
+        }
+        // A note deleted with `d` is only removed from the database once the undo window closes.
+        if let Some(pending) = &self.pending_deletion {
+            self.notes.items = self
+                .notes
+                .items
+                .iter()
+                .filter(|note| note.id != pending.note.id)
+                .cloned()
+                .collect();
+        }
+        let selected = self
+            .notes
+            .state
+            .selected()
+            .filter(|&index| index < self.notes.items.len())
+            .or((!self.notes.items.is_empty()).then_some(0));
+        self.notes.state.select(selected);
+        Ok(())
+    }
+    fn toggle_input(&mut self) {
+        self.wikilink_selected = None;
+        self.focused_input = match self.focused_input {
+            FocusedInput::Title => FocusedInput::Content,
+            FocusedInput::Content => FocusedInput::Title,
+        };
+    }
+    /// Adds a freshly created note where the current sort order puts it and returns its index.
+    fn insert_sorted(&mut self, note: Note) -> usize {
+        let index = match self.sort_order {
+            SortOrder::Title => self.notes.items.partition_point(|other| {
+                self.title_collator.compare(&other.title, &note.title) != Ordering::Greater
+            }),
+            SortOrder::Updated => 0,
+            SortOrder::Created | SortOrder::Random => self.notes.items.len(),
+        };
+        self.notes.apply(NoteListCommand::Insert(index, note));
+        index
+    }
+    fn copy_note(&mut self) {
+        if let Some(note) = self
+            .notes
+            .state
+            .selected()
+            .and_then(|index| self.notes.items.get(index))
+        {
+            let message = format!("Copied \"{}\"", note.title);
+            self.global_clipboard = Some(Note {
+                id: NEW_NOTE_DRAFT_ID,
+                ..note.clone()
+            });
+            self.notify(message, Level::Success, TOAST_DURATION);
+        }
+    }
+    fn paste_note(&mut self) {
+        let Some(clipboard) = &self.global_clipboard else {
+            return;
+        };
+
+        // A pasted note is a duplicate by definition, so the title check is skipped.
+        match self
+            .db
+            .add_note(&clipboard.title, &clipboard.content, false)
+        {
+            Ok(NoteOrDuplicate::Note(note)) => {
+                self.push_undo(UndoableAction::AddNote(note.id));
+                self.notify(
+                    format!("Pasted \"{}\"", note.title),
+                    Level::Success,
+                    TOAST_DURATION,
+                );
+                let index = self.insert_sorted(note);
+                self.notes.state.select(Some(index));
+                self.session_changed = true;
+            }
+            // Not returned while the check is skipped, but pointing at the note it matched
+            // beats failing if that ever changes.
+            Ok(NoteOrDuplicate::Duplicate { existing }) => {
+                self.notify(
+                    format!("\"{}\" already exists", existing.title),
+                    Level::Info,
+                    TOAST_DURATION,
+                );
+                self.select_note_id(existing.id);
+            }
+            Err(e) => self.notify(
+                format!("Paste failed: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            ),
+        }
+    }
+    /// Moves the selected note past the one below it, or above, in the created order.
+    fn move_note(&mut self, down: bool) {
+        if !matches!(self.sort_order, SortOrder::Created) {
+            let keys = self.keymap.list.describe(ListCommand::CycleSort);
+            self.notify(
+                format!("Notes are moved in the oldest first order, {keys} switches to it"),
+                Level::Info,
+                TOAST_DURATION,
+            );
+            return;
+        }
+        let Some(from) = self.notes.state.selected() else {
+            return;
+        };
+        let to = if down {
+            from + 1
+        } else {
+            match from.checked_sub(1) {
+                Some(to) => to,
+                None => return,
+            }
+        };
+        if to >= self.notes.items.len() {
+            return;
+        }
+        match self.swap_notes(from, to) {
+            Ok(()) => self.push_undo(UndoableAction::MoveNote { from, to }),
+            Err(e) => self.notify(
+                format!("Failed to move the note: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            ),
+        }
+    }
+
+    /// Swaps two neighbouring notes, in the database and in the list, and selects the one that
+    /// was at `from`.
+    fn swap_notes(&mut self, from: usize, to: usize) -> Result<(), DbError> {
+        let (moved, other) = (&self.notes.items[from], &self.notes.items[to]);
+        let (moved_position, other_position) = self.db.swap_positions(moved.id, other.id)?;
+        let moved = Note {
+            position: moved_position,
+            ..moved.clone()
+        };
+        let other = Note {
+            position: other_position,
+            ..other.clone()
+        };
+        self.notes.apply(NoteListCommand::Update(from, moved));
+        self.notes.apply(NoteListCommand::Update(to, other));
+        self.notes.apply(NoteListCommand::Reorder(from, to));
+        self.notes.state.select(Some(to));
+        self.session_changed = true;
+        Ok(())
+    }
+
+    /// Changes the selected note with `select`, keeping the preview cursor of the note left and
+    /// restoring the one of the note selected.
+    fn move_selection(&mut self, select: impl FnOnce(&mut Self)) {
+        if let Some(id) = self.selected_note().map(|note| note.id) {
+            match self.preview_cursor {
+                Some(cursor) => self.preview_cursor_per_note.insert(id, cursor),
+                None => self.preview_cursor_per_note.remove(&id),
+            };
+        }
+        select(self);
+        // `select_next` and `select_last` leave the index past the end until the list is drawn,
+        // and the preview is looked up before that.
+        if let Some(last) = self.notes.items.len().checked_sub(1)
+            && self
+                .notes
+                .state
+                .selected()
+                .is_some_and(|index| index > last)
+        {
+            self.notes.state.select(Some(last));
+        }
+        self.preview_cursor = self
+            .selected_note()
+            .and_then(|note| self.preview_cursor_per_note.get(&note.id).copied());
+    }
+
+    fn selected_note(&self) -> Option<&Note> {
+        self.notes
+            .state
+            .selected()
+            .and_then(|index| self.notes.items.get(index))
+    }
+
+    /// Flips the task under the preview cursor and saves the note straight away.
+    fn toggle_checkbox(&mut self) {
+        let (Some(index), Some(line)) = (self.notes.state.selected(), self.preview_cursor) else {
+            return;
+        };
+        let Some(note) = self.notes.items.get(index) else {
+            return;
+        };
+        let Some(content) = checklist::toggle(&note.content, line) else {
+            return;
+        };
+
+        match self.db.update_note(note.id, &note.title, &content) {
+            Ok((updated, _)) => {
+                if let NoteListCommand::Update(_, previous) = self
+                    .notes
+                    .apply(NoteListCommand::Update(index, updated.clone()))
+                {
+                    self.push_undo(UndoableAction::UpdateNote {
+                        id: previous.id,
+                        old: previous,
+                        new: updated,
+                    });
+                }
+                self.session_changed = true;
+            }
+            Err(e) => self.notify(
+                format!("Save failed: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            ),
+        }
+    }
+
+    /// Records a change for undo. A new change can't be redone over, so the redo stack goes.
+    fn push_undo(&mut self, action: UndoableAction) {
+        if self.undo_stack.len() == UNDO_STACK_LIMIT {
+            self.undo_stack.remove(0);
+        }
+        self.undo_stack.push(action);
+        self.redo_stack.clear();
+    }
+
+    fn undo(&mut self) {
+        let Some(action) = self.undo_stack.pop() else {
+            self.notify("Nothing to undo", Level::Info, TOAST_DURATION);
+            return;
+        };
+        match self.revert(action) {
+            Ok(Some(redo)) => self.redo_stack.push(redo),
+            Ok(None) => {}
+            Err(e) => self.notify(
+                format!("Undo failed: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            ),
+        }
+    }
+
+    fn redo(&mut self) {
+        let Some(action) = self.redo_stack.pop() else {
+            self.notify("Nothing to redo", Level::Info, TOAST_DURATION);
+            return;
+        };
+        match self.revert(action) {
+            Ok(Some(undo)) => self.undo_stack.push(undo),
+            Ok(None) => {}
+            Err(e) => self.notify(
+                format!("Redo failed: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            ),
+        }
+    }
+
+    /// Reverts `action` and returns the change that reverts it back, or `None` when there was
+    /// nothing left to revert, such as a note deleted since.
+    fn revert(&mut self, action: UndoableAction) -> Result<Option<UndoableAction>, DbError> {
+        self.session_changed = true;
+        match action {
+            UndoableAction::DeleteNote(note) => {
+                let id = note.id;
+                if self
+                    .pending_deletion
+                    .as_ref()
+                    .is_some_and(|pending| pending.note.id == id)
+                {
+                    self.undo_delete();
+                } else {
+                    // Past the undo window the note is gone from the database, history and all.
+                    let tags = note.tags.clone();
+                    self.db.restore_notes(&[(note, tags)], &[])?;
+                    self.reload_notes()?;
+                    self.select_note_id(id);
+                }
+                Ok(Some(UndoableAction::AddNote(id)))
+            }
+            UndoableAction::AddNote(id) => {
+                if let Some(index) = self.notes.items.iter().position(|n| n.id == id) {
+                    self.flush_deletion();
+                    let note = self.remove_note(index);
+                    return Ok(Some(UndoableAction::DeleteNote(note)));
+                }
+                let Some(note) = self.db.get_note(id)? else {
+                    return Ok(None);
+                };
+                self.db.delete_note(id)?;
+                Ok(Some(UndoableAction::DeleteNote(note)))
+            }
+            UndoableAction::UpdateNote { id, old, new } => {
+                let Some(current) = self.db.get_note(id)? else {
+                    return Ok(None);
+                };
+                let mut restored = current.clone();
+                if (current.title.as_str(), current.content.as_str())
+                    != (old.title.as_str(), old.content.as_str())
+                {
+                    (restored, _) = self.db.update_note(id, &old.title, &old.content)?;
+                }
+                if current.color != old.color {
+                    self.db.set_note_color(id, old.color)?;
+                    restored.color = old.color;
+                }
+                if let Some(index) = self.notes.items.iter().position(|n| n.id == id) {
+                    self.notes
+                        .apply(NoteListCommand::Update(index, restored.clone()));
+                    self.notes.state.select(Some(index));
+                }
+                Ok(Some(UndoableAction::UpdateNote {
+                    id,
+                    old: new,
+                    new: restored,
+                }))
+            }
+            UndoableAction::Archive { id, archived } => {
+                // The view switches to where the note is now, so that it can be selected.
+                self.db.set_archived(id, !archived)?;
+                self.show_archived = !archived;
+                self.reload_notes()?;
+                self.select_note_id(id);
+                Ok(Some(UndoableAction::Archive {
+                    id,
+                    archived: !archived,
+                }))
+            }
+            UndoableAction::MoveNote { from, to } => {
+                if from.max(to) >= self.notes.items.len() {
+                    return Ok(None);
+                }
+                self.swap_notes(to, from)?;
+                Ok(Some(UndoableAction::MoveNote { from: to, to: from }))
+            }
+            UndoableAction::Notes { originals, created } => {
+                // Redoing puts back these notes as they are now, and deletes the originals
+                // that the change had deleted.
+                let mut current = Vec::new();
+                let mut deleted = Vec::new();
+                for id in originals.iter().map(|(note, _)| note.id) {
+                    match self.db.get_note(id)? {
+                        Some(note) => {
+                            let tags = note.tags.clone();
+                            current.push((note, tags));
+                        }
+                        None => deleted.push(id),
+                    }
+                }
+                for id in &created {
+                    if let Some(note) = self.db.get_note(*id)? {
+                        let tags = note.tags.clone();
+                        current.push((note, tags));
+                    }
+                }
+
+                self.db.restore_notes(&originals, &created)?;
+                self.reload_notes()?;
+                if let Some((note, _)) = originals.first() {
+                    self.select_note_id(note.id);
+                }
+                Ok(Some(UndoableAction::Notes {
+                    originals: current,
+                    created: deleted,
+                }))
+            }
+        }
+    }
+
+    /// Opens the title picker right after `[[` is typed and closes it once the cursor leaves
+    /// the link. Typing past `[[` filters the titles, so the selection starts over.
+    fn update_wikilink_picker(&mut self) {
+        self.wikilink_selected = match text_utils::unclosed_wikilink(&self.content_input) {
+            Some("") => Some(0),
+            Some(_) => self.wikilink_selected.map(|_| 0),
+            None => None,
+        };
+    }
+
+    /// Notes whose title contains what was typed after `[[`, other than the one being edited.
+    fn wikilink_matches(&self) -> Vec<&Note> {
+        let Some(typed) = text_utils::unclosed_wikilink(&self.content_input) else {
+            return Vec::new();
+        };
+        let editing = (!self.editing_new)
+            .then(|| self.selected_note().map(|note| note.id))
+            .flatten();
+        self.notes
+            .items
+            .iter()
+            .filter(|note| Some(note.id) != editing)
+            .filter(|note| search::matches(&note.title, typed, self.advanced_search.options))
+            .collect()
+    }
+
+    fn insert_wikilink(&mut self) {
+        let Some(selected) = self.wikilink_selected.take() else {
+            return;
+        };
+        let Some(typed) = text_utils::unclosed_wikilink(&self.content_input) else {
+            return;
+        };
+        let typed = typed.chars().count();
+        let Some(title) = self
+            .wikilink_matches()
+            .get(selected)
+            .map(|note| format!("{}]]", note.title))
+        else {
+            return;
+        };
+
+        self.draft_dirty = true;
+        self.unsaved_changes = true;
+        text_utils::complete_before_cursor(&mut self.content_input, typed, &title);
+    }
+
+    fn append_journal_entry(&mut self) {
+        let text = self.journal_input.value().trim();
+        if text.is_empty() {
+            return;
+        }
+        let at = self.dates.local_time(Utc::now());
+        let (note, created) = match journal::append(&self.db, &self.config.journal, text, at) {
+            Ok(appended) => appended,
+            Err(e) => {
+                self.notify(
+                    format!("Failed to add the entry: {e}"),
+                    Level::Error,
+                    ERROR_TOAST_DURATION,
+                );
+                return;
+            }
+        };
+
+        self.set_screen(Screen::List);
+        self.session_changed = true;
+        self.notify(
+            format!("Added to \"{}\"", note.title),
+            Level::Success,
+            TOAST_DURATION,
+        );
+        if created {
+            let selected = self.notes.state.selected();
+            let index = self.insert_sorted(note);
+            if let Some(selected) = selected.filter(|&selected| selected >= index) {
+                self.notes.state.select(Some(selected + 1));
+            }
+        } else if let Some(index) = self.notes.items.iter().position(|n| n.id == note.id) {
+            self.notes.apply(NoteListCommand::Update(index, note));
+        }
+    }
+
+    /// Shows the earlier versions of the selected note, or says there are none.
+    fn open_history(&mut self) {
+        let Some(note) = self.selected_note() else {
+            return;
+        };
+        let note_id = note.id;
+        match self.db.get_note_history(note_id) {
+            Ok(revisions) if revisions.is_empty() => {
+                self.notify(
+                    "No earlier versions of this note",
+                    Level::Info,
+                    TOAST_DURATION,
+                );
+            }
+            Ok(revisions) => {
+                self.history.note_id = note_id;
+                self.history.revisions = revisions;
+                self.history.selected = 0;
+                self.set_screen(Screen::History);
+            }
+            Err(e) => self.notify(
+                format!("Failed to load history: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            ),
+        }
+    }
+
+    fn compare_revision(&mut self) {
+        let history = &mut self.history;
+        let Some(revision) = history.revisions.get(history.selected) else {
+            return;
+        };
+        let Some(current) = self.notes.items.iter().find(|n| n.id == history.note_id) else {
+            return;
+        };
+        history.diff = diff::line_diff(&revision.content, &current.content);
+        history.scroll = 0;
+        self.set_screen(Screen::RevisionDiff);
+    }
+
+    /// Saves the compared revision over the note like an edit made in the form, so the version
+    /// it replaces goes to the history and can be restored in turn.
+    fn restore_revision(&mut self) {
+        let Some(revision) = self.history.revisions.get(self.history.selected) else {
+            return;
+        };
+        let (title, content) = (revision.title.clone(), revision.content.clone());
+        let saved_at = self.dates.relative(revision.saved_at, Utc::now());
+        let Some(index) = self
+            .notes
+            .items
+            .iter()
+            .position(|n| n.id == self.history.note_id)
+        else {
+            return;
+        };
+
+        self.current_screen = Screen::List;
+        self.move_selection(|app| app.notes.state.select(Some(index)));
+        self.set_screen(Screen::Form);
+        self.load_form();
+        self.title_input = Input::new(title);
+        self.content_input = Input::new(content);
+        self.unsaved_changes = true;
+        self.save_note();
+        if !self.unsaved_changes {
+            self.notify(
+                format!(
+                    "Restored the version of {saved_at}; the one it replaced is in the history"
+                ),
+                Level::Success,
+                TOAST_DURATION,
+            );
+        }
+    }
+
+    fn set_note_color(&mut self, color: NoteColor) {
+        let Some(index) = self.notes.state.selected() else {
+            return;
+        };
+        let note = &self.notes.items[index];
+        if let Err(e) = self.db.set_note_color(note.id, color) {
+            self.notify(
+                format!("Failed to set the color: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            );
+            return;
+        }
+        let colored = Note {
+            color,
+            ..note.clone()
+        };
+        self.push_undo(UndoableAction::UpdateNote {
+            id: note.id,
+            old: note.clone(),
+            new: colored.clone(),
+        });
+        self.notes.apply(NoteListCommand::Update(index, colored));
+        // A note that no longer has the color being shown leaves the list.
+        if self.color_filter.is_some_and(|filter| filter != color)
+            && let Err(e) = self.reload_notes()
+        {
+            self.notify(
+                format!("Failed to reload notes: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            );
+        }
+    }
+
+    /// Lists only the notes sharing the selected note's color, or all of them again.
+    fn toggle_color_filter(&mut self) {
+        let filter = match self.color_filter {
+            Some(_) => None,
+            None => match self.selected_note().map(|note| note.color) {
+                Some(NoteColor::None) | None => {
+                    self.notify(
+                        "Give the note a color to filter by it",
+                        Level::Info,
+                        TOAST_DURATION,
+                    );
+                    return;
+                }
+                color => color,
+            },
+        };
+        let selected_id = self.selected_note().map(|note| note.id);
+        self.color_filter = filter;
+        if let Err(e) = self.reload_notes() {
+            self.notify(
+                format!("Failed to reload notes: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            );
+        }
+        if let Some(id) = selected_id {
+            self.select_note_id(id);
+        }
+    }
+
+    fn open_long_notes(&mut self) {
+        let threshold = self.config.notes.long_note_chars;
+        match self.db.find_long_notes(threshold) {
+            Ok(long) if long.is_empty() => {
+                self.notify(
+                    format!("No note is longer than {threshold} characters"),
+                    Level::Info,
+                    TOAST_DURATION,
+                );
+            }
+            Ok(long) => {
+                self.long_notes = long;
+                self.long_selected = 0;
+                self.set_screen(Screen::LongNotes);
+            }
+            Err(e) => self.notify(
+                format!("Failed to find long notes: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            ),
+        }
+    }
+
+    /// Selects the chosen long note in the list and, to `split` it, opens the split screen.
+    fn open_long_note(&mut self, split: bool) {
+        let Some(note) = self.long_notes.get(self.long_selected) else {
+            return;
+        };
+        let Some(index) = self.notes.items.iter().position(|n| n.id == note.id) else {
+            self.notify(
+                format!("\"{}\" isn't in the list", note.title),
+                Level::Warning,
+                TOAST_DURATION,
+            );
+            return;
+        };
+
+        self.current_screen = Screen::List;
+        self.move_selection(|app| app.notes.state.select(Some(index)));
+        if split {
+            self.open_split_view();
+        }
+    }
+
+    /// Opens the split screen on the selected note, the divider on the paragraph nearest the
+    /// middle.
+    fn open_split_view(&mut self) {
+        let Some(note) = self.selected_note() else {
+            return;
+        };
+        let lines = note.content.split('\n').collect::<Vec<_>>();
+        if lines.len() < 2 {
+            self.notify(
+                "A note needs two lines to be split",
+                Level::Warning,
+                TOAST_DURATION,
+            );
+            return;
+        }
+        let middle = lines.len() / 2;
+        let line = (1..lines.len())
+            .filter(|&line| lines[line - 1].trim().is_empty())
+            .min_by_key(|&line| line.abs_diff(middle))
+            .unwrap_or(middle);
+        self.split_view.note_id = note.id;
+        self.split_view.line = line;
+        self.set_screen(Screen::SplitNote);
+    }
+
+    /// Replaces the note on the split screen with the two halves on either side of its divider.
+    fn split_at_divider(&mut self) {
+        let Some(original) = self
+            .notes
+            .items
+            .iter()
+            .find(|note| note.id == self.split_view.note_id)
+            .cloned()
+        else {
+            return;
+        };
+        let tail = original
+            .content
+            .split_inclusive('\n')
+            .skip(self.split_view.line)
+            .collect::<String>();
+        let new_title = db::title_from_content(&tail);
+        if new_title.is_empty() {
+            self.notify(
+                "The part split off needs a first line to be titled after",
+                Level::Warning,
+                TOAST_DURATION,
+            );
+            return;
+        }
+        if self.config.notes.unique_titles
+            && !matches!(self.db.get_note_by_title(&new_title), Ok(None))
+        {
+            self.notify(
+                format!("\"{new_title}\" is already taken"),
+                Level::Warning,
+                TOAST_DURATION,
+            );
+            return;
+        }
+
+        let result = self.db.get_note_tags(original.id).and_then(|tags| {
+            let notes = self.db.split_note(original.id, self.split_view.line)?;
+            Ok((notes, tags))
+        });
+        match result.and_then(|(notes, tags)| self.reload_notes().map(|()| (notes, tags))) {
+            Ok(((top, bottom), tags)) => {
+                self.push_undo(UndoableAction::Notes {
+                    originals: vec![(original, tags)],
+                    created: vec![top.id, bottom.id],
+                });
+                self.notify(
+                    format!("Split into \"{}\" and \"{}\"", top.title, bottom.title),
+                    Level::Success,
+                    TOAST_DURATION,
+                );
+                self.select_note_id(top.id);
+                self.set_screen(Screen::List);
+            }
+            Err(e) => self.notify(
+                format!("Split failed: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            ),
+        }
+    }
+
+    fn open_broken_links(&mut self) {
+        match self.db.find_notes_with_broken_links() {
+            Ok(broken) if broken.is_empty() => {
+                self.notify("No broken links", Level::Info, TOAST_DURATION);
+            }
+            Ok(broken) => {
+                self.broken_links = broken;
+                self.broken_selected = 0;
+                self.set_screen(Screen::BrokenLinks);
+            }
+            Err(e) => self.notify(
+                format!("Failed to check links: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            ),
+        }
+    }
+
+    /// Opens the note selected in the broken links popup, the cursor on its first broken link.
+    fn open_broken_link(&mut self) {
+        let Some((note, targets)) = self.broken_links.get(self.broken_selected) else {
+            return;
+        };
+        let Some(index) = self.notes.items.iter().position(|n| n.id == note.id) else {
+            self.notify(
+                format!("\"{}\" was deleted", note.title),
+                Level::Warning,
+                TOAST_DURATION,
+            );
+            return;
+        };
+        let current = &self.notes.items[index].content;
+        let link = wiki_parser::links(current)
+            .into_iter()
+            .find(|link| targets.iter().any(|target| target == link.target))
+            .map(|link| current[..link.start].chars().count());
+
+        self.current_screen = Screen::List;
+        self.move_selection(|app| app.notes.state.select(Some(index)));
+        self.set_screen(Screen::Form);
+        self.load_form();
+        self.focused_input = FocusedInput::Content;
+        if let Some(cursor) = link {
+            self.content_input = self.content_input.clone().with_cursor(cursor);
+        }
+    }
+
+    /// Opens the note of `date` in the form with the cursor at the end of its content, creating
+    /// it first if needed.
+    fn open_daily_note(&mut self, date: NaiveDate) {
+        let (note, created) = match self.daily.open(&self.db, date) {
+            Ok(opened) => opened,
+            Err(e) => {
+                self.notify(
+                    format!("Failed to open the daily note: {e}"),
+                    Level::Error,
+                    ERROR_TOAST_DURATION,
+                );
+                return;
+            }
+        };
+        let index = if created {
+            self.session_changed = true;
+            self.insert_sorted(note)
+        } else {
+            match self.notes.items.iter().position(|n| n.id == note.id) {
+                Some(index) => index,
+                None => {
+                    self.notify(
+                        format!("\"{}\" is archived", note.title),
+                        Level::Warning,
+                        TOAST_DURATION,
+                    );
+                    return;
+                }
+            }
+        };
+
+        self.move_selection(|app| app.notes.state.select(Some(index)));
+        self.set_screen(Screen::Form);
+        self.load_form();
+        self.focused_input = FocusedInput::Content;
+    }
+
+    /// Moves from the daily note in the form to the one `days` later, or earlier if negative.
+    fn step_daily_note(&mut self, days: i64) {
+        if self.unsaved_changes {
+            self.notify(
+                "Save the note before changing day",
+                Level::Warning,
+                TOAST_DURATION,
+            );
+            return;
+        }
+        let Some(date) = self.daily.date_of(self.title_input.value()) else {
+            self.notify("Not a daily note", Level::Warning, TOAST_DURATION);
+            return;
+        };
+        if let Some(date) = date.checked_add_signed(chrono::Duration::days(days)) {
+            self.open_daily_note(date);
+        }
+    }
+
+    /// Opens the note after the one in the form, or before it, without going back to the list.
+    fn step_note(&mut self, forward: bool) {
+        if self.unsaved_changes || self.editing_new {
+            self.notify(
+                "Save the note before moving to another",
+                Level::Warning,
+                TOAST_DURATION,
+            );
+            return;
+        }
+        let Some(note) = self.selected_note() else {
+            return;
+        };
+        let siblings = self
+            .db
+            .get_note_siblings(note.id, self.sort_order, self.random_seed);
+        let sibling = match siblings {
+            Ok((previous, next)) => {
+                if forward {
+                    next
+                } else {
+                    previous
+                }
+            }
+            Err(e) => {
+                self.notify(
+                    format!("Failed to find the next note: {e}"),
+                    Level::Error,
+                    ERROR_TOAST_DURATION,
+                );
+                return;
+            }
+        };
+        let Some(sibling) = sibling else {
+            return;
+        };
+        match self.notes.items.iter().position(|n| n.id == sibling.id) {
+            Some(index) => {
+                self.notes.state.select(Some(index));
+                self.load_form();
+            }
+            // Hidden by the color filter, or added elsewhere since the list was loaded.
+            None => self.notify(
+                format!("\"{}\" isn't in the list", sibling.title),
+                Level::Warning,
+                TOAST_DURATION,
+            ),
+        }
+    }
+
+    fn export_pdf(&mut self) {
+        let path = self.pdf_path_input.value().trim();
+        if path.is_empty() {
+            return;
+        }
+        let output = PathBuf::from(path);
+        self.set_screen(Screen::List);
+        let Some(note) = self.selected_note() else {
+            return;
+        };
+
+        pdf_export::spawn(
+            &self.config.export.pdf_command,
+            note,
+            output.clone(),
+            self.pdf_exports.0.clone(),
+        );
+        self.notify(
+            format!("Exporting {}…", output.display()),
+            Level::Info,
+            TOAST_DURATION,
+        );
+    }
+
+    fn poll_pdf_exports(&mut self) {
+        while let Ok(outcome) = self.pdf_exports.1.try_recv() {
+            match outcome.result {
+                Ok(()) => self.notify(
+                    format!("Exported {}", outcome.output.display()),
+                    Level::Success,
+                    TOAST_DURATION,
+                ),
+                Err(e) => self.notify(
+                    format!("PDF export failed: {e} (see {})", pdf_export::LOG_PATH),
+                    Level::Error,
+                    ERROR_TOAST_DURATION,
+                ),
+            }
+            self.needs_redraw = true;
+        }
+    }
+
+    fn remember_viewed(&mut self, id: i64) {
+        self.recently_viewed.retain(|&viewed| viewed != id);
+        self.recently_viewed.push_front(id);
+        self.recently_viewed.truncate(RECENTLY_VIEWED_LIMIT);
+
+        let ids = self
+            .recently_viewed
+            .iter()
+            .map(i64::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        if let Err(e) = self.db.set_state(RECENTLY_VIEWED_KEY, &ids) {
+            self.notify(
+                format!("Failed to save recent notes: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            );
+        }
+    }
+
+    /// The recently viewed notes still in the list, with their indexes in it. Deleted and
+    /// archived notes are skipped but kept in `recently_viewed`, for undo and unarchiving.
+    fn recent_notes(&self) -> Vec<(usize, &Note)> {
+        self.recently_viewed
+            .iter()
+            .filter_map(|&id| {
+                self.notes
+                    .items
+                    .iter()
+                    .enumerate()
+                    .find(|(_, n)| n.id == id)
+            })
+            .collect()
+    }
+
+    /// The note marks apply to: the one in the form, or the one previewed in the list. A new
+    /// note has no id to keep marks under until it is saved.
+    fn marked_note(&self) -> Option<i64> {
+        match self.current_screen {
+            Screen::Form if self.editing_new => None,
+            _ => self.selected_note().map(|note| note.id),
+        }
+    }
+
+    /// The content marks point into, as currently shown on `screen`.
+    fn marked_content(&self, screen: Screen) -> &str {
+        match screen {
+            Screen::Form => self.content_input.value(),
+            _ => self
+                .selected_note()
+                .map_or("", |note| note.content.as_str()),
+        }
+    }
+
+    fn set_mark(&mut self, name: char) {
+        let Some(note_id) = self.marked_note() else {
+            self.notify(
+                "Save the note before setting marks",
+                Level::Warning,
+                TOAST_DURATION,
+            );
+            return;
+        };
+        let line = match self.current_screen {
+            Screen::Form => text_utils::cursor_line(&self.content_input),
+            _ => self.preview_cursor.unwrap_or(0),
+        };
+
+        match self.db.set_mark(note_id, name, line) {
+            Ok(()) => self.notify(
+                format!("Mark '{name}' set at line {}", line + 1),
+                Level::Success,
+                TOAST_DURATION,
+            ),
+            Err(e) => self.notify(
+                format!("Failed to set mark: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            ),
+        }
+    }
+
+    /// Moves the editor cursor, or the preview cursor in the list, to the line of a mark. A
+    /// mark past the end of the note lands on its last line.
+    fn jump_to_mark(&mut self, name: char) {
+        let Some(note_id) = self.marked_note() else {
+            return;
+        };
+        let mark = match self.db.get_marks(note_id) {
+            Ok(marks) => marks.into_iter().find(|mark| mark.name == name),
+            Err(e) => {
+                self.notify(
+                    format!("Failed to load marks: {e}"),
+                    Level::Error,
+                    ERROR_TOAST_DURATION,
+                );
+                return;
+            }
+        };
+        let Some(mark) = mark else {
+            self.notify(
+                format!("No mark '{name}' in this note"),
+                Level::Warning,
+                TOAST_DURATION,
+            );
+            return;
+        };
+
+        match self.current_screen {
+            Screen::Form => {
+                self.focused_input = FocusedInput::Content;
+                text_utils::move_to_line(&mut self.content_input, mark.line);
+            }
+            _ => {
+                let last_line = self.marked_content(Screen::List).matches('\n').count();
+                self.preview_cursor = Some(mark.line.min(last_line));
+            }
+        }
+    }
+
+    fn open_marks(&mut self) {
+        let Some(note_id) = self.marked_note() else {
+            self.notify(
+                "Save the note before setting marks",
+                Level::Warning,
+                TOAST_DURATION,
+            );
+            return;
+        };
+        let marks = match self.db.get_marks(note_id) {
+            Ok(marks) => marks,
+            Err(e) => {
+                self.notify(
+                    format!("Failed to load marks: {e}"),
+                    Level::Error,
+                    ERROR_TOAST_DURATION,
+                );
+                return;
+            }
+        };
+        if marks.is_empty() {
+            self.notify("No marks in this note", Level::Info, TOAST_DURATION);
+            return;
+        }
+
+        self.marks_popup = MarksPopup {
+            marks,
+            selected: 0,
+            return_to: self.current_screen,
+        };
+        self.current_screen = Screen::Marks;
+    }
+
+    fn close_marks(&mut self) {
+        if self.current_screen == Screen::Marks {
+            self.current_screen = self.marks_popup.return_to;
+        }
+    }
+
+    fn open_quick_switcher(&mut self) {
+        let tags = match self.db.get_tags_by_note() {
+            Ok(tags) => tags,
+            Err(e) => {
+                self.notify(
+                    format!("Failed to load tags: {e}"),
+                    Level::Error,
+                    ERROR_TOAST_DURATION,
+                );
+                HashMap::new()
+            }
+        };
+        let switcher = &mut self.quick_switcher;
+        switcher.input.reset();
+        switcher.tags = tags;
+        switcher.return_to = self.current_screen;
+        self.current_screen = Screen::QuickSwitcher;
+        self.refresh_quick_switcher();
+    }
+
+    fn open_text_search(&mut self) {
+        self.text_search.input.reset();
+        self.text_search.results.clear();
+        self.text_search.selected = 0;
+        self.set_screen(Screen::TextSearch);
+    }
+
+    fn refresh_text_search(&mut self) {
+        let search = &mut self.text_search;
+        match self.db.search(search.input.value()) {
+            Ok(results) => {
+                search.results = results;
+                search.selected = 0;
+            }
+            Err(e) => self.notify(
+                format!("Search failed: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            ),
+        }
+    }
+
+    /// Selects the chosen result in the list, clearing the list's filters if they hide it.
+    fn jump_to_search_result(&mut self) {
+        let search = &self.text_search;
+        let Some(id) = search.results.get(search.selected).map(|note| note.id) else {
+            return;
+        };
+        if !self.notes.items.iter().any(|note| note.id == id) {
+            self.list_filter = None;
+            self.color_filter = None;
+            self.show_archived = false;
+            if let Err(e) = self.reload_notes() {
+                self.notify(
+                    format!("Failed to reload notes: {e}"),
+                    Level::Error,
+                    ERROR_TOAST_DURATION,
+                );
+            }
+        }
+        self.set_screen(Screen::List);
+        self.move_selection(|app| app.select_note_id(id));
+    }
+
+    /// Ranks titles against the input, keeping list order between equal scores.
+    fn refresh_quick_switcher(&mut self) {
+        let switcher = &mut self.quick_switcher;
+        let query = switcher.input.value();
+        let mut scored = self
+            .notes
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, note)| Some((fuzzy::score(query, &note.title)?, index)))
+            .collect::<Vec<_>>();
+        scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+        switcher.results = scored
+            .into_iter()
+            .take(QUICK_SWITCHER_LIMIT)
+            .map(|(_, index)| index)
+            .collect();
+        switcher.selected = 0;
+    }
+
+    /// The marked notes in list order, or the selected one when nothing is marked.
+    fn bulk_tag_targets(&self) -> Vec<&Note> {
+        if self.marked.is_empty() {
+            return self.selected_note().into_iter().collect();
+        }
+        self.notes
+            .items
+            .iter()
+            .filter(|note| self.marked.contains(&note.id))
+            .collect()
+    }
+
+    fn apply_tag_edits(&mut self) {
+        let edits = TagEdits::parse(self.tag_editor.input.value());
+        if edits.is_empty() {
+            self.set_screen(Screen::List);
+            return;
+        }
+
+        let targets = self.bulk_tag_targets();
+        let ids = targets.iter().map(|note| note.id).collect::<Vec<_>>();
+        let originals = targets
+            .into_iter()
+            .map(|note| Ok((note.clone(), self.db.get_note_tags(note.id)?)))
+            .collect::<Result<Vec<_>, DbError>>();
+        let result = originals.and_then(|originals| {
+            let touched = self.db.edit_tags(&ids, &edits.add, &edits.remove)?;
+            self.reload_notes()?;
+            Ok((originals, touched))
+        });
+
+        match result {
+            Ok((originals, touched)) => {
+                self.session_changed = true;
+                self.marked.clear();
+                self.push_undo(UndoableAction::Notes {
+                    originals,
+                    created: Vec::new(),
+                });
+                self.notify(
+                    format!("Retagged {touched} notes, u undo"),
+                    Level::Success,
+                    TOAST_DURATION,
+                );
+                self.set_screen(Screen::List);
+            }
+            Err(e) => self.notify(
+                format!("Tagging failed: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            ),
+        }
+    }
+
+    /// Archives the selected note, or in the archived view brings it back. It leaves the list
+    /// either way, and the next note is selected.
+    fn archive_note(&mut self) {
+        let Some(note) = self.selected_note() else {
+            return;
+        };
+        let (id, title, archived) = (note.id, note.title.clone(), !self.show_archived);
+        if let Err(e) = self.db.set_archived(id, archived) {
+            self.notify(
+                format!("Failed to archive the note: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            );
+            return;
+        }
+        self.push_undo(UndoableAction::Archive { id, archived });
+        self.move_selection(|app| {
+            if let Err(e) = app.reload_notes() {
+                app.notify(
+                    format!("Failed to reload notes: {e}"),
+                    Level::Error,
+                    ERROR_TOAST_DURATION,
+                );
+            }
+        });
+        let message = if archived {
+            let keys = self.keymap.list.describe(ListCommand::ToggleArchived);
+            format!("Archived \"{title}\", {keys} lists the archived notes")
+        } else {
+            format!("Brought \"{title}\" back to the list")
+        };
+        self.notify(message, Level::Success, TOAST_DURATION);
+    }
+
+    /// Switches the list between the notes in use and the archived ones.
+    fn toggle_archived_view(&mut self) {
+        self.show_archived = !self.show_archived;
+        self.move_selection(|app| {
+            app.notes.state.select(Some(0));
+            if let Err(e) = app.reload_notes() {
+                app.notify(
+                    format!("Failed to reload notes: {e}"),
+                    Level::Error,
+                    ERROR_TOAST_DURATION,
+                );
+            }
+        });
+    }
+
+    /// Lists the notes in the next order, with the same note selected.
+    fn cycle_sort(&mut self) {
+        let selected_id = self.selected_note().map(|note| note.id);
+        self.sort_order = self.sort_order.next();
+        self.move_selection(|app| {
+            if let Err(e) = app.reload_notes() {
+                app.notify(
+                    format!("Failed to sort notes: {e}"),
+                    Level::Error,
+                    ERROR_TOAST_DURATION,
+                );
+            }
+            if let Some(id) = selected_id {
+                app.select_note_id(id);
+            }
+        });
+    }
+
+    fn open_list_filter(&mut self) {
+        if self.list_filter.is_none() {
+            self.list_filter = Some(ListFilter {
+                input: Input::default(),
+                typing: true,
+                return_to: self.selected_note().map(|note| note.id),
+                regex: None,
+                error: None,
+            });
+        } else if let Some(filter) = &mut self.list_filter {
+            filter.typing = true;
+        }
+    }
+
+    /// Filters the list again after the query changed, keeping the selected note if it still
+    /// matches and selecting the best, first, match otherwise.
+    fn refresh_list_filter(&mut self) {
+        let selected_id = self.selected_note().map(|note| note.id);
+        self.move_selection(|app| {
+            if let Err(e) = app.reload_notes() {
+                app.notify(
+                    format!("Failed to filter notes: {e}"),
+                    Level::Error,
+                    ERROR_TOAST_DURATION,
+                );
+            }
+            app.notes
+                .state
+                .select((!app.notes.items.is_empty()).then_some(0));
+            if let Some(id) = selected_id {
+                app.select_note_id(id);
+            }
+        });
+    }
+
+    /// Lists every note again, with the note selected in the filtered list still selected.
+    fn clear_list_filter(&mut self) {
+        let Some(filter) = self.list_filter.take() else {
+            return;
+        };
+        let selected_id = self
+            .selected_note()
+            .map(|note| note.id)
+            .or(filter.return_to);
+        self.move_selection(|app| {
+            if let Err(e) = app.reload_notes() {
+                app.notify(
+                    format!("Failed to reload notes: {e}"),
+                    Level::Error,
+                    ERROR_TOAST_DURATION,
+                );
+            }
+            if let Some(id) = selected_id {
+                app.select_note_id(id);
+            }
+        });
+    }
+
+    fn select_note_id(&mut self, id: i64) {
+        if let Some(index) = self.notes.items.iter().position(|n| n.id == id) {
+            self.notes.state.select(Some(index));
+        }
+    }
+
+    fn mark_merge_source(&mut self) {
+        let Some(note) = self.selected_note() else {
+            return;
+        };
+        if self.merge_source == Some(note.id) {
+            self.merge_source = None;
+            return;
+        }
+
+        let title = note.title.clone();
+        self.merge_source = Some(note.id);
+        let merge_into = self.keymap.list.describe(ListCommand::MergeInto);
+        self.notify(
+            format!("Marked \"{title}\", select another note and press {merge_into}"),
+            Level::Info,
+            TOAST_DURATION,
+        );
+    }
+
+    /// The marked note and the selected one, when they can be merged.
+    fn merge_pair(&self) -> Result<(&Note, &Note), &'static str> {
+        let source = self
+            .merge_source
+            .and_then(|id| self.notes.items.iter().find(|n| n.id == id))
+            .ok_or("Mark a note to merge first")?;
+        let target = self.selected_note().ok_or("Select a note to merge into")?;
+        if source.id == target.id {
+            return Err("Can't merge a note into itself");
+        }
+        Ok((source, target))
+    }
+
+    fn merge_notes(&mut self) {
+        let (source, target) = match self.merge_pair() {
+            Ok((source, target)) => (source.clone(), target.clone()),
+            Err(e) => {
+                self.notify(e.to_string(), Level::Warning, TOAST_DURATION);
+                return;
+            }
+        };
+
+        let result = self.db.get_note_tags(target.id).and_then(|target_tags| {
+            let source_tags = self.db.get_note_tags(source.id)?;
+            self.db.merge_notes(&source, &target)?;
+            Ok(vec![
+                (target.clone(), target_tags),
+                (source.clone(), source_tags),
+            ])
+        });
+
+        match result.and_then(|originals| {
+            self.push_undo(UndoableAction::Notes {
+                originals,
+                created: Vec::new(),
+            });
+            self.reload_notes()
+        }) {
+            Ok(()) => {
+                self.merge_source = None;
+                self.select_note_id(target.id);
+                self.notify(
+                    format!("Merged \"{}\" into \"{}\"", source.title, target.title),
+                    Level::Success,
+                    TOAST_DURATION,
+                );
+            }
+            Err(e) => self.notify(
+                format!("Merge failed: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            ),
+        }
+    }
+
+    /// Takes the note off the list right away; the database delete waits for the undo window
+    /// to lapse, the next delete, or quitting.
+    fn delete_note(&mut self) {
+        self.flush_deletion();
+        if let Some(selected_index) = self.notes.state.selected()
+            && selected_index < self.notes.items.len()
+        {
+            let note = self.remove_note(selected_index);
+            self.push_undo(UndoableAction::DeleteNote(note));
+        }
+    }
+
+    /// Takes the note at `index` off the list, leaving it as the pending deletion, and returns
+    /// it.
+    fn remove_note(&mut self, index: usize) -> Note {
+        let note = self.notes.items[index].clone();
+        self.notes.apply(NoteListCommand::Remove(index));
+        self.session_changed = true;
+        if index != 0 {
+            self.notes.state.select(Some(index - 1));
+        }
+        self.pending_deletion = Some(PendingDeletion {
+            note: note.clone(),
+            index,
+            deadline: Instant::now() + UNDO_DELETE_WINDOW,
+        });
+        note
+    }
+
+    fn flush_deletion(&mut self) {
+        if let Some(pending) = self.pending_deletion.take()
+            && let Err(e) = self.db.delete_note(pending.note.id)
+        {
+            self.notify(
+                format!("Delete failed: {e}"),
+                Level::Error,
+                ERROR_TOAST_DURATION,
+            );
+        }
+    }
+
+    fn undo_delete(&mut self) {
+        if let Some(pending) = self.pending_deletion.take() {
+            let index = pending.index.min(self.notes.items.len());
+            self.notes
+                .apply(NoteListCommand::Insert(index, pending.note));
+            self.notes.state.select(Some(index));
+        }
+    }
+}
+
+const EMPTY_STATE_ART: &[&str] = &[
+    " _o_o_o_o_o_ ",
+    "|           |",
+    "| ~~~~~~~~  |",
+    "| ~~~~~~    |",
+    "| ~~~~~~~~~ |",
+    "| ~~~~      |",
+    "|___________|",
+];
+
+/// Shown instead of the list and preview panes while there are no notes.
+fn render_empty_state(frame: &mut Frame, area: Rect) {
+    let mut lines: Vec<Line> = EMPTY_STATE_ART.iter().map(|row| Line::raw(*row)).collect();
+    lines.push(Line::default());
+    lines.push(Line::raw("No notes yet."));
+    lines.push(Line::raw("Press 'a' to add your first note."));
+
+    let message_area = area.centered(
+        Constraint::Percentage(100),
+        Constraint::Length(lines.len() as u16),
+    );
+    frame.render_widget(
+        Paragraph::new(lines).centered().style(Style::new().dim()),
+        message_area,
+    );
+}
+
+/// The titles offered after `[[` in the content editor, `selected_index` highlighted.
+fn render_wikilink_picker(
+    frame: &mut Frame,
+    area: Rect,
+    matching_notes: &[&Note],
+    selected_index: usize,
+    theme: &Theme,
+) {
+    let block = Block::bordered().title(" Link to ");
+    let list = if matching_notes.is_empty() {
+        List::new([Line::from("No matching note").dim()])
+    } else {
+        matching_notes
+            .iter()
+            .map(|note| Line::from(note.title.as_str()))
+            .collect::<List>()
+            .highlight_style(theme.selection())
+    };
+    let mut state = ListState::default().with_selected(Some(selected_index));
+
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(list.block(block), area, &mut state);
+}
+
+/// Splits `text` into spans with every match of `query` shown in `color`.
+fn highlight_matches<'a>(
+    text: &'a str,
+    query: &str,
+    options: SearchOptions,
+    color: Color,
+) -> Line<'a> {
+    highlight_ranges(text, 0, &search::find_matches(text, query, options), color)
+}
+
+/// Splits `part`, found `start` bytes into a longer text, into spans with the bytes it shares
+/// with `matches`, ranges of that text in order, shown in `color`.
+fn highlight_ranges<'a>(
+    part: &'a str,
+    start: usize,
+    matches: &[Range<usize>],
+    color: Color,
+) -> Line<'a> {
+    let mut line = Line::default();
+    let mut end = 0;
+    for range in matches {
+        let from = range.start.saturating_sub(start).min(part.len());
+        let to = range.end.saturating_sub(start).min(part.len());
+        if from == to {
+            continue;
+        }
+        line.push_span(&part[end..from]);
+        line.push_span(part[from..to].bold().fg(color));
+        end = to;
+    }
+    line.push_span(&part[end..]);
+    line
+}
+
+/// Draws `node` indented by `depth` levels, followed by its notes one level deeper when it is
+/// expanded. `selected` is the highlighted row within the node. Returns the rows drawn.
+fn render_tree_node(
+    node: &TreeNode,
+    depth: u16,
+    frame: &mut Frame,
+    area: Rect,
+    selected: Option<usize>,
+    theme: &Theme,
+) -> u16 {
+    let indent = "  ".repeat(depth as usize);
+    let marker = if node.expanded { "▼" } else { "▶" };
+    let mut lines = vec![Line::from(format!(
+        "{indent}{marker} {} ({})",
+        node.tag,
+        node.notes.len()
+    ))];
+    if node.expanded {
+        lines.extend(
+            node.notes
+                .iter()
+                .map(|note| Line::from(format!("{indent}    {}", note.title))),
+        );
+    }
+
+    let rows = (lines.len() as u16).min(area.height);
+    for (row, line) in lines.into_iter().take(rows as usize).enumerate() {
+        let line = if selected == Some(row) {
+            line.style(theme.selection())
+        } else {
+            line
+        };
+        let line_area = Rect {
+            y: area.y + row as u16,
+            height: 1,
+            ..area
+        };
+        frame.render_widget(line, line_area);
+    }
+    rows
+}
+
+/// One `name count` line per row, with the count dimmed.
+fn counted_list<'a>(rows: &'a [(String, u32)], unit: &str) -> List<'a> {
+    rows.iter()
+        .map(|(name, count)| Line::from_iter([name.to_span(), format!(" {count}{unit}").dim()]))
+        .collect()
+}
+
+/// `[tag1, tag2, +N]`, or nothing for an untagged note.
+fn tag_chips(tags: &[String]) -> Option<Span<'static>> {
+    if tags.is_empty() {
+        return None;
+    }
+    let chips = if tags.len() > TAG_CHIPS_LIMIT {
+        format!("{}, {}, +{}", tags[0], tags[1], tags.len() - 2)
+    } else {
+        tags.join(", ")
+    };
+    Some(Span::from(format!("[{chips}]")).dim().bg(Color::DarkGray))
+}
+
+/// `title` as a file name in the current directory, keeping letters, digits, `-` and `_`.
+fn pdf_file_name(title: &str) -> String {
+    let name = title
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    if name.is_empty() {
+        "note.pdf".to_string()
+    } else {
+        format!("{name}.pdf")
+    }
+}
+
+/// Draws `lines` in a bordered box centered over the dimmed current screen.
+fn render_modal(frame: &mut Frame, lines: Vec<Line>, width: u16) {
+    let area = frame.area();
+    frame.buffer_mut().set_style(area, Style::new().dim());
+
+    let modal_area = area.centered(
+        Constraint::Length(width),
+        Constraint::Length(lines.len() as u16 + 2),
+    );
+    let modal = Paragraph::new(lines).block(Block::bordered().border_set(border::THICK));
+
+    frame.render_widget(Clear, modal_area);
+    frame.render_widget(modal, modal_area);
+}
+
+/// `[[one]], [[two]]`
+fn wikilinks(targets: &[String]) -> String {
+    targets
+        .iter()
+        .map(|target| format!("[[{target}]]"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Extends `pending` with `key` and resolves it against `bindings`. A key that doesn't continue
+/// the pending sequence is retried on its own, so a stray prefix doesn't swallow it.
+fn lookup_sequence<C: keymap::Command>(
+    bindings: &keymap::Bindings<C>,
+    pending: &mut Vec<KeyPress>,
+    key: event::KeyEvent,
+) -> Lookup<C> {
+    let key = KeyPress::from_event(key);
+    pending.push(key);
+
+    let mut lookup = bindings.lookup(pending);
+    if matches!(lookup, Lookup::NoMatch) && pending.len() > 1 {
+        pending.clear();
+        pending.push(key);
+        lookup = bindings.lookup(pending);
+    }
+
+    if !matches!(lookup, Lookup::Pending) {
+        pending.clear();
+    }
+    lookup
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{TempDir, database};
+
+    /// An app on the list of notes titled `titles`, in that order, with the default config.
+    fn app(titles: &[&str]) -> (TempDir, App) {
+        let dir = TempDir::new();
+        let db = database(&dir);
+        for title in titles {
+            db.add_note_with_created_at(title, "", None).unwrap();
+        }
+        let config = Config::default();
+        let keymap = Keymap::new(&config.keymap).unwrap();
+        let theme = Theme::new(&config.theme);
+        let dates = DateDisplay::new(&config.display).unwrap();
+        let daily = Daily::new(&config.daily).unwrap();
+        let collator = TitleCollator::new("und").unwrap();
+        let app = App::new(config, keymap, theme, db, collator, dates, daily).unwrap();
+        (dir, app)
+    }
+
+    fn send(app: &mut App, code: KeyCode, kind: KeyEventKind) {
+        let key = event::KeyEvent::new_with_kind(code, KeyModifiers::NONE, kind);
+        app.handle_event(Event::Key(key));
+    }
+
+    fn press(app: &mut App, key: char) {
+        send(app, KeyCode::Char(key), KeyEventKind::Press);
+    }
+
+    fn hold(app: &mut App, key: char) {
+        send(app, KeyCode::Char(key), KeyEventKind::Repeat);
+    }
+
+    fn release(app: &mut App, key: char) {
+        send(app, KeyCode::Char(key), KeyEventKind::Release);
+    }
+
+    fn selected(app: &App) -> usize {
+        app.notes.state.selected().unwrap()
+    }
+
+    /// Ticks with nothing going on, counting the frames they ask for.
+    fn idle_draws(app: &mut App, ticks: usize) -> usize {
+        (0..ticks)
+            .filter(|_| {
+                app.update(true);
+                app.take_redraw()
+            })
+            .count()
+    }
+
+    #[test]
+    fn idle_app_doesnt_redraw() {
+        let (_dir, mut app) = app(&["a", "b"]);
+        assert!(app.take_redraw());
+        assert_eq!(idle_draws(&mut app, 100), 0);
+
+        press(&mut app, 'j');
+        assert!(app.take_redraw());
+        assert_eq!(idle_draws(&mut app, 100), 0);
+
+        app.handle_event(Event::Resize(80, 24));
+        assert!(app.take_redraw());
+        assert_eq!(idle_draws(&mut app, 100), 0);
+    }
+
+    #[test]
+    fn expiring_notification_redraws_once() {
+        let (_dir, mut app) = app(&["a"]);
+        app.take_redraw();
+        app.notify("saved".to_string(), Level::Info, Duration::ZERO);
+        app.take_redraw();
+        assert_eq!(idle_draws(&mut app, 10), 1);
+    }
+
+    #[test]
+    fn pending_deletion_redraws_its_countdown_until_flushed() {
+        let (_dir, mut app) = app(&["a", "b"]);
+        press(&mut app, 'd');
+        app.take_redraw();
+        assert_eq!(idle_draws(&mut app, 3), 3);
+
+        app.pending_deletion.as_mut().unwrap().deadline = Instant::now();
+        assert_eq!(idle_draws(&mut app, 10), 1);
+        assert!(app.pending_deletion.is_none());
+    }
+
+    #[test]
+    fn list_ignores_releases() {
+        let (_dir, mut app) = app(&["a", "b", "c"]);
+        press(&mut app, 'j');
+        release(&mut app, 'j');
+        assert_eq!(selected(&app), 1);
+    }
+
+    #[test]
+    fn list_movement_repeats_while_held() {
+        let (_dir, mut app) = app(&["a", "b", "c", "d"]);
+        press(&mut app, 'j');
+        hold(&mut app, 'j');
+        hold(&mut app, 'j');
+        release(&mut app, 'j');
+        assert_eq!(selected(&app), 3);
+    }
+
+    #[test]
+    fn held_digit_doesnt_grow_the_count() {
+        let titles = (0..30).map(|i| i.to_string()).collect::<Vec<_>>();
+        let (_dir, mut app) = app(&titles.iter().map(String::as_str).collect::<Vec<_>>());
+        press(&mut app, '2');
+        hold(&mut app, '2');
+        press(&mut app, 'j');
+        assert_eq!(selected(&app), 2);
+    }
+
+    #[test]
+    fn held_prefix_key_waits_for_the_next_one() {
+        let (_dir, mut app) = app(&["a", "b"]);
+        // `c c` would filter by color; holding the first `c` must not make it.
+        press(&mut app, 'c');
+        hold(&mut app, 'c');
+        press(&mut app, 'r');
+        assert!(app.color_filter.is_none());
+        assert!(app.selected_note().unwrap().color == NoteColor::Red);
+    }
+
+    #[test]
+    fn toggles_dont_repeat() {
+        let (_dir, mut app) = app(&["a", "b"]);
+        press(&mut app, 'v');
+        hold(&mut app, 'v');
+        hold(&mut app, 'v');
+        let id = app.selected_note().unwrap().id;
+        assert!(app.marked.contains(&id));
+    }
+
+    #[test]
+    fn holding_quit_doesnt_double_press() {
+        let (_dir, mut app) = app(&["a"]);
+        app.config.quit.confirm = QuitConfirm::DoublePress;
+        press(&mut app, 'q');
+        hold(&mut app, 'q');
+        assert!(!app.should_quit);
+        press(&mut app, 'q');
+        assert!(app.should_quit);
+    }
+
+    #[test]
+    fn confirm_dialog_ignores_release_and_repeat() {
+        let (_dir, mut app) = app(&["a"]);
+        app.config.quit.confirm = QuitConfirm::Always;
+        press(&mut app, 'q');
+        release(&mut app, 'q');
+        assert!(app.current_screen == Screen::ExitConfirm);
+        hold(&mut app, 'y');
+        assert!(!app.should_quit);
+        press(&mut app, 'n');
+        release(&mut app, 'n');
+        assert!(app.current_screen == Screen::List);
+    }
+
+    #[test]
+    fn form_typing_repeats_and_ignores_releases() {
+        let (_dir, mut app) = app(&["a"]);
+        send(&mut app, KeyCode::Enter, KeyEventKind::Press);
+        send(&mut app, KeyCode::Enter, KeyEventKind::Release);
+        assert!(app.current_screen == Screen::Form);
+        press(&mut app, 'x');
+        hold(&mut app, 'x');
+        release(&mut app, 'x');
+        assert_eq!(app.title_input.value(), "axx");
+    }
+
+    #[test]
+    fn saving_an_unchanged_note_leaves_nothing_to_undo() {
+        let (_dir, mut app) = app(&["a"]);
+        app.load_form();
+        app.save_note();
+        assert!(app.undo_stack.is_empty());
+        assert!(!app.session_changed);
+
+        app.title_input = app.title_input.clone().with_value("b".to_string());
+        app.save_note();
+        assert_eq!(app.undo_stack.len(), 1);
+        assert!(app.session_changed);
+    }
+
+    #[test]
+    fn undoing_a_paste_removes_the_copy() {
+        let (_dir, mut app) = app(&["a", "b"]);
+        app.copy_note();
+        app.paste_note();
+        assert_eq!(app.notes.items.len(), 3);
+        assert_eq!(app.undo_stack.len(), 1);
+
+        press(&mut app, 'u');
+        assert_eq!(app.notes.items.len(), 2);
+        app.flush_deletion();
+        assert_eq!(app.db.get_all_notes().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn split_screen_replaces_the_note_until_undone() {
+        let (_dir, mut app) = app(&[]);
+        let note = app
+            .db
+            .add_note_with_created_at("Plans", "Monday\ngym\n\nTuesday\nswim", None)
+            .unwrap();
+        app.reload_notes().unwrap();
+        app.select_note_id(note.id);
+        app.open_split_view();
+        assert_eq!(app.split_view.line, 3);
+        app.split_at_divider();
+
+        let titles = |app: &App| {
+            let mut notes = app
+                .notes
+                .items
+                .iter()
+                .map(|n| (n.id, n.title.clone()))
+                .collect::<Vec<_>>();
+            notes.sort();
+            notes
+        };
+        assert!(app.current_screen == Screen::List);
+        assert_eq!(
+            titles(&app)
+                .into_iter()
+                .map(|(_, title)| title)
+                .collect::<Vec<_>>(),
+            ["Plans", "Tuesday"]
+        );
+        assert!(app.notes.items.iter().all(|n| n.id != note.id));
+
+        press(&mut app, 'u');
+        assert_eq!(titles(&app), [(note.id, "Plans".to_string())]);
+        assert_eq!(app.notes.items[0].content, note.content);
     }
 }
@@ -0,0 +1,457 @@
+use rusqlite::Connection;
+
+use crate::db::DbError;
+
+/// Schema changes in the order they were made. A database whose `user_version` is `n` has had
+/// the first `n` applied, and opening it applies the rest. New changes go at the end; the ones
+/// already released are never edited, so every database ends up with the same schema.
+const MIGRATIONS: &[fn(&Connection) -> rusqlite::Result<()>] = &[
+    baseline,
+    add_positions,
+    add_timestamp_defaults,
+    add_archive_batches,
+];
+
+/// `notes` as a new database creates it. The migrations that rebuild the table use it too, so
+/// that `sqlite_master` reads the same whichever way a database got there.
+const NOTES_TABLE: &str = "CREATE TABLE IF NOT EXISTS notes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            title TEXT NOT NULL,
+            content TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+            updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+        )";
+
+/// Applies the migrations `conn` hasn't had yet, each in a transaction that also bumps
+/// `user_version`, so an interrupted upgrade resumes where it stopped. A database written by a
+/// newer version is left untouched.
+pub fn run(conn: &Connection) -> Result<(), DbError> {
+    let version: u32 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version as usize > MIGRATIONS.len() {
+        return Err(DbError::SchemaTooNew {
+            found: version,
+            supported: MIGRATIONS.len(),
+        });
+    }
+    if version as usize == MIGRATIONS.len() {
+        return Ok(());
+    }
+
+    // Dropping a table being rebuilt would otherwise delete the rows referencing it. SQLite
+    // ignores the pragma inside a transaction, so it's set around them.
+    let foreign_keys: bool = conn.pragma_query_value(None, "foreign_keys", |row| row.get(0))?;
+    conn.pragma_update(None, "foreign_keys", false)?;
+    let applied = apply(conn, version as usize);
+    conn.pragma_update(None, "foreign_keys", foreign_keys)?;
+    applied
+}
+
+fn apply(conn: &Connection, version: usize) -> Result<(), DbError> {
+    for (applied, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = conn.unchecked_transaction()?;
+        migration(&transaction)?;
+        transaction.pragma_update(None, "user_version", applied as u32 + 1)?;
+        transaction.commit()?;
+    }
+    Ok(())
+}
+
+/// The schema as it stood when versioning began. Databases from before then all have version 0
+/// whatever they contain, so every step checks for what it adds and they all end up alike.
+fn baseline(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(NOTES_TABLE, [])?;
+    add_timestamp_columns(conn)?;
+    add_archived_column(conn)?;
+    add_content_hash_column(conn)?;
+    add_color_column(conn)?;
+    add_source_url_column(conn)?;
+    create_full_text_index(conn)?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS drafts (
+            note_id INTEGER PRIMARY KEY,
+            title TEXT NOT NULL,
+            content TEXT NOT NULL,
+            saved_at INTEGER NOT NULL
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS tags (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS note_tags (
+            note_id INTEGER NOT NULL REFERENCES notes (id) ON DELETE CASCADE,
+            tag_id INTEGER NOT NULL REFERENCES tags (id) ON DELETE CASCADE,
+            PRIMARY KEY (note_id, tag_id)
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS note_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            note_id INTEGER NOT NULL REFERENCES notes (id) ON DELETE CASCADE,
+            title TEXT NOT NULL,
+            content TEXT NOT NULL,
+            saved_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS note_history_by_note ON note_history (note_id, saved_at)",
+        [],
+    )?;
+    add_merged_from_column(conn)?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS app_state (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS marks (
+            note_id INTEGER NOT NULL REFERENCES notes (id) ON DELETE CASCADE,
+            name TEXT NOT NULL,
+            line INTEGER NOT NULL,
+            PRIMARY KEY (note_id, name)
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// `position` orders the created order, starting from the ids so that nothing moves.
+fn add_positions(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE notes ADD COLUMN position INTEGER;
+         UPDATE notes SET position = id;
+         CREATE INDEX notes_by_position ON notes (position);",
+    )
+}
+
+/// For the steps adding a column that a database from before versioning may already have.
+fn has_column(conn: &Connection, table: &str, column: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2)",
+        [table, column],
+        |row| row.get(0),
+    )
+}
+
+/// Databases created before notes were timestamped lack the columns. SQLite can't add a column
+/// with a non-constant default, so existing rows are backfilled with the migration time and
+/// `add_timestamp_defaults` later rebuilds the table with the defaults.
+fn add_timestamp_columns(conn: &Connection) -> rusqlite::Result<()> {
+    if has_column(conn, "notes", "created_at")? {
+        return Ok(());
+    }
+
+    conn.execute_batch(
+        "ALTER TABLE notes ADD COLUMN created_at TEXT;
+         ALTER TABLE notes ADD COLUMN updated_at TEXT;
+         UPDATE notes SET
+            created_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now'),
+            updated_at = strftime('%Y-%m-%dT%H:%M:%SZ', 'now');",
+    )
+}
+
+/// Databases upgraded from before notes were timestamped have nullable `created_at` and
+/// `updated_at` columns without defaults. SQLite can't change a column, so the table is set
+/// aside, recreated the way a new database has it and the rows copied back, ids included.
+fn add_timestamp_defaults(conn: &Connection) -> rusqlite::Result<()> {
+    let has_default: bool = conn.query_row(
+        "SELECT dflt_value IS NOT NULL FROM pragma_table_info('notes') WHERE name = 'created_at'",
+        [],
+        |row| row.get(0),
+    )?;
+    if has_default {
+        return Ok(());
+    }
+
+    // Without the legacy behaviour the rename would point the other tables' foreign keys at the
+    // old table.
+    conn.pragma_update(None, "legacy_alter_table", true)?;
+    conn.execute("ALTER TABLE notes RENAME TO notes_without_defaults", [])?;
+    conn.pragma_update(None, "legacy_alter_table", false)?;
+    // Its indexes and triggers are recreated on the new table under the same names.
+    let attached = conn
+        .prepare(
+            "SELECT type, name FROM sqlite_master
+             WHERE tbl_name = 'notes_without_defaults' AND type IN ('index', 'trigger')
+             AND sql IS NOT NULL",
+        )?
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for (kind, name) in attached {
+        conn.execute(&format!("DROP {kind} \"{name}\""), [])?;
+    }
+
+    conn.execute(NOTES_TABLE, [])?;
+    add_archived_column(conn)?;
+    add_content_hash_column(conn)?;
+    add_color_column(conn)?;
+    add_source_url_column(conn)?;
+    conn.execute_batch(
+        "ALTER TABLE notes ADD COLUMN position INTEGER;
+         CREATE INDEX notes_by_position ON notes (position);
+         INSERT INTO notes (id, title, content, created_at, updated_at, archived_at,
+                            content_hash, color, source_url, position)
+         SELECT id, title, content,
+                COALESCE(created_at, strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+                COALESCE(updated_at, strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+                archived_at, content_hash, color, source_url, position
+         FROM notes_without_defaults;
+         -- Ids of deleted notes stay unused.
+         DELETE FROM sqlite_sequence WHERE name = 'notes';
+         UPDATE sqlite_sequence SET name = 'notes' WHERE name = 'notes_without_defaults';
+         DROP TABLE notes_without_defaults;",
+    )?;
+    // After the copy, so that the rows already in `notes_fts` aren't indexed a second time.
+    create_full_text_index(conn)
+}
+
+/// `archive_batch` numbers the `archive --older-than` runs, so `archive --undo` restores the
+/// last one and not the notes archived one at a time since. The notes archived last before the
+/// upgrade become the first batch, as undoing restored them until now.
+fn add_archive_batches(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE notes ADD COLUMN archive_batch INTEGER;
+         UPDATE notes SET archive_batch = 1
+         WHERE archived_at = (SELECT MAX(archived_at) FROM notes);",
+    )
+}
+
+/// Archived notes keep everything but drop out of the list, search and tags. `archived_at` is
+/// NULL for notes that were never archived.
+fn add_archived_column(conn: &Connection) -> rusqlite::Result<()> {
+    if has_column(conn, "notes", "archived_at")? {
+        return Ok(());
+    }
+
+    conn.execute("ALTER TABLE notes ADD COLUMN archived_at TEXT", [])?;
+    Ok(())
+}
+
+fn add_color_column(conn: &Connection) -> rusqlite::Result<()> {
+    if has_column(conn, "notes", "color")? {
+        return Ok(());
+    }
+
+    conn.execute("ALTER TABLE notes ADD COLUMN color TEXT", [])?;
+    Ok(())
+}
+
+fn add_source_url_column(conn: &Connection) -> rusqlite::Result<()> {
+    if has_column(conn, "notes", "source_url")? {
+        return Ok(());
+    }
+
+    conn.execute("ALTER TABLE notes ADD COLUMN source_url TEXT", [])?;
+    Ok(())
+}
+
+fn add_merged_from_column(conn: &Connection) -> rusqlite::Result<()> {
+    if has_column(conn, "note_history", "merged_from")? {
+        return Ok(());
+    }
+
+    conn.execute("ALTER TABLE note_history ADD COLUMN merged_from TEXT", [])?;
+    Ok(())
+}
+
+/// `notes_fts` indexes titles and contents for `Database::search`. It reads the text from `notes`
+/// and triggers keep it up to date; databases from before it existed are indexed when it's
+/// created.
+fn create_full_text_index(conn: &Connection) -> rusqlite::Result<()> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = 'notes_fts')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE notes_fts USING fts5(
+                title, content, content = 'notes', content_rowid = 'id'
+             );
+             INSERT INTO notes_fts (notes_fts) VALUES ('rebuild');",
+        )?;
+    }
+
+    conn.execute_batch(
+        "CREATE TRIGGER IF NOT EXISTS notes_fts_insert AFTER INSERT ON notes
+         BEGIN
+            INSERT INTO notes_fts (rowid, title, content)
+            VALUES (NEW.id, NEW.title, NEW.content);
+         END;
+         CREATE TRIGGER IF NOT EXISTS notes_fts_delete AFTER DELETE ON notes
+         BEGIN
+            INSERT INTO notes_fts (notes_fts, rowid, title, content)
+            VALUES ('delete', OLD.id, OLD.title, OLD.content);
+         END;
+         CREATE TRIGGER IF NOT EXISTS notes_fts_update AFTER UPDATE OF title, content ON notes
+         BEGIN
+            INSERT INTO notes_fts (notes_fts, rowid, title, content)
+            VALUES ('delete', OLD.id, OLD.title, OLD.content);
+            INSERT INTO notes_fts (rowid, title, content)
+            VALUES (NEW.id, NEW.title, NEW.content);
+         END;",
+    )
+}
+
+/// `content_hash` holds the SHA-256 of the content, filled in by `refresh_content_hashes` when
+/// it's needed. Rather than every write keeping it up to date, a trigger clears it whenever the
+/// content changes, which also covers rows written by other tools.
+fn add_content_hash_column(conn: &Connection) -> rusqlite::Result<()> {
+    if !has_column(conn, "notes", "content_hash")? {
+        conn.execute("ALTER TABLE notes ADD COLUMN content_hash TEXT", [])?;
+    }
+
+    conn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS notes_by_content_hash ON notes (content_hash);
+         CREATE TRIGGER IF NOT EXISTS notes_clear_content_hash
+         AFTER UPDATE OF content ON notes WHEN NEW.content_hash IS NOT NULL
+         BEGIN
+            UPDATE notes SET content_hash = NULL WHERE id = NEW.id;
+         END;",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V0: &str = include_str!("../tests/fixtures/v0.sql");
+
+    fn v0_database() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(V0).unwrap();
+        conn
+    }
+
+    /// Everything but SQLite's own tables, whose rows depend on what was inserted.
+    fn schema(conn: &Connection) -> Vec<(String, String, Option<String>)> {
+        conn.prepare(
+            "SELECT type, name, sql FROM sqlite_master WHERE name NOT LIKE 'sqlite_%'
+             ORDER BY type, name",
+        )
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .unwrap()
+        .collect::<rusqlite::Result<_>>()
+        .unwrap()
+    }
+
+    fn user_version(conn: &Connection) -> u32 {
+        conn.pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn upgraded_v0_database_has_the_schema_of_a_new_one() {
+        let fresh = Connection::open_in_memory().unwrap();
+        run(&fresh).unwrap();
+        let upgraded = v0_database();
+        run(&upgraded).unwrap();
+
+        assert_eq!(schema(&upgraded), schema(&fresh));
+        assert_eq!(user_version(&upgraded), MIGRATIONS.len() as u32);
+    }
+
+    #[test]
+    fn upgrade_keeps_notes_and_their_ids() {
+        let conn = v0_database();
+        run(&conn).unwrap();
+
+        let notes = conn
+            .prepare("SELECT id, title, position, created_at IS NOT NULL FROM notes ORDER BY id")
+            .unwrap()
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, bool>(3)?,
+                ))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            notes,
+            [
+                (1, "Groceries".to_string(), 1, true),
+                (2, "Ideas".to_string(), 2, true)
+            ]
+        );
+
+        let found: i64 = conn
+            .query_row(
+                "SELECT rowid FROM notes_fts WHERE notes_fts MATCH 'groceries' ORDER BY rowid",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(found, 1);
+
+        // The id of the deleted note isn't handed out again.
+        conn.execute("INSERT INTO notes (title, content) VALUES ('New', '')", [])
+            .unwrap();
+        assert_eq!(conn.last_insert_rowid(), 4);
+    }
+
+    #[test]
+    fn rebuilding_notes_keeps_the_rows_referencing_them() {
+        let conn = v0_database();
+        conn.pragma_update(None, "foreign_keys", true).unwrap();
+        baseline(&conn).unwrap();
+        add_positions(&conn).unwrap();
+        conn.pragma_update(None, "user_version", 2).unwrap();
+        conn.execute_batch(
+            "INSERT INTO tags (name) VALUES ('food');
+             INSERT INTO note_tags (note_id, tag_id) VALUES (1, 1);
+             INSERT INTO note_history (note_id, title, content) VALUES (1, 'Groceries', '');
+             INSERT INTO marks (note_id, name, line) VALUES (2, 'a', 0);",
+        )
+        .unwrap();
+
+        run(&conn).unwrap();
+
+        let count = |table: &str| -> i64 {
+            conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+        assert_eq!(
+            (count("note_tags"), count("note_history"), count("marks")),
+            (1, 1, 1)
+        );
+        let foreign_keys: bool = conn
+            .pragma_query_value(None, "foreign_keys", |row| row.get(0))
+            .unwrap();
+        assert!(foreign_keys);
+    }
+
+    #[test]
+    fn newer_database_is_refused() {
+        let conn = v0_database();
+        conn.pragma_update(None, "user_version", MIGRATIONS.len() as u32 + 1)
+            .unwrap();
+
+        assert!(matches!(run(&conn), Err(DbError::SchemaTooNew { .. })));
+        assert_eq!(schema(&conn).len(), 1);
+    }
+}
@@ -1,12 +1,322 @@
+use std::{ops::Range, sync::Arc};
+
+use chrono::{DateTime, Utc};
 use ratatui::widgets::ListState;
+use serde::Deserialize;
+
+use crate::search::{self, SearchOptions};
 
 pub struct NoteList {
-    pub items: Vec<Note>,
+    /// Shared so screens and threads can hold the list without cloning it. Edits are
+    /// copy-on-write: cheap reads and sharing are paid for with a copy of every note on each
+    /// insert or removal, which is negligible next to a database round trip at notes-app sizes.
+    pub items: Arc<[Note]>,
     pub state: ListState,
 }
+
+/// An edit of a `NoteList`, applied with `NoteList::apply`. Indexes are positions in the list
+/// at the time the command is applied.
+pub enum NoteListCommand {
+    /// Puts the note at this index, shifting the ones after it down.
+    Insert(usize, Note),
+    Remove(usize),
+    /// Replaces the note at this index.
+    Update(usize, Note),
+    /// Moves the note at the first index to the second.
+    Reorder(usize, usize),
+}
+
+impl NoteList {
+    /// Applies `command` and returns the command that undoes it. Updates and reorders copy
+    /// the list first if it is shared; inserts and removals rebuild it.
+    pub fn apply(&mut self, command: NoteListCommand) -> NoteListCommand {
+        match command {
+            NoteListCommand::Insert(index, note) => {
+                let mut items = self.items.to_vec();
+                items.insert(index, note);
+                self.items = items.into();
+                NoteListCommand::Remove(index)
+            }
+            NoteListCommand::Remove(index) => {
+                let mut items = self.items.to_vec();
+                let note = items.remove(index);
+                self.items = items.into();
+                NoteListCommand::Insert(index, note)
+            }
+            NoteListCommand::Update(index, note) => {
+                let previous = std::mem::replace(&mut Arc::make_mut(&mut self.items)[index], note);
+                NoteListCommand::Update(index, previous)
+            }
+            NoteListCommand::Reorder(from, to) => {
+                let items = Arc::make_mut(&mut self.items);
+                if from < to {
+                    items[from..=to].rotate_left(1);
+                } else {
+                    items[to..=from].rotate_right(1);
+                }
+                NoteListCommand::Reorder(to, from)
+            }
+        }
+    }
+
+    /// `(matching, non_matching)`, each in list order, for views that show the two apart.
+    // Nothing splits the list yet; the pinned-notes pane and partial reloads will.
+    #[allow(dead_code)]
+    pub fn partition_by_predicate(&self, f: impl Fn(&Note) -> bool) -> (Vec<&Note>, Vec<&Note>) {
+        self.items.iter().partition(|note| f(note))
+    }
+}
+
 #[derive(Clone)]
 pub struct Note {
     pub id: i64,
     pub title: String,
     pub content: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// Sorted by name, as they were when the note was read.
+    pub tags: Vec<String>,
+    pub color: NoteColor,
+    /// The page the note was clipped from, for notes added by a web clipper.
+    pub source_url: Option<String>,
+    /// Where the note goes in the created order, which moving it by hand changes.
+    pub position: i64,
+    /// Archived notes are only listed in the archived view.
+    pub archived_at: Option<DateTime<Utc>>,
+}
+
+/// A color label set with `c` in the list, stored by name in `notes.color`.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum NoteColor {
+    #[default]
+    None,
+    Red,
+    Green,
+    Blue,
+    Yellow,
+}
+
+impl NoteColor {
+    /// The letter typed after `c` to pick the color.
+    pub fn from_key(key: char) -> Option<NoteColor> {
+        match key {
+            'r' => Some(NoteColor::Red),
+            'g' => Some(NoteColor::Green),
+            'b' => Some(NoteColor::Blue),
+            'y' => Some(NoteColor::Yellow),
+            'n' => Some(NoteColor::None),
+            _ => None,
+        }
+    }
+
+    /// Stored in the database; unlabelled notes have `NULL`.
+    pub fn name(self) -> Option<&'static str> {
+        match self {
+            NoteColor::None => None,
+            NoteColor::Red => Some("red"),
+            NoteColor::Green => Some("green"),
+            NoteColor::Blue => Some("blue"),
+            NoteColor::Yellow => Some("yellow"),
+        }
+    }
+
+    /// Names written by another version are read as no color rather than failing the query.
+    pub fn from_name(name: Option<&str>) -> NoteColor {
+        match name {
+            Some("red") => NoteColor::Red,
+            Some("green") => NoteColor::Green,
+            Some("blue") => NoteColor::Blue,
+            Some("yellow") => NoteColor::Yellow,
+            _ => NoteColor::None,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            NoteColor::None => "",
+            NoteColor::Red => "🔴",
+            NoteColor::Green => "🟢",
+            NoteColor::Blue => "🔵",
+            NoteColor::Yellow => "🟡",
+        }
+    }
+}
+
+impl Note {
+    /// The content around the first match of `query`, with up to `context_chars` characters on
+    /// each side, on one line. `…` marks where the content was cut. Without a match in the
+    /// content (the title may have matched) the excerpt starts at the beginning.
+    pub fn excerpt(&self, query: &str, context_chars: usize, options: SearchOptions) -> String {
+        let matched = search::find_matches(&self.content, query, options)
+            .into_iter()
+            .next()
+            .unwrap_or(0..0);
+        self.excerpt_around(matched, context_chars)
+    }
+
+    /// Like `excerpt`, around the byte range `matched` of the content however it was found.
+    pub fn excerpt_around(&self, matched: Range<usize>, context_chars: usize) -> String {
+        let content = self.content.as_str();
+        let start = content[..matched.start]
+            .char_indices()
+            .rev()
+            .take(context_chars)
+            .last()
+            .map_or(matched.start, |(index, _)| index);
+        let after = if matched.is_empty() {
+            2 * context_chars
+        } else {
+            context_chars
+        };
+        let end = content[matched.end..]
+            .char_indices()
+            .nth(after)
+            .map_or(content.len(), |(index, _)| matched.end + index);
+
+        let mut excerpt = String::new();
+        if start > 0 {
+            excerpt.push('…');
+        }
+        excerpt.extend(content[start..end].chars().map(|c| match c {
+            '\n' | '\r' | '\t' => ' ',
+            c => c,
+        }));
+        if end < content.len() {
+            excerpt.push('…');
+        }
+        excerpt
+    }
+}
+
+/// A line of a note remembered under a letter, set with `m` and jumped to with `'`.
+pub struct Mark {
+    pub name: char,
+    /// Zero-based. Edits don't move it, so it may point past the end of a note that shrank.
+    pub line: usize,
+}
+
+/// Unsaved form content, periodically persisted so it survives a crash.
+pub struct Draft {
+    pub note_id: i64,
+    pub title: String,
+    pub content: String,
+}
+
+/// A note as it was before a save replaced it, from `note_history`.
+pub struct Revision {
+    pub title: String,
+    pub content: String,
+    pub saved_at: DateTime<Utc>,
+    /// Title of the note this version was saved from, when it came in with a merge.
+    pub merged_from: Option<String>,
+}
+
+/// A tag in the tag browser, with the notes listed under it when expanded.
+pub struct TreeNode {
+    pub tag: String,
+    pub notes: Vec<Note>,
+    pub expanded: bool,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortOrder {
+    /// Oldest first, unless moved by hand.
+    #[default]
+    Created,
+    /// Most recently edited first.
+    Updated,
+    Title,
+    /// Shuffled once per session. The list can't be reordered by hand in this mode.
+    Random,
+}
+
+impl SortOrder {
+    /// The order `o` switches to in the list. The shuffle is only reached through the config.
+    pub fn next(self) -> SortOrder {
+        match self {
+            SortOrder::Created => SortOrder::Updated,
+            SortOrder::Updated => SortOrder::Title,
+            SortOrder::Title | SortOrder::Random => SortOrder::Created,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortOrder::Created => "oldest first",
+            SortOrder::Updated => "recently edited",
+            SortOrder::Title => "A→Z",
+            SortOrder::Random => "shuffled",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(content: &str) -> Note {
+        Note {
+            id: 1,
+            title: String::new(),
+            content: content.to_string(),
+            created_at: DateTime::UNIX_EPOCH,
+            updated_at: DateTime::UNIX_EPOCH,
+            tags: Vec::new(),
+            color: NoteColor::None,
+            source_url: None,
+            position: 1,
+            archived_at: None,
+        }
+    }
+
+    fn excerpt(content: &str, query: &str, context_chars: usize) -> String {
+        note(content).excerpt(query, context_chars, SearchOptions::default())
+    }
+
+    #[test]
+    fn excerpt_of_a_match_at_the_start_is_only_cut_after() {
+        assert_eq!(excerpt("Rust is fun to write", "rust", 5), "Rust is f…");
+    }
+
+    #[test]
+    fn excerpt_of_a_match_in_the_middle_is_cut_on_both_sides() {
+        assert_eq!(
+            excerpt("one two three four five", "three", 4),
+            "…two three fou…"
+        );
+    }
+
+    #[test]
+    fn excerpt_of_a_match_at_the_end_is_only_cut_before() {
+        assert_eq!(excerpt("a long way to the end", "end", 5), "… the end");
+    }
+
+    #[test]
+    fn excerpt_counts_characters_and_flattens_lines() {
+        assert_eq!(excerpt("été\nà la\tmer", "la", 3), "… à la me…");
+    }
+
+    #[test]
+    fn excerpt_without_a_match_starts_at_the_beginning() {
+        assert_eq!(excerpt("nothing to see here", "absent", 4), "nothing …");
+        assert_eq!(excerpt("short", "absent", 4), "short");
+    }
+
+    #[test]
+    fn partition_keeps_list_order_on_both_sides() {
+        let list = NoteList {
+            items: (1..=6)
+                .map(|id| Note {
+                    id,
+                    ..note(if id % 3 == 0 { "pinned" } else { "" })
+                })
+                .collect(),
+            state: ListState::default(),
+        };
+        let (matching, other) = list.partition_by_predicate(|note| note.content == "pinned");
+        let ids = |notes: Vec<&Note>| notes.iter().map(|note| note.id).collect::<Vec<_>>();
+        assert_eq!(ids(matching), [3, 6]);
+        assert_eq!(ids(other), [1, 2, 4, 5]);
+    }
 }
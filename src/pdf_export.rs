@@ -0,0 +1,102 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::mpsc::Sender,
+    thread,
+};
+
+use chrono::Utc;
+
+use crate::Note;
+
+/// Every conversion appends the converter's stderr here, next to the database.
+pub const LOG_PATH: &str = "export.log";
+
+/// Sent back once a conversion started by `spawn` has finished.
+pub struct Outcome {
+    pub output: PathBuf,
+    /// A message for the status line on failure; the full stderr is in `LOG_PATH`.
+    pub result: Result<(), String>,
+}
+
+/// Converts `note` in a background thread and reports on `done`. `template` is the configured
+/// command line, split on whitespace before `{input}` and `{output}` are substituted, so paths
+/// containing spaces stay single arguments.
+pub fn spawn(template: &str, note: &Note, output: PathBuf, done: Sender<Outcome>) {
+    let template = template.to_string();
+    let markdown = format!("# {}\n\n{}\n", note.title, note.content);
+    let input = std::env::temp_dir().join(format!(
+        "ratata-notes-{}-{}.md",
+        std::process::id(),
+        note.id
+    ));
+
+    thread::spawn(move || {
+        let result = convert(&template, &markdown, &input, &output);
+        fs::remove_file(&input).ok();
+        done.send(Outcome { output, result }).ok();
+    });
+}
+
+fn convert(template: &str, markdown: &str, input: &Path, output: &Path) -> Result<(), String> {
+    fs::write(input, markdown).map_err(|e| format!("Can't write {}: {e}", input.display()))?;
+
+    let args = template
+        .split_whitespace()
+        .map(|arg| {
+            arg.replace("{input}", &input.to_string_lossy())
+                .replace("{output}", &output.to_string_lossy())
+        })
+        .collect::<Vec<_>>();
+    let Some((program, args)) = args.split_first() else {
+        return Err("export.pdf_command is empty".to_string());
+    };
+
+    let converted = match Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+    {
+        Ok(converted) => converted,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(format!(
+                "{program} is not installed or not on PATH; install it or set export.pdf_command"
+            ));
+        }
+        Err(e) => return Err(format!("Can't run {program}: {e}")),
+    };
+
+    let stderr = String::from_utf8_lossy(&converted.stderr);
+    // The status line says what went wrong; a log that can't be written isn't worth failing for.
+    log(program, output, &converted.status.to_string(), &stderr).ok();
+
+    if converted.status.success() {
+        Ok(())
+    } else {
+        let last_line = stderr.lines().rfind(|line| !line.trim().is_empty());
+        Err(match last_line {
+            Some(line) => format!("{program} failed: {}", line.trim()),
+            None => format!("{program} failed with {}", converted.status),
+        })
+    }
+}
+
+fn log(program: &str, output: &Path, status: &str, stderr: &str) -> io::Result<()> {
+    let mut log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(LOG_PATH)?;
+    writeln!(
+        log,
+        "{} {program} -> {}: {status}",
+        Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+        output.display()
+    )?;
+    log.write_all(stderr.as_bytes())?;
+    if !stderr.is_empty() && !stderr.ends_with('\n') {
+        writeln!(log)?;
+    }
+    Ok(())
+}
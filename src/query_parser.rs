@@ -0,0 +1,133 @@
+use crate::search::{self, SearchOptions};
+
+/// A search query split into the text to find and the `NOT:term` words that rule a note out.
+pub struct Query<'a> {
+    /// The other words, rejoined with single spaces and matched as one phrase. The whole query
+    /// when it has no `NOT:` words, so ordinary searches keep their spacing.
+    pub text: String,
+    pub excluded: Vec<&'a str>,
+}
+
+/// Splits `query` on whitespace into its `NOT:term` words and the rest. `NOT:"two words"` rules
+/// out a phrase, up to the closing quote or the end of the query. A bare `NOT:` is dropped;
+/// `not:` in lowercase is searched for like any other word.
+pub fn parse(query: &str) -> Query<'_> {
+    if !query
+        .split_whitespace()
+        .any(|word| word.starts_with("NOT:"))
+    {
+        return Query {
+            text: query.to_string(),
+            excluded: Vec::new(),
+        };
+    }
+
+    let mut included = Vec::new();
+    let mut excluded = Vec::new();
+    let mut rest = query;
+    while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+        rest = &rest[start..];
+        if let Some(quoted) = rest.strip_prefix("NOT:\"") {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            let phrase = quoted[..end].trim();
+            if !phrase.is_empty() {
+                excluded.push(phrase);
+            }
+            rest = quoted.get(end + 1..).unwrap_or("");
+            continue;
+        }
+
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (word, after) = rest.split_at(end);
+        rest = after;
+        match word.strip_prefix("NOT:") {
+            Some("") => {}
+            Some(term) => excluded.push(term),
+            None => included.push(word),
+        }
+    }
+    Query {
+        text: included.join(" "),
+        excluded,
+    }
+}
+
+impl Query<'_> {
+    /// Whether the title or the content contains the text, and neither contains an excluded term.
+    pub fn matches(&self, title: &str, content: &str, options: SearchOptions) -> bool {
+        let contains = |term: &str| {
+            search::matches(title, term, options) || search::matches(content, term, options)
+        };
+        contains(&self.text) && !self.excluded.iter().any(|term| contains(term))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPTIONS: SearchOptions = SearchOptions {
+        fold_accents: false,
+    };
+
+    #[test]
+    fn without_exclusions_the_query_is_kept_as_typed() {
+        let query = parse("  two  spaces ");
+        assert_eq!(query.text, "  two  spaces ");
+        assert!(query.excluded.is_empty());
+    }
+
+    #[test]
+    fn exclusions_are_split_from_the_text() {
+        let query = parse("meeting NOT:draft  notes NOT:old");
+        assert_eq!(query.text, "meeting notes");
+        assert_eq!(query.excluded, ["draft", "old"]);
+    }
+
+    #[test]
+    fn bare_not_is_dropped() {
+        let query = parse("NOT:");
+        assert_eq!(query.text, "");
+        assert!(query.excluded.is_empty());
+        assert!(query.matches("Anything", "at all", OPTIONS));
+
+        let query = parse("meeting NOT: notes");
+        assert_eq!(query.text, "meeting notes");
+        assert!(query.excluded.is_empty());
+    }
+
+    #[test]
+    fn dashes_and_lowercase_not_are_plain_text() {
+        for text in ["-tag", "--", "not:draft", "-\"two words\""] {
+            let query = parse(text);
+            assert_eq!(query.text, text);
+            assert!(query.excluded.is_empty(), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn quoted_exclusion_is_a_phrase() {
+        let query = parse("plan NOT:\"first draft\"");
+        assert_eq!(query.text, "plan");
+        assert_eq!(query.excluded, ["first draft"]);
+        assert!(!query.matches("Plan", "the first draft", OPTIONS));
+        assert!(query.matches("Plan", "a draft first", OPTIONS));
+
+        let query = parse("plan NOT:\"unclosed quote");
+        assert_eq!(query.text, "plan");
+        assert_eq!(query.excluded, ["unclosed quote"]);
+
+        let query = parse("plan NOT:\"\"");
+        assert_eq!(query.text, "plan");
+        assert!(query.excluded.is_empty());
+    }
+
+    #[test]
+    fn excluding_the_only_term_keeps_every_other_note() {
+        let query = parse("NOT:draft");
+        assert_eq!(query.text, "");
+        assert!(query.matches("Meeting", "agenda", OPTIONS));
+        assert!(!query.matches("Draft", "agenda", OPTIONS));
+        assert!(!query.matches("Meeting", "a draft agenda", OPTIONS));
+    }
+}
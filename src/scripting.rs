@@ -0,0 +1,246 @@
+use std::{
+    cell::{Cell, RefCell},
+    fs,
+    path::Path,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use rhai::{AST, CallFnOptions, Dynamic, Engine, EvalAltResult, FuncArgs, Scope};
+
+pub enum Hook {
+    OnOpen,
+    OnSave,
+}
+
+impl Hook {
+    fn function_name(&self) -> &'static str {
+        match self {
+            Hook::OnOpen => "on_open",
+            Hook::OnSave => "on_save",
+        }
+    }
+}
+
+struct Script {
+    name: String,
+    ast: AST,
+}
+
+struct ScriptCommand {
+    key: char,
+    script: usize,
+    function: String,
+}
+
+/// Runs user `.rhai` scripts from the config dir.
+///
+/// Scripts get no filesystem or process access, `print`/`debug` are swallowed so they can't
+/// scribble over the terminal, and every call is bounded by `timeout`.
+pub struct ScriptHost {
+    engine: Engine,
+    scripts: Vec<Script>,
+    commands: Vec<ScriptCommand>,
+    call_started: Rc<Cell<Instant>>,
+    timeout: Duration,
+}
+
+impl ScriptHost {
+    /// Compiles every script in `dir` and runs its top level so it can `bind_key`.
+    /// Scripts that fail are skipped and their errors returned alongside the host.
+    pub fn load(dir: &Path, timeout: Duration) -> (ScriptHost, Vec<String>) {
+        let call_started = Rc::new(Cell::new(Instant::now()));
+        let bindings: Rc<RefCell<Vec<(char, String)>>> = Rc::default();
+
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(1_000_000)
+            .set_max_call_levels(32)
+            .set_max_expr_depths(64, 32)
+            .set_max_string_size(1 << 20)
+            .set_max_array_size(10_000)
+            .set_max_map_size(10_000)
+            .disable_symbol("eval")
+            .on_print(|_| {})
+            .on_debug(|_, _, _| {});
+
+        let started = call_started.clone();
+        engine.on_progress(move |_| {
+            if started.get().elapsed() > timeout {
+                Some(Dynamic::UNIT)
+            } else {
+                None
+            }
+        });
+
+        let registered = bindings.clone();
+        engine.register_fn("bind_key", move |key: &str, function: &str| {
+            if let Some(key) = key.chars().next() {
+                registered.borrow_mut().push((key, function.to_string()));
+            }
+        });
+
+        let mut host = ScriptHost {
+            engine,
+            scripts: Vec::new(),
+            commands: Vec::new(),
+            call_started,
+            timeout,
+        };
+        let mut errors = Vec::new();
+
+        let mut paths = fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "rhai"))
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        for path in paths {
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+
+            let ast = match host.engine.compile_file(path) {
+                Ok(ast) => ast,
+                Err(e) => {
+                    errors.push(format!("{name}: {e}"));
+                    continue;
+                }
+            };
+
+            host.call_started.set(Instant::now());
+            if let Err(e) = host.engine.run_ast(&ast) {
+                errors.push(host.describe_error(&name, &e));
+                bindings.borrow_mut().clear();
+                continue;
+            }
+
+            let script = host.scripts.len();
+            host.commands
+                .extend(
+                    bindings
+                        .borrow_mut()
+                        .drain(..)
+                        .map(|(key, function)| ScriptCommand {
+                            key,
+                            script,
+                            function,
+                        }),
+                );
+            host.scripts.push(Script { name, ast });
+        }
+
+        (host, errors)
+    }
+
+    /// Calls `on_startup()` in every script that defines it.
+    pub fn run_startup(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        for script in &self.scripts {
+            if defines(&script.ast, "on_startup", 0)
+                && let Err(e) = self.call(script, "on_startup", ())
+            {
+                errors.push(e);
+            }
+        }
+        errors
+    }
+
+    /// Threads `content` through every script defining the hook.
+    /// Returns `Ok(None)` if no script changed the content.
+    pub fn run_hook(
+        &self,
+        hook: Hook,
+        title: &str,
+        content: &str,
+    ) -> Result<Option<String>, String> {
+        let function = hook.function_name();
+        let mut current: Option<String> = None;
+
+        for script in &self.scripts {
+            if !defines(&script.ast, function, 2) {
+                continue;
+            }
+            let input = current.as_deref().unwrap_or(content).to_string();
+            if let Some(output) = self.call_transform(script, function, title, input)? {
+                current = Some(output);
+            }
+        }
+
+        Ok(current)
+    }
+
+    pub fn has_command(&self, key: char) -> bool {
+        self.commands.iter().any(|c| c.key == key)
+    }
+
+    pub fn run_command(
+        &self,
+        key: char,
+        title: &str,
+        content: &str,
+    ) -> Result<Option<String>, String> {
+        let Some(command) = self.commands.iter().find(|c| c.key == key) else {
+            return Ok(None);
+        };
+        let script = &self.scripts[command.script];
+        self.call_transform(script, &command.function, title, content.to_string())
+    }
+
+    fn call_transform(
+        &self,
+        script: &Script,
+        function: &str,
+        title: &str,
+        content: String,
+    ) -> Result<Option<String>, String> {
+        let result = self.call(script, function, (title.to_string(), content))?;
+
+        if result.is_unit() {
+            Ok(None)
+        } else if result.is_string() {
+            Ok(Some(result.cast::<String>()))
+        } else {
+            Err(format!(
+                "{}: {function} must return a string or (), got {}",
+                script.name,
+                result.type_name()
+            ))
+        }
+    }
+
+    fn call(
+        &self,
+        script: &Script,
+        function: &str,
+        args: impl FuncArgs,
+    ) -> Result<Dynamic, String> {
+        self.call_started.set(Instant::now());
+        self.engine
+            .call_fn_with_options(
+                CallFnOptions::new().eval_ast(false),
+                &mut Scope::new(),
+                &script.ast,
+                function,
+                args,
+            )
+            .map_err(|e| self.describe_error(&script.name, &e))
+    }
+
+    fn describe_error(&self, name: &str, error: &EvalAltResult) -> String {
+        match error {
+            EvalAltResult::ErrorTerminated(..) => {
+                format!("{name}: timed out after {}ms", self.timeout.as_millis())
+            }
+            e => format!("{name}: {e}"),
+        }
+    }
+}
+
+fn defines(ast: &AST, function: &str, arity: usize) -> bool {
+    ast.iter_functions()
+        .any(|f| f.name == function && f.params.len() == arity)
+}
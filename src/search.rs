@@ -0,0 +1,153 @@
+use std::ops::Range;
+
+use unicode_normalization::{UnicodeNormalization, char::canonical_combining_class};
+
+#[derive(Clone, Copy, Default)]
+pub struct SearchOptions {
+    /// Ignore diacritics, so `resume` matches `résumé`.
+    pub fold_accents: bool,
+}
+
+/// Normalizes text before it is stored, so composed and decomposed input compare equal in SQL.
+pub fn normalize(text: &str) -> String {
+    text.nfc().collect()
+}
+
+/// `text` normalized to NFC and lowercased for matching, remembering where every piece came
+/// from so matches can be mapped back onto the original string.
+struct Folded {
+    text: String,
+    /// `(folded range, original range)` of each base character and its combining marks.
+    clusters: Vec<(Range<usize>, Range<usize>)>,
+}
+
+impl Folded {
+    fn new(original: &str, options: SearchOptions) -> Folded {
+        let mut folded = Folded {
+            text: String::with_capacity(original.len()),
+            clusters: Vec::new(),
+        };
+
+        let mut start = 0;
+        for (index, c) in original.char_indices().skip(1) {
+            // Combining marks stay with their base character; they never begin a cluster.
+            if canonical_combining_class(c) == 0 {
+                folded.push(&original[start..index], start..index, options);
+                start = index;
+            }
+        }
+        if start < original.len() {
+            folded.push(&original[start..], start..original.len(), options);
+        }
+
+        folded
+    }
+
+    fn push(&mut self, cluster: &str, original: Range<usize>, options: SearchOptions) {
+        let folded_start = self.text.len();
+        let cluster = if options.fold_accents {
+            cluster
+                .nfd()
+                .filter(|&c| canonical_combining_class(c) == 0)
+                .collect()
+        } else {
+            cluster.to_string()
+        };
+        // `İ` would lowercase to `i` + U+0307 and then never match a plain `i`; it is folded
+        // like Turkish does instead. The dotless `ı` is a letter of its own and kept as is.
+        let cluster = cluster.nfc().collect::<String>().replace('İ', "i");
+        self.text.extend(cluster.to_lowercase().nfc());
+        self.clusters
+            .push((folded_start..self.text.len(), original));
+    }
+
+    /// Widens a range of the folded text to the whole original clusters it touches.
+    fn original_range(&self, folded: Range<usize>) -> Range<usize> {
+        let first = self
+            .clusters
+            .partition_point(|(range, _)| range.end <= folded.start);
+        let last = self
+            .clusters
+            .partition_point(|(range, _)| range.start < folded.end);
+
+        match (self.clusters.get(first), self.clusters.get(last.max(1) - 1)) {
+            (Some((_, start)), Some((_, end))) => start.start..end.end,
+            _ => 0..0,
+        }
+    }
+}
+
+/// Byte ranges in `haystack` of every non-overlapping occurrence of `query`, matched
+/// case-insensitively regardless of how either side composes its accents. With
+/// `fold_accents`, a match covers the accented original text.
+pub fn find_matches(haystack: &str, query: &str, options: SearchOptions) -> Vec<Range<usize>> {
+    let query = Folded::new(query, options).text;
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let folded = Folded::new(haystack, options);
+    folded
+        .text
+        .match_indices(&query)
+        .map(|(start, matched)| folded.original_range(start..start + matched.len()))
+        .collect()
+}
+
+pub fn matches(haystack: &str, query: &str, options: SearchOptions) -> bool {
+    let query = Folded::new(query, options).text;
+    Folded::new(haystack, options).text.contains(&query)
+}
+
+#[cfg(test)]
+// `find_matches` returns a list of ranges; single-match cases are lists of one.
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+
+    const EXACT: SearchOptions = SearchOptions {
+        fold_accents: false,
+    };
+    const FOLDED: SearchOptions = SearchOptions { fold_accents: true };
+
+    #[test]
+    fn composed_query_finds_decomposed_text() {
+        let text = "un cafe\u{301} noir";
+        assert_eq!(find_matches(text, "café", EXACT), [3..9]);
+        assert_eq!(&text[3..9], "cafe\u{301}");
+    }
+
+    #[test]
+    fn decomposed_query_finds_composed_text() {
+        let text = "un café noir";
+        assert_eq!(find_matches(text, "cafe\u{301}", EXACT), [3..8]);
+        assert!(matches(text, "CAFE\u{301}", EXACT));
+    }
+
+    #[test]
+    fn accents_only_match_when_folded() {
+        let text = "Re\u{301}sume\u{301} and résumé";
+        assert!(find_matches(text, "resume", EXACT).is_empty());
+        assert_eq!(find_matches(text, "resume", FOLDED), [0..10, 15..23]);
+    }
+
+    #[test]
+    fn dotted_capital_i_matches_plain_i() {
+        for text in ["İstanbul", "I\u{307}stanbul"] {
+            assert_eq!(find_matches(text, "istanbul", EXACT), [0..text.len()]);
+        }
+        assert!(matches("istanbul", "İSTANBUL", EXACT));
+    }
+
+    #[test]
+    fn dotless_i_is_a_letter_of_its_own() {
+        assert!(matches("ılık su", "ılık", EXACT));
+        assert!(!matches("ılık su", "ilik", EXACT));
+        assert!(!matches("ilik", "ılık", FOLDED));
+    }
+
+    #[test]
+    fn normalize_composes() {
+        assert_eq!(normalize("cafe\u{301}"), "café");
+    }
+}
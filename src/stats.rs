@@ -0,0 +1,62 @@
+use chrono::{Datelike, Months, NaiveDate};
+
+/// Aggregates shown on the statistics screen, as returned by `Database::get_stats`.
+#[derive(Default)]
+pub struct Stats {
+    pub note_count: u32,
+    /// In characters; `None` without notes.
+    pub average_length: Option<f64>,
+    /// `(YYYY-MM, notes created that month)`, oldest first. Months without notes are missing.
+    pub created_per_month: Vec<(String, u32)>,
+    /// `(tag, notes)`, most used first.
+    pub top_tags: Vec<(String, u32)>,
+    /// `(title, characters)`, longest first.
+    pub largest_notes: Vec<(String, u32)>,
+    /// Days a note was created or last updated, in UTC, newest first. Earlier edits of a note
+    /// aren't recorded, so this undercounts notes edited several times.
+    pub edit_days: Vec<NaiveDate>,
+}
+
+impl Stats {
+    /// `(month label, notes existing at the end of that month)` for the `months` months up to
+    /// and including the one of `today`.
+    pub fn totals_by_month(&self, today: NaiveDate, months: u32) -> Vec<(String, u32)> {
+        let first = today.with_day(1).unwrap_or(today);
+        let mut total = 0;
+        let mut created = self.created_per_month.iter().peekable();
+
+        (0..months)
+            .rev()
+            .filter_map(|back| first.checked_sub_months(Months::new(back)))
+            .map(|month| {
+                let key = month.format("%Y-%m").to_string();
+                while let Some((_, count)) = created.next_if(|(created, _)| *created <= key) {
+                    total += count;
+                }
+                (month.format("%b").to_string(), total)
+            })
+            .collect()
+    }
+
+    /// Consecutive days with edits, ending today or, before the first edit of the day,
+    /// yesterday.
+    pub fn streak(&self, today: NaiveDate) -> usize {
+        let mut expected = match self.edit_days.first() {
+            Some(&day) if day == today || Some(day) == today.pred_opt() => day,
+            _ => return 0,
+        };
+
+        let mut streak = 0;
+        for &day in &self.edit_days {
+            if day != expected {
+                break;
+            }
+            streak += 1;
+            match day.pred_opt() {
+                Some(previous) => expected = previous,
+                None => break,
+            }
+        }
+        streak
+    }
+}
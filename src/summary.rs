@@ -0,0 +1,72 @@
+use chrono::{Days, NaiveDate, Utc};
+
+use crate::{
+    dates::DateDisplay,
+    db::{Database, DbError},
+};
+
+/// Days reported by `ratata-notes summary` without `--since`, today included.
+const DEFAULT_DAYS: u64 = 7;
+
+struct Day {
+    date: NaiveDate,
+    created: usize,
+    modified: usize,
+    words: usize,
+}
+
+/// Prints one row per day from `since` to today. Only the latest edit of a note is known, so a
+/// note counts as modified on the day it was last changed, and "words" sums the notes created
+/// or modified that day.
+pub fn print(db: &Database, dates: &DateDisplay, since: Option<NaiveDate>) -> Result<(), DbError> {
+    let today = dates.local_date(Utc::now());
+    let since = since.unwrap_or_else(|| today - Days::new(DEFAULT_DAYS - 1));
+
+    let from = dates.start_of_day(since);
+    let to = dates.start_of_day(today + Days::new(1));
+    let created = db.get_notes_created_in_range(from, to)?;
+    let modified = db.get_notes_modified_in_range(from, to)?;
+
+    let mut days = since
+        .iter_days()
+        .take_while(|date| *date <= today)
+        .map(|date| Day {
+            date,
+            created: 0,
+            modified: 0,
+            words: 0,
+        })
+        .collect::<Vec<_>>();
+    let index_of = |at| usize::try_from((dates.local_date(at) - since).num_days()).ok();
+
+    for note in &created {
+        if let Some(day) = index_of(note.created_at).and_then(|index| days.get_mut(index)) {
+            day.created += 1;
+            day.words += note.content.split_whitespace().count();
+        }
+    }
+    for note in &modified {
+        if let Some(day) = index_of(note.updated_at).and_then(|index| days.get_mut(index)) {
+            day.modified += 1;
+            // Already counted on the day it was created.
+            if dates.local_date(note.created_at) != day.date {
+                day.words += note.content.split_whitespace().count();
+            }
+        }
+    }
+
+    println!(
+        "{:<10}  {:>7}  {:>8}  {:>6}",
+        "Date", "Created", "Modified", "Words"
+    );
+    for day in &days {
+        println!(
+            "{:<10}  {:>7}  {:>8}  {:>6}",
+            day.date.format("%Y-%m-%d"),
+            day.created,
+            day.modified,
+            day.words
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use tui_input::Input;
+
+/// Tags to add and remove, parsed from input such as `+projectx -inbox`. A tag without a sign
+/// is added.
+#[derive(Default)]
+pub struct TagEdits {
+    pub add: Vec<String>,
+    pub remove: Vec<String>,
+}
+
+impl TagEdits {
+    pub fn parse(input: &str) -> TagEdits {
+        let mut edits = TagEdits::default();
+        for token in input.split_whitespace() {
+            let (tags, tag) = match token.strip_prefix('-') {
+                Some(tag) => (&mut edits.remove, tag),
+                None => (&mut edits.add, token.strip_prefix('+').unwrap_or(token)),
+            };
+            if !tag.is_empty() {
+                tags.push(tag.to_string());
+            }
+        }
+        edits
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.add.is_empty() && self.remove.is_empty()
+    }
+}
+
+/// The tag input opened with `t`, completing the tag being typed from the existing ones.
+#[derive(Default)]
+pub struct TagEditor {
+    pub input: Input,
+    known_tags: Vec<String>,
+}
+
+impl TagEditor {
+    pub fn open(&mut self, known_tags: Vec<String>) {
+        self.input.reset();
+        self.known_tags = known_tags;
+    }
+
+    /// The tag being typed, without its sign.
+    fn partial_tag(&self) -> &str {
+        let value = self.input.value();
+        let last = value.rsplit(char::is_whitespace).next().unwrap_or(value);
+        last.trim_start_matches(['+', '-'])
+    }
+
+    /// Existing tags starting with the tag being typed, ignoring case.
+    pub fn completions(&self) -> Vec<&str> {
+        let partial = self.partial_tag().to_lowercase();
+        if partial.is_empty() {
+            return Vec::new();
+        }
+        self.known_tags
+            .iter()
+            .filter(|tag| tag.to_lowercase().starts_with(&partial))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Replaces the tag being typed with its first completion, ready for the next one.
+    pub fn complete(&mut self) {
+        let Some(&completion) = self.completions().first() else {
+            return;
+        };
+        let value = self.input.value();
+        let kept = value.len() - self.partial_tag().len();
+        let completed = format!("{}{completion} ", &value[..kept]);
+        let cursor = completed.chars().count();
+        self.input = Input::new(completed).with_cursor(cursor);
+    }
+}
@@ -0,0 +1,41 @@
+//! Helpers shared by the unit tests.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{collation::TitleCollator, db::Database};
+
+/// A directory of its own under the system temp dir, removed with its contents when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> TempDir {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "ratata-notes-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A new `notes.db` in `dir`, sorting titles with the root collation.
+pub fn database(dir: &TempDir) -> Database {
+    let path = dir.path().join("notes.db");
+    Database::new(path.to_str().unwrap(), TitleCollator::new("und").unwrap()).unwrap()
+}
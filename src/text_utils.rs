@@ -0,0 +1,161 @@
+use tui_input::Input;
+use unicode_width::UnicodeWidthChar;
+
+/// Surrounds the selection with `before` and `after`. The editor has no selection yet, so the
+/// markers are inserted at the cursor, which is left between them ready for typing.
+pub fn wrap_selection(input: &mut Input, before: &str, after: &str) {
+    insert_at_cursor(input, &format!("{before}{after}"), before.chars().count());
+}
+
+/// Inserts `text` in one edit, leaving the cursor `offset` characters into it.
+pub fn insert_at_cursor(input: &mut Input, text: &str, offset: usize) {
+    let split = cursor_byte(input);
+    let value = input.value();
+    let inserted = format!("{}{text}{}", &value[..split], &value[split..]);
+    *input = Input::new(inserted).with_cursor(input.cursor() + offset);
+}
+
+/// Byte offset of the cursor in the input's value.
+fn cursor_byte(input: &Input) -> usize {
+    let value = input.value();
+    value
+        .char_indices()
+        .nth(input.cursor())
+        .map_or(value.len(), |(index, _)| index)
+}
+
+/// What was typed after the last `[[` before the cursor, if that link isn't closed yet.
+pub fn unclosed_wikilink(input: &Input) -> Option<&str> {
+    let before = &input.value()[..cursor_byte(input)];
+    let typed = &before[before.rfind("[[")? + 2..];
+    (!typed.contains("]]")).then_some(typed)
+}
+
+/// Replaces the `typed` characters before the cursor with `text`, leaving the cursor after it.
+pub fn complete_before_cursor(input: &mut Input, typed: usize, text: &str) {
+    let cursor = input.cursor().saturating_sub(typed);
+    let value = input.value();
+    let start = value
+        .char_indices()
+        .nth(cursor)
+        .map_or(value.len(), |(index, _)| index);
+
+    let completed = format!("{}{text}{}", &value[..start], &value[cursor_byte(input)..]);
+    *input = Input::new(completed).with_cursor(cursor + text.chars().count());
+}
+
+/// The Markdown formatting the cursor is in, on its own line. Markers are counted from the start
+/// of the line, so `**` opened on an earlier line doesn't count.
+#[derive(Default)]
+pub struct Formatting {
+    pub bold: bool,
+    pub italic: bool,
+    /// Markdown has no underline; notes use `<u>…</u>`.
+    pub underline: bool,
+    pub wikilink: bool,
+    pub header: bool,
+    pub horizontal_rule: bool,
+}
+
+pub fn formatting_at_cursor(input: &Input) -> Formatting {
+    let value = input.value();
+    let cursor = cursor_byte(input);
+    let line_start = value[..cursor].rfind('\n').map_or(0, |index| index + 1);
+    let line_end = value[cursor..]
+        .find('\n')
+        .map_or(value.len(), |index| cursor + index);
+    let (before, after) = (&value[line_start..cursor], &value[cursor..line_end]);
+    // Between an opening and a closing marker when an odd number of them come before.
+    let between = |before: &str, after: &str, marker: &str| {
+        before.matches(marker).count() % 2 == 1 && after.contains(marker)
+    };
+    let enclosed = |open: &str, close: &str| {
+        before
+            .rfind(open)
+            .is_some_and(|start| !before[start..].contains(close))
+            && after.contains(close)
+    };
+    let (single_before, single_after) = (before.replace("**", ""), after.replace("**", ""));
+    let line = value[line_start..line_end].trim();
+
+    Formatting {
+        bold: between(before, after, "**"),
+        italic: between(&single_before, &single_after, "*")
+            || between(&single_before, &single_after, "_"),
+        underline: enclosed("<u>", "</u>"),
+        wikilink: enclosed("[[", "]]"),
+        header: line.starts_with('#'),
+        horizontal_rule: line.len() >= 3
+            && ['-', '*', '_']
+                .iter()
+                .any(|&marker| line.chars().all(|c| c == marker)),
+    }
+}
+
+/// Zero-based line the cursor is on.
+pub fn cursor_line(input: &Input) -> usize {
+    input.value()[..cursor_byte(input)].matches('\n').count()
+}
+
+/// Moves the cursor to the start of `line`, or of the last line when there are fewer. Returns
+/// the line it landed on.
+pub fn move_to_line(input: &mut Input, line: usize) -> usize {
+    let value = input.value();
+    let line = line.min(value.matches('\n').count());
+    let cursor = value
+        .split('\n')
+        .take(line)
+        .map(|text| text.chars().count() + 1)
+        .sum();
+    *input = input.clone().with_cursor(cursor);
+    line
+}
+
+/// Byte offset of `part`, a slice of `text` such as a row from `soft_wrap`, within `text`.
+pub fn offset_in(text: &str, part: &str) -> usize {
+    part.as_ptr() as usize - text.as_ptr() as usize
+}
+
+/// Splits `text` into the rows it takes in a pane `width` columns wide, each with the index of
+/// the line it comes from. A line breaks after the last space that fits, or mid-word when a word
+/// is wider than the pane; an empty line is still one row.
+pub fn soft_wrap(text: &str, width: usize) -> Vec<(usize, &str)> {
+    let width = width.max(1);
+    let mut rows = Vec::new();
+    for (index, line) in text.split('\n').enumerate() {
+        let mut rest = line;
+        loop {
+            let mut used = 0;
+            let mut after_space = None;
+            let overflow = rest.char_indices().find(|&(at, c)| {
+                used += c.width().unwrap_or(0);
+                if c == ' ' {
+                    after_space = Some(at + 1);
+                }
+                used > width
+            });
+            let Some((at, c)) = overflow else {
+                rows.push((index, rest));
+                break;
+            };
+            if c == ' ' {
+                // The space the row would end on isn't worth a row of its own.
+                rows.push((index, &rest[..at]));
+                rest = &rest[at + 1..];
+                if rest.is_empty() {
+                    break;
+                }
+                continue;
+            }
+            let split = match after_space {
+                Some(split) if split < at => split,
+                _ if at > 0 => at,
+                // A character wider than the whole pane still takes a row.
+                _ => c.len_utf8(),
+            };
+            rows.push((index, &rest[..split]));
+            rest = &rest[split..];
+        }
+    }
+    rows
+}
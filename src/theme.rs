@@ -0,0 +1,179 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use color_eyre::eyre::eyre;
+use ratatui::{
+    style::{Color, Style, Stylize},
+    text::Span,
+};
+use serde::Deserialize;
+
+/// `[theme]` in the config file: a preset, optionally with individual colors overridden.
+///
+/// Colors accept anything ratatui understands: names (`"yellow"`), indexes (`"208"`) or hex
+/// (`"#e69f00"`).
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub preset: ThemePreset,
+    pub accent: Option<Color>,
+    pub danger: Option<Color>,
+    pub success: Option<Color>,
+    pub error: Option<Color>,
+    pub text: Option<Color>,
+    pub selection_fg: Option<Color>,
+    pub selection_bg: Option<Color>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemePreset {
+    #[default]
+    Default,
+    /// Red-green safe, tuned for reduced green sensitivity.
+    Deuteranopia,
+    /// Red-green safe, avoiding reds that read as dark for reduced red sensitivity.
+    Protanopia,
+    /// Blue-yellow safe.
+    Tritanopia,
+}
+
+/// Colors used to draw the UI. Hue is never the only signal: destructive key hints and error
+/// messages also carry a `!` prefix.
+#[derive(Clone, Copy)]
+pub struct Theme {
+    /// Key names in help lines, focused input borders and the pending-keys indicator.
+    pub accent: Color,
+    /// Key names of destructive actions, such as delete and discard.
+    pub danger: Color,
+    /// Confirmation messages in the status line.
+    pub success: Color,
+    /// Error messages in the status line.
+    pub error: Color,
+    /// Unselected rows of the notes list.
+    pub text: Color,
+    /// Text of the selected row in lists.
+    pub selection_fg: Color,
+    /// Background of the selected row in lists.
+    pub selection_bg: Color,
+}
+
+/// Marks destructive hints and errors so they don't rely on color alone.
+const SEVERITY_PREFIX: &str = "!";
+
+impl Theme {
+    pub fn new(config: &ThemeConfig) -> Theme {
+        let preset = Theme::preset(config.preset);
+        Theme {
+            accent: config.accent.unwrap_or(preset.accent),
+            danger: config.danger.unwrap_or(preset.danger),
+            success: config.success.unwrap_or(preset.success),
+            error: config.error.unwrap_or(preset.error),
+            text: config.text.unwrap_or(preset.text),
+            selection_fg: config.selection_fg.unwrap_or(preset.selection_fg),
+            selection_bg: config.selection_bg.unwrap_or(preset.selection_bg),
+        }
+    }
+
+    /// Reads a `theme.toml`, which holds the keys of `[theme]` at its top level.
+    pub fn from_file(path: &Path) -> color_eyre::Result<Theme> {
+        let raw = fs::read_to_string(path)?;
+        let config =
+            toml::from_str(&raw).map_err(|e| eyre!("invalid theme {}: {e}", path.display()))?;
+        Ok(Theme::new(&config))
+    }
+
+    /// The presets other than `Default` use the Okabe-Ito palette, picking pairs that stay
+    /// apart for each kind of color vision deficiency.
+    pub fn preset(preset: ThemePreset) -> Theme {
+        let base = Theme {
+            accent: Color::Yellow,
+            danger: Color::Red,
+            success: Color::Green,
+            error: Color::Red,
+            text: Color::White,
+            selection_fg: Color::Black,
+            selection_bg: Color::White,
+        };
+
+        match preset {
+            ThemePreset::Default => base,
+            ThemePreset::Deuteranopia => Theme {
+                accent: Color::from_u32(0x56b4e9),
+                danger: Color::from_u32(0xd55e00),
+                success: Color::from_u32(0x0072b2),
+                error: Color::from_u32(0xd55e00),
+                ..base
+            },
+            ThemePreset::Protanopia => Theme {
+                accent: Color::from_u32(0x56b4e9),
+                danger: Color::from_u32(0xe69f00),
+                success: Color::from_u32(0x0072b2),
+                error: Color::from_u32(0xe69f00),
+                ..base
+            },
+            ThemePreset::Tritanopia => Theme {
+                accent: Color::from_u32(0xcc79a7),
+                danger: Color::from_u32(0xd55e00),
+                success: Color::from_u32(0x009e73),
+                error: Color::from_u32(0xd55e00),
+                ..base
+            },
+        }
+    }
+
+    pub fn key<'a>(&self, key: impl Into<Span<'a>>) -> Span<'a> {
+        key.into().bold().fg(self.accent)
+    }
+
+    pub fn destructive_key(&self, key: &str) -> Span<'static> {
+        format!("{SEVERITY_PREFIX}{key}").bold().fg(self.danger)
+    }
+
+    pub fn error_message(&self, message: &str) -> Span<'static> {
+        format!("{SEVERITY_PREFIX} {message}").fg(self.error)
+    }
+
+    pub fn focused_border(&self) -> Style {
+        Style::new().fg(self.accent)
+    }
+
+    pub fn selection(&self) -> Style {
+        Style::new().fg(self.selection_fg).bg(self.selection_bg)
+    }
+}
+
+/// `theme.toml` next to the config file, checked for edits while the app runs.
+pub struct ThemeWatcher {
+    path: PathBuf,
+    /// `None` while the file doesn't exist.
+    modified: Option<SystemTime>,
+}
+
+impl ThemeWatcher {
+    pub fn new(path: PathBuf) -> ThemeWatcher {
+        let modified = modified(&path);
+        ThemeWatcher { path, modified }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the file was written, created or removed since the last call.
+    pub fn changed(&mut self) -> bool {
+        let modified = modified(&self.path);
+        let changed = modified != self.modified;
+        self.modified = modified;
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
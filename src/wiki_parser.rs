@@ -0,0 +1,36 @@
+/// A `[[title]]` link in a note's content.
+pub struct Link<'a> {
+    /// The title between the brackets, without surrounding spaces.
+    pub target: &'a str,
+    /// Byte offset of the opening `[[`.
+    pub start: usize,
+}
+
+/// Every `[[title]]` in `content`, in order. A link ends at the first `]]` and can't span lines;
+/// `[[` without a matching `]]` and empty links like `[[ ]]` are ignored.
+pub fn links(content: &str) -> Vec<Link<'_>> {
+    let mut links = Vec::new();
+    let mut from = 0;
+    while let Some(open) = content[from..].find("[[").map(|offset| from + offset) {
+        let inner_start = open + 2;
+        let rest = &content[inner_start..];
+        let Some(close) = rest.find("]]") else {
+            break;
+        };
+        let inner = &rest[..close];
+        // `[[a [[b]]` links to "b": the closer link wins, like the `[[` title picker.
+        if let Some(reopen) = inner.rfind("[[") {
+            from = inner_start + reopen;
+            continue;
+        }
+        from = inner_start + close + 2;
+        let target = inner.trim();
+        if !inner.contains('\n') && !target.is_empty() {
+            links.push(Link {
+                target,
+                start: open,
+            });
+        }
+    }
+    links
+}
@@ -0,0 +1,11 @@
+-- A database as the first release left it: no user_version, no timestamps, no other tables.
+CREATE TABLE notes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    content TEXT NOT NULL
+);
+INSERT INTO notes (title, content) VALUES
+    ('Groceries', 'eggs'),
+    ('Ideas', 'See [[Groceries]]'),
+    ('Deleted', '');
+DELETE FROM notes WHERE title = 'Deleted';
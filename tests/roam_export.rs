@@ -0,0 +1,73 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// A directory of its own for the database the binary creates in its working directory.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        let path = std::env::temp_dir().join(format!("ratata-notes-roam-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn notes(dir: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_ratata-notes"));
+    // Keeps the config, theme and templates of whoever runs the tests out of it.
+    command
+        .current_dir(dir)
+        .env("XDG_CONFIG_HOME", dir)
+        .env("HOME", dir);
+    command
+}
+
+fn add(dir: &Path, title: &str, content: &str) {
+    let mut child = notes(dir)
+        .args(["add", title])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(content.as_bytes())
+        .unwrap();
+    assert!(child.wait().unwrap().success(), "adding {title:?} failed");
+}
+
+#[test]
+fn roam_export_matches_the_fixture() {
+    let dir = TempDir::new();
+    add(&dir.0, "Groceries", "- milk\n\n- eggs, see [[Recipes]]\n");
+    add(
+        &dir.0,
+        "Recipes",
+        "Pancakes\n  flour, milk and [[Groceries]]\n",
+    );
+    add(&dir.0, "Empty", "");
+
+    let status = notes(&dir.0)
+        .args(["--export-roam", "roam.json"])
+        .stdout(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let exported: serde_json::Value =
+        serde_json::from_slice(&fs::read(dir.0.join("roam.json")).unwrap()).unwrap();
+    let expected: serde_json::Value =
+        serde_json::from_str(include_str!("fixtures/roam_export.json")).unwrap();
+    assert_eq!(exported, expected);
+}